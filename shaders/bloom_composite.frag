@@ -0,0 +1,32 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D sourceTexture;
+layout(set = 1, binding = 1) uniform sampler sourceSampler;
+
+// the top of the bloom pyramid, holding every level summed
+layout(set = 2, binding = 0) uniform texture2D bloomTexture;
+layout(set = 2, binding = 1) uniform sampler bloomSampler;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec3 color = texture(sampler2D(sourceTexture, sourceSampler), fragUv).rgb;
+    vec3 bloom = texture(sampler2D(bloomTexture, bloomSampler), fragUv).rgb;
+    outColor = vec4(color + bloom * post.bloomIntensity, 1.0);
+}
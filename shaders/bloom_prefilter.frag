@@ -0,0 +1,42 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D sourceTexture;
+layout(set = 1, binding = 1) uniform sampler sourceSampler;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+// four bilinear taps a texel out cover a 4x4 block of the source, which
+// blurs a little while halving the resolution
+vec3 downsample() {
+    vec2 texel = 1.0 / vec2(textureSize(sampler2D(sourceTexture, sourceSampler), 0));
+    vec3 sum = vec3(0.0, 0.0, 0.0);
+    sum += texture(sampler2D(sourceTexture, sourceSampler), fragUv + vec2(-texel.x, -texel.y)).rgb;
+    sum += texture(sampler2D(sourceTexture, sourceSampler), fragUv + vec2(texel.x, -texel.y)).rgb;
+    sum += texture(sampler2D(sourceTexture, sourceSampler), fragUv + vec2(-texel.x, texel.y)).rgb;
+    sum += texture(sampler2D(sourceTexture, sourceSampler), fragUv + vec2(texel.x, texel.y)).rgb;
+    return sum * 0.25;
+}
+
+void main() {
+    vec3 color = downsample();
+    // keep only the light above the threshold, fading in so the edge isn't hard
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    float bright = max(luminance - post.bloomThreshold, 0.0) / max(luminance, 0.0001);
+    outColor = vec4(color * bright, 1.0);
+}
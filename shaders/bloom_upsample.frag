@@ -0,0 +1,36 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D sourceTexture;
+layout(set = 1, binding = 1) uniform sampler sourceSampler;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+// a 3x3 tent filter over the smaller level, blended onto the larger one
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(sampler2D(sourceTexture, sourceSampler), 0));
+    vec3 sum = vec3(0.0, 0.0, 0.0);
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float weight = float((2 - abs(x)) * (2 - abs(y)));
+            vec2 offset = vec2(float(x), float(y)) * texel;
+            sum += weight * texture(sampler2D(sourceTexture, sourceSampler), fragUv + offset).rgb;
+        }
+    }
+    outColor = vec4(sum / 16.0, 0.0);
+}
@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 0) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+// premultiplied, so lines can be translucent
+void main() {
+    outColor = vec4(fragColor.rgb * fragColor.a, fragColor.a);
+}
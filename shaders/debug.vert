@@ -0,0 +1,18 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform CameraUniforms {
+    mat4 view;
+    mat4 proj;
+    vec4 position;
+    mat4 inverseViewProj;
+} camera;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec4 inColor;
+
+layout(location = 0) out vec4 fragColor;
+
+void main() {
+    gl_Position = camera.proj * camera.view * vec4(inPosition, 1.0);
+    fragColor = inColor;
+}
@@ -0,0 +1,188 @@
+#version 450
+
+const uint MAX_LIGHTS = 16;
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+const uint MAX_SHADOW_MAPS = 8;
+const uint SHADOW_CASCADES = 4;
+
+const float PI = 3.14159265359;
+
+layout(set = 0, binding = 0) uniform CameraUniforms {
+    mat4 view;
+    mat4 proj;
+    vec4 position;
+    mat4 inverseViewProj;
+} camera;
+
+layout(input_attachment_index = 0, set = 1, binding = 0) uniform subpassInput albedoInput;
+layout(input_attachment_index = 1, set = 1, binding = 1) uniform subpassInput normalInput;
+layout(input_attachment_index = 2, set = 1, binding = 2) uniform subpassInput materialInput;
+layout(input_attachment_index = 3, set = 1, binding = 3) uniform subpassInput depthInput;
+
+struct Light {
+    // xyz position, w type
+    vec4 positionType;
+    // xyz direction, w range (0 for unlimited)
+    vec4 directionRange;
+    // rgb colour, a intensity
+    vec4 colorIntensity;
+    // cos of the inner and outer spot cone angles
+    vec4 cone;
+    // first shadow map layer (-1 for none), layer count
+    vec4 shadow;
+};
+
+layout(set = 2, binding = 0) uniform LightUniforms {
+    uint count;
+    Light lights[MAX_LIGHTS];
+    mat4 shadowMatrices[MAX_SHADOW_MAPS];
+    // view space distance at which each cascade ends
+    vec4 cascadeSplits;
+} lightUniforms;
+layout(set = 2, binding = 1) uniform texture2DArray shadowMaps;
+layout(set = 2, binding = 2) uniform samplerShadow shadowSampler;
+
+layout(location = 0) in vec2 fragNdc;
+
+// added onto the ambient and emissive light the geometry subpass wrote
+layout(location = 0) out vec4 outColor;
+
+float distributionGgx(float nDotH, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+float geometrySchlickGgx(float nDotX, float roughness) {
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+    return nDotX / (nDotX * (1.0 - k) + k);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// smooth window from KHR_lights_punctual, reaching zero at the range
+float rangeAttenuation(float distance, float range) {
+    if (range <= 0.0) {
+        return 1.0;
+    }
+    float ratio = distance / range;
+    return clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+}
+
+// fraction of the light reaching the fragment, from a 3x3 PCF kernel
+float shadowFactor(Light light, vec3 position) {
+    if (light.shadow.x < 0.0) {
+        return 1.0;
+    }
+
+    uint layer = uint(light.shadow.x);
+    if (light.shadow.y > 1.0) {
+        // directional lights pick the cascade covering the fragment's depth
+        float depth = -(camera.view * vec4(position, 1.0)).z;
+        uint cascade = 0;
+        while (cascade < SHADOW_CASCADES && depth > lightUniforms.cascadeSplits[cascade]) {
+            cascade++;
+        }
+        if (cascade == SHADOW_CASCADES) {
+            return 1.0;
+        }
+        layer += cascade;
+    }
+
+    vec4 lightSpace = lightUniforms.shadowMatrices[layer] * vec4(position, 1.0);
+    vec3 coords = lightSpace.xyz / lightSpace.w;
+    if (coords.z <= 0.0 || coords.z >= 1.0) {
+        return 1.0;
+    }
+    vec2 uv = coords.xy * 0.5 + 0.5;
+
+    vec2 texelSize = 1.0 / vec2(textureSize(sampler2DArrayShadow(shadowMaps, shadowSampler), 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(float(x), float(y)) * texelSize;
+            lit += texture(
+                sampler2DArrayShadow(shadowMaps, shadowSampler),
+                vec4(uv + offset, float(layer), coords.z)
+            );
+        }
+    }
+    return lit / 9.0;
+}
+
+void main() {
+    float depth = subpassLoad(depthInput).r;
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    vec4 worldPosition = camera.inverseViewProj * vec4(fragNdc, depth, 1.0);
+    vec3 position = worldPosition.xyz / worldPosition.w;
+
+    vec3 baseColor = subpassLoad(albedoInput).rgb;
+    vec3 n = normalize(subpassLoad(normalInput).xyz);
+    vec4 material = subpassLoad(materialInput);
+    float metallic = material.r;
+    float roughness = material.g;
+
+    vec3 v = normalize(camera.position.xyz - position);
+    float nDotV = max(dot(n, v), 0.0001);
+
+    vec3 f0 = mix(vec3(0.04, 0.04, 0.04), baseColor, metallic);
+    vec3 diffuseColor = baseColor * (1.0 - metallic);
+
+    vec3 radiance = vec3(0.0, 0.0, 0.0);
+    for (uint i = 0; i < min(lightUniforms.count, MAX_LIGHTS); i++) {
+        Light light = lightUniforms.lights[i];
+        uint lightType = uint(light.positionType.w);
+
+        vec3 l;
+        float attenuation = 1.0;
+        if (lightType == LIGHT_DIRECTIONAL) {
+            l = -light.directionRange.xyz;
+        } else {
+            vec3 toLight = light.positionType.xyz - position;
+            float distance = length(toLight);
+            l = toLight / distance;
+            attenuation = rangeAttenuation(distance, light.directionRange.w)
+                / max(distance * distance, 0.0001);
+
+            if (lightType == LIGHT_SPOT) {
+                float cosAngle = dot(-l, light.directionRange.xyz);
+                float spot = clamp(
+                    (cosAngle - light.cone.y) / max(light.cone.x - light.cone.y, 0.0001),
+                    0.0,
+                    1.0
+                );
+                attenuation *= spot * spot;
+            }
+        }
+
+        float nDotL = dot(n, l);
+        if (nDotL <= 0.0 || attenuation <= 0.0) {
+            continue;
+        }
+
+        attenuation *= shadowFactor(light, position);
+
+        vec3 h = normalize(v + l);
+        float nDotH = max(dot(n, h), 0.0);
+        vec3 f = fresnelSchlick(max(dot(h, v), 0.0), f0);
+
+        float d = distributionGgx(nDotH, roughness);
+        float g = geometrySchlickGgx(nDotV, roughness) * geometrySchlickGgx(nDotL, roughness);
+        vec3 specular = d * g * f / (4.0 * nDotV * nDotL);
+        vec3 diffuse = (1.0 - f) * diffuseColor / PI;
+
+        vec3 lightColor = light.colorIntensity.rgb * light.colorIntensity.a;
+        radiance += (diffuse + specular) * lightColor * attenuation * nDotL;
+    }
+
+    outColor = vec4(radiance, 0.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) out vec2 fragNdc;
+
+// a single triangle covering the viewport, no vertex buffer needed, wound
+// counter-clockwise on screen so back face culling keeps it
+void main() {
+    vec2 uv = vec2(float(gl_VertexIndex & 2), float((gl_VertexIndex << 1) & 2));
+    fragNdc = uv * 2.0 - 1.0;
+    gl_Position = vec4(fragNdc, 0.0, 1.0);
+}
@@ -0,0 +1,27 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D sourceTexture;
+layout(set = 1, binding = 1) uniform sampler sourceSampler;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec3 color = texture(sampler2D(sourceTexture, sourceSampler), fragUv).rgb;
+    outColor = vec4(pow(max(color, vec3(0.0, 0.0, 0.0)), vec3(1.0 / post.gamma)), 1.0);
+}
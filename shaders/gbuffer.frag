@@ -0,0 +1,109 @@
+#version 450
+
+// stands in for the indirect light the renderer doesn't compute
+const vec3 ambientLight = vec3(0.03, 0.03, 0.03);
+
+struct ObjectUniforms {
+    mat4 model;
+    mat4 normalMatrix;
+    vec4 baseColor;
+    vec4 emissive;
+    // metallic, roughness, normal scale, occlusion strength
+    vec4 material;
+    // texture indices, only read with bindless textures
+    uvec4 textures;
+    uint emissiveTexture;
+};
+
+// every draw's object uniforms, indexed by the object the vertex shader
+// passes on
+layout(std430, set = 1, binding = 0) readonly buffer Objects {
+    ObjectUniforms objects[];
+};
+
+// this fragment's, looked up first thing in main
+ObjectUniforms object;
+
+// built again with -DBINDLESS into gbuffer_bindless_frag.spv for descriptor indexing, see the README
+#ifdef BINDLESS
+const uint MAX_TEXTURES = 1024;
+
+// every loaded texture, which the object uniforms pick from by index
+layout(set = 2, binding = 0) uniform texture2D textures[MAX_TEXTURES];
+layout(set = 2, binding = 1) uniform sampler samplers[MAX_TEXTURES];
+
+vec4 materialTexture(uint index, vec2 uv) {
+    return texture(sampler2D(textures[index], samplers[index]), uv);
+}
+
+#define SAMPLE_BASE_COLOR(uv) materialTexture(object.textures.x, uv)
+#define SAMPLE_METALLIC_ROUGHNESS(uv) materialTexture(object.textures.y, uv)
+#define SAMPLE_NORMAL(uv) materialTexture(object.textures.z, uv)
+#define SAMPLE_OCCLUSION(uv) materialTexture(object.textures.w, uv)
+#define SAMPLE_EMISSIVE(uv) materialTexture(object.emissiveTexture, uv)
+#else
+layout(set = 2, binding = 0) uniform texture2D baseColorTexture;
+layout(set = 2, binding = 1) uniform sampler baseColorSampler;
+layout(set = 2, binding = 2) uniform texture2D metallicRoughnessTexture;
+layout(set = 2, binding = 3) uniform sampler metallicRoughnessSampler;
+layout(set = 2, binding = 4) uniform texture2D normalTexture;
+layout(set = 2, binding = 5) uniform sampler normalSampler;
+layout(set = 2, binding = 6) uniform texture2D occlusionTexture;
+layout(set = 2, binding = 7) uniform sampler occlusionSampler;
+layout(set = 2, binding = 8) uniform texture2D emissiveTexture;
+layout(set = 2, binding = 9) uniform sampler emissiveSampler;
+
+#define SAMPLE_BASE_COLOR(uv) texture(sampler2D(baseColorTexture, baseColorSampler), uv)
+#define SAMPLE_METALLIC_ROUGHNESS(uv) texture(sampler2D(metallicRoughnessTexture, metallicRoughnessSampler), uv)
+#define SAMPLE_NORMAL(uv) texture(sampler2D(normalTexture, normalSampler), uv)
+#define SAMPLE_OCCLUSION(uv) texture(sampler2D(occlusionTexture, occlusionSampler), uv)
+#define SAMPLE_EMISSIVE(uv) texture(sampler2D(emissiveTexture, emissiveSampler), uv)
+#endif
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec2 fragUv;
+layout(location = 4) in vec3 fragColor;
+layout(location = 5) flat in uint fragObject;
+
+// the lit colour starts out as ambient and emissive light, which the
+// lighting subpass adds each light to
+layout(location = 0) out vec4 outColor;
+// rgb albedo, a ambient occlusion
+layout(location = 1) out vec4 outAlbedo;
+// xyz world space normal
+layout(location = 2) out vec4 outNormal;
+// metallic, roughness
+layout(location = 3) out vec4 outMaterial;
+
+vec3 surfaceNormal() {
+    vec3 n = normalize(fragNormal);
+    vec3 t = normalize(fragTangent.xyz - n * dot(n, fragTangent.xyz));
+    vec3 b = cross(n, t) * fragTangent.w;
+
+    vec3 tangentNormal = SAMPLE_NORMAL(fragUv).xyz * 2.0 - 1.0;
+    tangentNormal.xy *= object.material.z;
+    return normalize(mat3(t, b, n) * tangentNormal);
+}
+
+void main() {
+    object = objects[fragObject];
+
+    vec4 baseColor = object.baseColor * vec4(fragColor, 1.0)
+        * SAMPLE_BASE_COLOR(fragUv);
+    vec4 metallicRoughness = SAMPLE_METALLIC_ROUGHNESS(fragUv);
+    float metallic = object.material.x * metallicRoughness.b;
+    // very smooth surfaces turn lights into single bright pixels
+    float roughness = clamp(object.material.y * metallicRoughness.g, 0.04, 1.0);
+    float occlusion = 1.0 + object.material.w
+        * (SAMPLE_OCCLUSION(fragUv).r - 1.0);
+    vec3 emissive = object.emissive.rgb
+        * SAMPLE_EMISSIVE(fragUv).rgb;
+
+    vec3 ambient = ambientLight * baseColor.rgb * occlusion;
+    outColor = vec4(ambient + emissive, baseColor.a);
+    outAlbedo = vec4(baseColor.rgb, occlusion);
+    outNormal = vec4(surfaceNormal(), 0.0);
+    outMaterial = vec4(metallic, roughness, 0.0, 0.0);
+}
@@ -0,0 +1,69 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D guiTexture;
+layout(set = 1, binding = 1) uniform sampler guiSampler;
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+const uint OUTPUT_HDR10 = 1;
+const uint OUTPUT_SCRGB = 2;
+
+// linear Rec.709 to linear Rec.2020, columns first
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// SMPTE ST 2084 inverse EOTF, from absolute nits to a 0..1 signal
+vec3 pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0)), vec3(m1, m1, m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2, m2, m2));
+}
+
+vec3 srgbToLinear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4, 2.4, 2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045, 0.04045, 0.04045)));
+}
+
+// egui's colours and textures are sRGB with premultiplied alpha, made to be
+// blended as they are onto an SDR image. HDR output decodes them first and
+// encodes like the output pass, SDR white at paper white
+void main() {
+    vec4 color = fragColor * texture(sampler2D(guiTexture, guiSampler), fragUv);
+    if (post.outputSpace != OUTPUT_HDR10 && post.outputSpace != OUTPUT_SCRGB) {
+        outColor = color;
+        return;
+    }
+
+    vec3 linear = srgbToLinear(color.rgb / max(color.a, 0.0001));
+    if (post.outputSpace == OUTPUT_HDR10) {
+        linear = pq(REC709_TO_REC2020 * linear * post.paperWhite);
+    } else {
+        linear *= post.paperWhite / 80.0;
+    }
+    outColor = vec4(linear * color.a, color.a);
+}
@@ -0,0 +1,56 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D sourceTexture;
+layout(set = 1, binding = 1) uniform sampler sourceSampler;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+const uint OUTPUT_HDR10 = 1;
+const uint OUTPUT_SCRGB = 2;
+
+// linear Rec.709 to linear Rec.2020, columns first
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// SMPTE ST 2084 inverse EOTF, from absolute nits to a 0..1 signal
+vec3 pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0)), vec3(m1, m1, m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2, m2, m2));
+}
+
+// encodes the end of the chain for the swapchain's colour space. SDR is
+// copied as is, the UNORM format clamping it to 0..1
+void main() {
+    vec3 color = texture(sampler2D(sourceTexture, sourceSampler), fragUv).rgb;
+    if (post.outputSpace == OUTPUT_HDR10) {
+        color = pq(REC709_TO_REC2020 * max(color, vec3(0.0, 0.0, 0.0)) * post.paperWhite);
+    } else if (post.outputSpace == OUTPUT_SCRGB) {
+        // scRGB's 1 is 80 nits
+        color *= post.paperWhite / 80.0;
+    }
+    outColor = vec4(color, 1.0);
+}
@@ -1,9 +1,258 @@
 #version 450
 
-layout(location = 0) in vec3 fragColor;
+const uint MAX_LIGHTS = 16;
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+const uint MAX_SHADOW_MAPS = 8;
+const uint SHADOW_CASCADES = 4;
+
+const float PI = 3.14159265359;
+
+// stands in for the indirect light the renderer doesn't compute
+const vec3 ambientLight = vec3(0.03, 0.03, 0.03);
+
+layout(set = 0, binding = 0) uniform CameraUniforms {
+    mat4 view;
+    mat4 proj;
+    vec4 position;
+    mat4 inverseViewProj;
+} camera;
+
+struct ObjectUniforms {
+    mat4 model;
+    mat4 normalMatrix;
+    vec4 baseColor;
+    vec4 emissive;
+    // metallic, roughness, normal scale, occlusion strength
+    vec4 material;
+    // texture indices, only read with bindless textures
+    uvec4 textures;
+    uint emissiveTexture;
+};
+
+// every draw's object uniforms, indexed by the object the vertex shader
+// passes on
+layout(std430, set = 1, binding = 0) readonly buffer Objects {
+    ObjectUniforms objects[];
+};
+
+// this fragment's, looked up first thing in main
+ObjectUniforms object;
+
+// built again with -DBINDLESS into shader_bindless_frag.spv for descriptor indexing, see the README
+#ifdef BINDLESS
+const uint MAX_TEXTURES = 1024;
+
+// every loaded texture, which the object uniforms pick from by index
+layout(set = 2, binding = 0) uniform texture2D textures[MAX_TEXTURES];
+layout(set = 2, binding = 1) uniform sampler samplers[MAX_TEXTURES];
+
+vec4 materialTexture(uint index, vec2 uv) {
+    return texture(sampler2D(textures[index], samplers[index]), uv);
+}
+
+#define SAMPLE_BASE_COLOR(uv) materialTexture(object.textures.x, uv)
+#define SAMPLE_METALLIC_ROUGHNESS(uv) materialTexture(object.textures.y, uv)
+#define SAMPLE_NORMAL(uv) materialTexture(object.textures.z, uv)
+#define SAMPLE_OCCLUSION(uv) materialTexture(object.textures.w, uv)
+#define SAMPLE_EMISSIVE(uv) materialTexture(object.emissiveTexture, uv)
+#else
+layout(set = 2, binding = 0) uniform texture2D baseColorTexture;
+layout(set = 2, binding = 1) uniform sampler baseColorSampler;
+layout(set = 2, binding = 2) uniform texture2D metallicRoughnessTexture;
+layout(set = 2, binding = 3) uniform sampler metallicRoughnessSampler;
+layout(set = 2, binding = 4) uniform texture2D normalTexture;
+layout(set = 2, binding = 5) uniform sampler normalSampler;
+layout(set = 2, binding = 6) uniform texture2D occlusionTexture;
+layout(set = 2, binding = 7) uniform sampler occlusionSampler;
+layout(set = 2, binding = 8) uniform texture2D emissiveTexture;
+layout(set = 2, binding = 9) uniform sampler emissiveSampler;
+
+#define SAMPLE_BASE_COLOR(uv) texture(sampler2D(baseColorTexture, baseColorSampler), uv)
+#define SAMPLE_METALLIC_ROUGHNESS(uv) texture(sampler2D(metallicRoughnessTexture, metallicRoughnessSampler), uv)
+#define SAMPLE_NORMAL(uv) texture(sampler2D(normalTexture, normalSampler), uv)
+#define SAMPLE_OCCLUSION(uv) texture(sampler2D(occlusionTexture, occlusionSampler), uv)
+#define SAMPLE_EMISSIVE(uv) texture(sampler2D(emissiveTexture, emissiveSampler), uv)
+#endif
+
+struct Light {
+    // xyz position, w type
+    vec4 positionType;
+    // xyz direction, w range (0 for unlimited)
+    vec4 directionRange;
+    // rgb colour, a intensity
+    vec4 colorIntensity;
+    // cos of the inner and outer spot cone angles
+    vec4 cone;
+    // first shadow map layer (-1 for none), layer count
+    vec4 shadow;
+};
+
+layout(set = 3, binding = 0) uniform LightUniforms {
+    uint count;
+    Light lights[MAX_LIGHTS];
+    mat4 shadowMatrices[MAX_SHADOW_MAPS];
+    // view space distance at which each cascade ends
+    vec4 cascadeSplits;
+} lightUniforms;
+layout(set = 3, binding = 1) uniform texture2DArray shadowMaps;
+layout(set = 3, binding = 2) uniform samplerShadow shadowSampler;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec2 fragUv;
+layout(location = 4) in vec3 fragColor;
+layout(location = 5) flat in uint fragObject;
 
 layout(location = 0) out vec4 outColor;
 
+float distributionGgx(float nDotH, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+float geometrySchlickGgx(float nDotX, float roughness) {
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+    return nDotX / (nDotX * (1.0 - k) + k);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// smooth window from KHR_lights_punctual, reaching zero at the range
+float rangeAttenuation(float distance, float range) {
+    if (range <= 0.0) {
+        return 1.0;
+    }
+    float ratio = distance / range;
+    return clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+}
+
+// fraction of the light reaching the fragment, from a 3x3 PCF kernel
+float shadowFactor(Light light) {
+    if (light.shadow.x < 0.0) {
+        return 1.0;
+    }
+
+    uint layer = uint(light.shadow.x);
+    if (light.shadow.y > 1.0) {
+        // directional lights pick the cascade covering the fragment's depth
+        float depth = -(camera.view * vec4(fragPosition, 1.0)).z;
+        uint cascade = 0;
+        while (cascade < SHADOW_CASCADES && depth > lightUniforms.cascadeSplits[cascade]) {
+            cascade++;
+        }
+        if (cascade == SHADOW_CASCADES) {
+            return 1.0;
+        }
+        layer += cascade;
+    }
+
+    vec4 lightSpace = lightUniforms.shadowMatrices[layer] * vec4(fragPosition, 1.0);
+    vec3 coords = lightSpace.xyz / lightSpace.w;
+    if (coords.z <= 0.0 || coords.z >= 1.0) {
+        return 1.0;
+    }
+    vec2 uv = coords.xy * 0.5 + 0.5;
+
+    vec2 texelSize = 1.0 / vec2(textureSize(sampler2DArrayShadow(shadowMaps, shadowSampler), 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(float(x), float(y)) * texelSize;
+            lit += texture(
+                sampler2DArrayShadow(shadowMaps, shadowSampler),
+                vec4(uv + offset, float(layer), coords.z)
+            );
+        }
+    }
+    return lit / 9.0;
+}
+
+vec3 surfaceNormal() {
+    vec3 n = normalize(fragNormal);
+    vec3 t = normalize(fragTangent.xyz - n * dot(n, fragTangent.xyz));
+    vec3 b = cross(n, t) * fragTangent.w;
+
+    vec3 tangentNormal = SAMPLE_NORMAL(fragUv).xyz * 2.0 - 1.0;
+    tangentNormal.xy *= object.material.z;
+    return normalize(mat3(t, b, n) * tangentNormal);
+}
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
-}
\ No newline at end of file
+    object = objects[fragObject];
+
+    vec4 baseColor = object.baseColor * vec4(fragColor, 1.0)
+        * SAMPLE_BASE_COLOR(fragUv);
+    vec4 metallicRoughness = SAMPLE_METALLIC_ROUGHNESS(fragUv);
+    float metallic = object.material.x * metallicRoughness.b;
+    // very smooth surfaces turn lights into single bright pixels
+    float roughness = clamp(object.material.y * metallicRoughness.g, 0.04, 1.0);
+    float occlusion = 1.0 + object.material.w
+        * (SAMPLE_OCCLUSION(fragUv).r - 1.0);
+    vec3 emissive = object.emissive.rgb
+        * SAMPLE_EMISSIVE(fragUv).rgb;
+
+    vec3 n = surfaceNormal();
+    vec3 v = normalize(camera.position.xyz - fragPosition);
+    float nDotV = max(dot(n, v), 0.0001);
+
+    vec3 f0 = mix(vec3(0.04, 0.04, 0.04), baseColor.rgb, metallic);
+    vec3 diffuseColor = baseColor.rgb * (1.0 - metallic);
+
+    vec3 radiance = vec3(0.0, 0.0, 0.0);
+    for (uint i = 0; i < min(lightUniforms.count, MAX_LIGHTS); i++) {
+        Light light = lightUniforms.lights[i];
+        uint lightType = uint(light.positionType.w);
+
+        vec3 l;
+        float attenuation = 1.0;
+        if (lightType == LIGHT_DIRECTIONAL) {
+            l = -light.directionRange.xyz;
+        } else {
+            vec3 toLight = light.positionType.xyz - fragPosition;
+            float distance = length(toLight);
+            l = toLight / distance;
+            attenuation = rangeAttenuation(distance, light.directionRange.w)
+                / max(distance * distance, 0.0001);
+
+            if (lightType == LIGHT_SPOT) {
+                float cosAngle = dot(-l, light.directionRange.xyz);
+                float spot = clamp(
+                    (cosAngle - light.cone.y) / max(light.cone.x - light.cone.y, 0.0001),
+                    0.0,
+                    1.0
+                );
+                attenuation *= spot * spot;
+            }
+        }
+
+        float nDotL = dot(n, l);
+        if (nDotL <= 0.0 || attenuation <= 0.0) {
+            continue;
+        }
+
+        attenuation *= shadowFactor(light);
+
+        vec3 h = normalize(v + l);
+        float nDotH = max(dot(n, h), 0.0);
+        vec3 f = fresnelSchlick(max(dot(h, v), 0.0), f0);
+
+        float d = distributionGgx(nDotH, roughness);
+        float g = geometrySchlickGgx(nDotV, roughness) * geometrySchlickGgx(nDotL, roughness);
+        vec3 specular = d * g * f / (4.0 * nDotV * nDotL);
+        vec3 diffuse = (1.0 - f) * diffuseColor / PI;
+
+        vec3 lightColor = light.colorIntensity.rgb * light.colorIntensity.a;
+        radiance += (diffuse + specular) * lightColor * attenuation * nDotL;
+    }
+
+    vec3 ambient = ambientLight * baseColor.rgb * occlusion;
+    outColor = vec4(ambient + radiance + emissive, baseColor.a);
+}
@@ -1,20 +1,64 @@
 #version 450
 
-vec2 positions[3] = vec2[](
-    vec2(0.0, -0.5),
-    vec2(0.5, 0.5),
-    vec2(-0.5, 0.5)
-);
+layout(set = 0, binding = 0) uniform CameraUniforms {
+    mat4 view;
+    mat4 proj;
+    vec4 position;
+    mat4 inverseViewProj;
+} camera;
 
-vec3 colors[3] = vec3[](
-    vec3(1.0, 0.0, 0.0),
-    vec3(0.0, 1.0, 0.0),
-    vec3(0.0, 0.0, 1.0)
-);
+struct ObjectUniforms {
+    mat4 model;
+    mat4 normalMatrix;
+    vec4 baseColor;
+    vec4 emissive;
+    vec4 material;
+    // texture indices, only read with bindless textures
+    uvec4 textures;
+    uint emissiveTexture;
+};
 
-layout(location = 0) out vec3 fragColor;
+// every draw's object uniforms, indexed by the object each instance is
+// drawn with
+layout(std430, set = 1, binding = 0) readonly buffer Objects {
+    ObjectUniforms objects[];
+};
 
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec4 inTangent;
+layout(location = 3) in vec2 inUv;
+layout(location = 4) in vec3 inColor;
+// per instance, the model matrix a column at a time, placed within the object
+layout(location = 5) in vec4 instanceModel0;
+layout(location = 6) in vec4 instanceModel1;
+layout(location = 7) in vec4 instanceModel2;
+layout(location = 8) in vec4 instanceModel3;
+layout(location = 9) in vec3 instanceColor;
+// which draw's object the instance belongs to
+layout(location = 10) in uint instanceObject;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 fragTangent;
+layout(location = 3) out vec2 fragUv;
+layout(location = 4) out vec3 fragColor;
+layout(location = 5) flat out uint fragObject;
+
+// instances only rotate and scale uniformly, so their own matrix turns
+// normals as it does positions
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-    fragColor = colors[gl_VertexIndex];
-}
\ No newline at end of file
+    ObjectUniforms object = objects[instanceObject];
+    mat4 instanceModel = mat4(instanceModel0, instanceModel1, instanceModel2, instanceModel3);
+    mat4 model = object.model * instanceModel;
+    vec4 worldPosition = model * vec4(inPosition, 1.0);
+    gl_Position = camera.proj * camera.view * worldPosition;
+
+    fragPosition = worldPosition.xyz;
+    fragNormal = mat3(object.normalMatrix) * mat3(instanceModel) * inNormal;
+    // tangents lie in the surface, so they transform like positions
+    fragTangent = vec4(mat3(model) * inTangent.xyz, inTangent.w);
+    fragUv = inUv;
+    fragColor = inColor * instanceColor;
+    fragObject = instanceObject;
+}
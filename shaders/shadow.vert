@@ -0,0 +1,33 @@
+#version 450
+
+layout(push_constant) uniform ShadowPass {
+    mat4 lightViewProj;
+} shadowPass;
+
+struct ObjectUniforms {
+    mat4 model;
+    mat4 normalMatrix;
+    vec4 baseColor;
+    vec4 emissive;
+    vec4 material;
+    // texture indices, only read with bindless textures
+    uvec4 textures;
+    uint emissiveTexture;
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer Objects {
+    ObjectUniforms objects[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 5) in vec4 instanceModel0;
+layout(location = 6) in vec4 instanceModel1;
+layout(location = 7) in vec4 instanceModel2;
+layout(location = 8) in vec4 instanceModel3;
+layout(location = 10) in uint instanceObject;
+
+void main() {
+    mat4 instanceModel = mat4(instanceModel0, instanceModel1, instanceModel2, instanceModel3);
+    mat4 model = objects[instanceObject].model * instanceModel;
+    gl_Position = shadowPass.lightViewProj * model * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,62 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+// signed distance to the glyph outlines in alpha, 0.5 on the outline
+layout(set = 1, binding = 0) uniform texture2D atlasTexture;
+layout(set = 1, binding = 1) uniform sampler atlasSampler;
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+const uint OUTPUT_HDR10 = 1;
+const uint OUTPUT_SCRGB = 2;
+
+// linear Rec.709 to linear Rec.2020, columns first
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// SMPTE ST 2084 inverse EOTF, from absolute nits to a 0..1 signal
+vec3 pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0)), vec3(m1, m1, m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2, m2, m2));
+}
+
+// antialiases the outline over about a screen pixel whatever the scale, and
+// encodes the colour for the swapchain like the output pass. Premultiplied
+// for blending over the finished frame
+void main() {
+    float distance = texture(sampler2D(atlasTexture, atlasSampler), fragUv).a;
+    float width = max(fwidth(distance) * 0.5, 0.001);
+    float alpha = smoothstep(0.5 - width, 0.5 + width, distance) * fragColor.a;
+
+    vec3 color = fragColor.rgb;
+    if (post.outputSpace == OUTPUT_HDR10) {
+        color = pq(REC709_TO_REC2020 * color * post.paperWhite);
+    } else if (post.outputSpace == OUTPUT_SCRGB) {
+        color *= post.paperWhite / 80.0;
+    }
+    outColor = vec4(color * alpha, alpha);
+}
@@ -0,0 +1,46 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform PostUniforms {
+    float exposure;
+    // 0 ACES, 1 Reinhard
+    uint tonemapper;
+    float bloomThreshold;
+    float bloomIntensity;
+    float vignetteStrength;
+    float gamma;
+    // 0 SDR, 1 HDR10, 2 scRGB
+    uint outputSpace;
+    float paperWhite;
+    float peak;
+} post;
+
+layout(set = 1, binding = 0) uniform texture2D sourceTexture;
+layout(set = 1, binding = 1) uniform sampler sourceSampler;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+const uint TONEMAP_ACES = 0;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+// maps into 0..peak, which is 1 for SDR and the display's brightest white
+// relative to paper white for HDR
+void main() {
+    vec3 color = texture(sampler2D(sourceTexture, sourceSampler), fragUv).rgb * post.exposure;
+    color /= post.peak;
+    if (post.tonemapper == TONEMAP_ACES) {
+        color = aces(color);
+    } else {
+        color = reinhard(color);
+    }
+    outColor = vec4(color * post.peak, 1.0);
+}
@@ -0,0 +1,333 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Vector3, Vector4};
+use winit::window::Window;
+
+use crate::input::{ActionMap, InputState};
+use crate::mesh::{Aabb, Sphere};
+
+const UP: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
+
+/// Keeps the camera from flipping over when looking straight up or down
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Per-frame camera matrices, must match the `CameraUniforms` block in the shaders
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniforms {
+    pub view: Matrix4<f32>,
+    pub proj: Matrix4<f32>,
+    /// World space eye position, w is 1
+    pub position: Vector4<f32>,
+    /// Takes clip space back to world space, to find positions from depth
+    pub inverse_view_proj: Matrix4<f32>,
+}
+
+/// Right handed perspective projection for Vulkan: clip space Y points down
+/// and depth runs from 0 at the near plane to 1 at the far plane, unlike the
+/// OpenGL convention `cgmath::perspective` follows.
+pub fn perspective(fovy: Rad<f32>, aspect: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let f = 1.0 / (fovy.0 / 2.0).tan();
+
+    #[rustfmt::skip]
+    let proj = Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0,
+        0.0, -f, 0.0, 0.0,
+        0.0, 0.0, far / (near - far), -1.0,
+        0.0, 0.0, near * far / (near - far), 0.0,
+    );
+
+    proj
+}
+
+/// Orthographic counterpart of `perspective`, mapping the box between the
+/// given view space bounds to Vulkan clip space
+pub fn orthographic(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+) -> Matrix4<f32> {
+    #[rustfmt::skip]
+    let proj = Matrix4::new(
+        2.0 / (right - left), 0.0, 0.0, 0.0,
+        0.0, -2.0 / (top - bottom), 0.0, 0.0,
+        0.0, 0.0, -1.0 / (far - near), 0.0,
+        -(right + left) / (right - left), (top + bottom) / (top - bottom), -near / (far - near), 1.0,
+    );
+
+    proj
+}
+
+/// The six planes bounding what a view-projection matrix sees, in the space
+/// the matrix transforms from, as `(normal, distance)` with the normals
+/// pointing inwards and normalized.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        // cgmath stores columns, the planes come from the rows
+        let row = |i: usize| Vector4::new(matrix.x[i], matrix.y[i], matrix.z[i], matrix.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        // Vulkan clip space depth runs from 0 rather than -w, so the near
+        // plane is just the z row
+        let planes = [w + x, w - x, w + y, w - y, z, w - z]
+            .map(|plane| plane / plane.truncate().magnitude());
+
+        Frustum { planes }
+    }
+
+    /// Whether any of the sphere is inside
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center.to_vec()) + plane.w >= -sphere.radius)
+    }
+
+    /// Whether any of the box might be inside. Conservative: a box just off a
+    /// corner of the frustum, outside it but not wholly behind any one plane,
+    /// still passes
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+pub struct Projection {
+    pub fovy: Rad<f32>,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Projection {
+    pub fn new(width: u32, height: u32) -> Self {
+        Projection {
+            fovy: Rad(std::f32::consts::FRAC_PI_4),
+            aspect: width as f32 / height.max(1) as f32,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.aspect = width as f32 / height.max(1) as f32;
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        perspective(self.fovy, self.aspect, self.near, self.far)
+    }
+}
+
+/// Unit vector for the given yaw and pitch, with zero yaw looking down -Z
+fn direction(yaw: f32, pitch: f32) -> Vector3<f32> {
+    Vector3::new(
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+        -pitch.cos() * yaw.cos(),
+    )
+}
+
+/// Free flying camera driven by the `move_*` actions, with mouse look while
+/// the cursor is grabbed (`look` to grab, `release_cursor` to let go).
+pub struct FlyCamera {
+    pub position: Point3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub speed: f32,
+    pub sensitivity: f32,
+    cursor_grabbed: bool,
+}
+
+impl FlyCamera {
+    pub fn new(position: Point3<f32>, yaw: f32, pitch: f32) -> Self {
+        FlyCamera {
+            position,
+            yaw,
+            pitch,
+            speed: 2.0,
+            sensitivity: 0.003,
+            cursor_grabbed: false,
+        }
+    }
+
+    /// Camera at `position` facing `forward`, which needn't be normalized
+    pub fn looking_along(position: Point3<f32>, forward: Vector3<f32>) -> Self {
+        let forward = forward.normalize();
+        let pitch = forward.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+        let yaw = forward.x.atan2(-forward.z);
+        Self::new(position, yaw, pitch)
+    }
+
+    fn set_cursor_grab(&mut self, window: &Window, grab: bool) {
+        // not every platform supports grabbing, mouse look still works without it
+        if window.set_cursor_grab(grab).is_ok() {
+            window.set_cursor_visible(!grab);
+        }
+        self.cursor_grabbed = grab;
+    }
+
+    fn update(
+        &mut self,
+        input: &InputState,
+        actions: &ActionMap,
+        window: &Window,
+        delta_time: f32,
+    ) {
+        if !self.cursor_grabbed && actions.pressed("look", input) {
+            self.set_cursor_grab(window, true);
+        } else if self.cursor_grabbed && actions.pressed("release_cursor", input) {
+            self.set_cursor_grab(window, false);
+        }
+
+        if self.cursor_grabbed {
+            let (dx, dy) = input.mouse_delta();
+            self.yaw += dx as f32 * self.sensitivity;
+            self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let forward = direction(self.yaw, self.pitch);
+        let right = forward.cross(UP).normalize();
+
+        let movement = forward * actions.axis("move_forward", "move_backward", input)
+            + right * actions.axis("move_right", "move_left", input)
+            + UP * actions.axis("move_up", "move_down", input);
+        if movement.magnitude2() > 0.0 {
+            self.position += movement.normalize() * self.speed * delta_time;
+        }
+    }
+
+    fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, direction(self.yaw, self.pitch), UP)
+    }
+}
+
+/// Camera circling a target point: drag while `look` is held to rotate and
+/// scroll to zoom.
+pub struct OrbitCamera {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub sensitivity: f32,
+}
+
+impl OrbitCamera {
+    pub fn new(target: Point3<f32>, distance: f32, yaw: f32, pitch: f32) -> Self {
+        OrbitCamera {
+            target,
+            distance,
+            yaw,
+            pitch,
+            sensitivity: 0.005,
+        }
+    }
+
+    fn eye(&self) -> Point3<f32> {
+        self.target - direction(self.yaw, self.pitch) * self.distance
+    }
+
+    fn update(&mut self, input: &InputState, actions: &ActionMap) {
+        if actions.down("look", input) {
+            let (dx, dy) = input.mouse_delta();
+            self.yaw += dx as f32 * self.sensitivity;
+            self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        self.distance = (self.distance * 0.9_f32.powf(input.wheel_delta())).clamp(0.5, 50.0);
+    }
+
+    fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye(), self.target, UP)
+    }
+}
+
+pub enum Camera {
+    Fly(FlyCamera),
+    Orbit(OrbitCamera),
+}
+
+impl Camera {
+    /// Switches between fly and orbit mode, keeping the current eye position
+    /// and viewing direction.
+    pub fn toggle_mode(&mut self, window: &Window) {
+        *self = match self {
+            Camera::Fly(fly) => {
+                fly.set_cursor_grab(window, false);
+                let distance = 3.0;
+                Camera::Orbit(OrbitCamera::new(
+                    fly.position + direction(fly.yaw, fly.pitch) * distance,
+                    distance,
+                    fly.yaw,
+                    fly.pitch,
+                ))
+            }
+            Camera::Orbit(orbit) => {
+                Camera::Fly(FlyCamera::new(orbit.eye(), orbit.yaw, orbit.pitch))
+            }
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        actions: &ActionMap,
+        window: &Window,
+        delta_time: f32,
+    ) {
+        if actions.pressed("toggle_camera", input) {
+            self.toggle_mode(window);
+        }
+
+        match self {
+            Camera::Fly(fly) => fly.update(input, actions, window, delta_time),
+            Camera::Orbit(orbit) => orbit.update(input, actions),
+        }
+    }
+
+    /// Gives the cursor back, e.g. when the window loses focus
+    pub fn release_cursor(&mut self, window: &Window) {
+        if let Camera::Fly(fly) = self {
+            if fly.cursor_grabbed {
+                fly.set_cursor_grab(window, false);
+            }
+        }
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        match self {
+            Camera::Fly(fly) => fly.position,
+            Camera::Orbit(orbit) => orbit.eye(),
+        }
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        match self {
+            Camera::Fly(fly) => fly.view_matrix(),
+            Camera::Orbit(orbit) => orbit.view_matrix(),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use std::path::Path;
+
+use log::debug;
+
+/// Reads the TOML file at `path` into `T`, with `what` naming the config in
+/// errors and logs, e.g. "render"
+pub fn load<T: DeserializeOwned + Debug>(path: impl AsRef<Path>, what: &str) -> Result<T> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read {} config {}", what, path.display()))?;
+    let config: T = toml::from_str(&contents)
+        .with_context(|| format!("could not parse {} config {}", what, path.display()))?;
+
+    debug!("Loaded {} config: {:?}", what, config);
+
+    Ok(config)
+}
+
+/// Like `load`, but falls back to the defaults if the file is missing or invalid
+pub fn load_or_default<T: DeserializeOwned + Debug + Default>(
+    path: impl AsRef<Path>,
+    what: &str,
+) -> T {
+    load(path, what).unwrap_or_else(|e| {
+        log::warn!("using default {} config: {:#}", what, e);
+        T::default()
+    })
+}
@@ -0,0 +1,181 @@
+use ash::extensions::khr::DrawIndirectCount;
+use ash::vk;
+use cgmath::{Matrix4, Vector4};
+
+use crate::camera::Frustum;
+use crate::mesh::GpuMesh;
+use crate::scene::MaterialId;
+
+/// One draw's bounds and indirect parameters, must match `CullDraw` in cull.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CullDraw {
+    pub world: Matrix4<f32>,
+    /// Model space bounds of the mesh, w unused
+    pub bounds_min: [f32; 4],
+    pub bounds_max: [f32; 4],
+    pub index_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+    pub instance_count: u32,
+    /// Index of the draw's `ObjectUniforms`
+    pub object: u32,
+    /// Index of the draw's `CullBatch`, and where the batch's commands start
+    pub batch: u32,
+    pub first_command: u32,
+    /// std430 rounds the struct up to a multiple of 16 bytes
+    _padding: [u32; 3],
+}
+
+impl CullDraw {
+    pub fn new(
+        world: Matrix4<f32>,
+        mesh: &GpuMesh,
+        first_instance: u32,
+        instance_count: u32,
+        object: u32,
+        batch: u32,
+        first_command: u32,
+    ) -> Self {
+        CullDraw {
+            world,
+            bounds_min: mesh.bounds.min.to_homogeneous().into(),
+            bounds_max: mesh.bounds.max.to_homogeneous().into(),
+            index_count: mesh.index_count,
+            first_index: mesh.first_index,
+            vertex_offset: mesh.vertex_offset,
+            first_instance,
+            instance_count,
+            object,
+            batch,
+            first_command,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Must match the `CullParams` uniform block in cull.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CullParams {
+    pub planes: [Vector4<f32>; 6],
+    pub draw_count: u32,
+    /// Whether to pack each batch's visible draws at the start of its
+    /// commands, for an indirect count draw, rather than leave each draw in
+    /// its own slot
+    pub compact: u32,
+    /// std140 rounds the block up to a multiple of 16 bytes
+    _padding: [u32; 2],
+}
+
+impl CullParams {
+    pub fn new(frustum: &Frustum, draw_count: u32, compact: bool) -> Self {
+        CullParams {
+            planes: frustum.planes,
+            draw_count,
+            compact: compact as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// A run of a frame's draws sharing a material, which the scene pass draws
+/// with one indirect draw once the material's textures are bound. With
+/// bindless textures every draw is in the one run
+#[derive(Clone, Copy, Debug)]
+pub struct CullBatch {
+    pub material: MaterialId,
+    /// The run's first command, also its first draw
+    pub first_command: u32,
+    pub draw_count: u32,
+}
+
+/// How many of the scene's nodes with a mesh its traversal found in view
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuCullStats {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+/// What the last frame's culling pass left to draw
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+    pub visible_draws: usize,
+    pub visible_instances: usize,
+}
+
+/// The compute pipeline turning each frame's draws into indirect draws of
+/// just their visible instances, one indirect draw per material
+pub struct GpuCulling {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    /// Only loaded when the device has VK_KHR_draw_indirect_count, which lets
+    /// draws culled entirely be skipped rather than drawn with no instances
+    pub draw_indirect_count: Option<DrawIndirectCount>,
+}
+
+impl GpuCulling {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
+
+/// A window's culling inputs and outputs for one frame in flight. The draws
+/// and parameters are written by the host; the visible instances, their
+/// objects and the indirect commands only by the compute pass. The counts
+/// are zeroed by the host, counted by the pass and read back for stats: the
+/// visible instances, then each batch's visible draws.
+pub struct CullFrame {
+    pub params_buffer: vk::Buffer,
+    pub params_memory: vk::DeviceMemory,
+    pub draw_buffer: vk::Buffer,
+    pub draw_memory: vk::DeviceMemory,
+    pub visible_instance_buffer: vk::Buffer,
+    pub visible_instance_memory: vk::DeviceMemory,
+    pub visible_object_buffer: vk::Buffer,
+    pub visible_object_memory: vk::DeviceMemory,
+    pub command_buffer: vk::Buffer,
+    pub command_memory: vk::DeviceMemory,
+    pub count_buffer: vk::Buffer,
+    pub count_memory: vk::DeviceMemory,
+    pub descriptor_set: vk::DescriptorSet,
+    /// Draws culled by the frame last recorded, none if it drew directly
+    pub draw_count: u32,
+    /// The frame's draws by material, in the order they were culled
+    pub batches: Vec<CullBatch>,
+}
+
+impl CullFrame {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        for (buffer, memory) in [
+            (self.params_buffer, self.params_memory),
+            (self.draw_buffer, self.draw_memory),
+            (self.visible_instance_buffer, self.visible_instance_memory),
+            (self.visible_object_buffer, self.visible_object_memory),
+            (self.command_buffer, self.command_memory),
+            (self.count_buffer, self.count_memory),
+        ] {
+            device.destroy_buffer(buffer, None);
+            device.free_memory(memory, None);
+        }
+    }
+}
+
+/// A window's culling buffers, one set per frame in flight
+pub struct CullBuffers {
+    pub descriptor_pool: vk::DescriptorPool,
+    pub frames: Vec<CullFrame>,
+}
+
+impl CullBuffers {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        for frame in &self.frames {
+            frame.destroy(device);
+        }
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+    }
+}
@@ -0,0 +1,185 @@
+use ash::vk;
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use std::sync::{Mutex, PoisonError};
+
+use crate::mesh::Aabb;
+
+/// Most line vertices drawn into a window in a frame, sizing its vertex
+/// buffers. Lines past it are dropped
+pub const MAX_DEBUG_VERTICES: usize = 65536;
+
+/// Segments in each of a sphere's circles
+const SPHERE_SEGMENTS: usize = 32;
+
+/// Lines queued since the app last took them, shared so anything can draw
+static LINES: Mutex<DebugLines> = Mutex::new(DebugLines {
+    depth_tested: Vec::new(),
+    overlay: Vec::new(),
+});
+
+/// Vertex layout of the debug line pipelines, must match the inputs of debug.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl DebugVertex {
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 12,
+            },
+        ]
+    }
+}
+
+/// A frame's world space lines, two vertices each
+#[derive(Default)]
+pub struct DebugLines {
+    /// Hidden behind the scene like any geometry
+    pub depth_tested: Vec<DebugVertex>,
+    /// Drawn over everything
+    pub overlay: Vec<DebugVertex>,
+}
+
+/// Takes every line queued since the last call, leaving none for the next frame
+pub fn take() -> DebugLines {
+    std::mem::take(&mut *LINES.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+pub fn line(from: Point3<f32>, to: Point3<f32>, color: [f32; 4], depth_test: bool) {
+    lines(&[(from, to)], color, depth_test);
+}
+
+pub fn aabb(aabb: &Aabb, color: [f32; 4], depth_test: bool) {
+    box_edges(aabb.corners(), color, depth_test);
+}
+
+/// Three circles round the sphere, one facing down each axis
+pub fn sphere(center: Point3<f32>, radius: f32, color: [f32; 4], depth_test: bool) {
+    let point = |axis: usize, i: usize| {
+        let angle = i as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        let offset = match axis {
+            0 => Vector3::new(0.0, cos, sin),
+            1 => Vector3::new(sin, 0.0, cos),
+            _ => Vector3::new(cos, sin, 0.0),
+        };
+        center + offset * radius
+    };
+    let segments: Vec<_> = (0..3)
+        .flat_map(|axis| (0..SPHERE_SEGMENTS).map(move |i| (point(axis, i), point(axis, i + 1))))
+        .collect();
+    lines(&segments, color, depth_test);
+}
+
+/// The x, y and z axes of `transform` in red, green and blue, `size` long
+/// before any scaling it has
+pub fn axes(transform: Matrix4<f32>, size: f32, depth_test: bool) {
+    let origin = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+    for (axis, color) in [
+        (Vector3::unit_x(), [1.0, 0.0, 0.0, 1.0]),
+        (Vector3::unit_y(), [0.0, 1.0, 0.0, 1.0]),
+        (Vector3::unit_z(), [0.0, 0.0, 1.0, 1.0]),
+    ] {
+        let end = transform.transform_point(Point3::new(0.0, 0.0, 0.0) + axis * size);
+        line(origin, end, color, depth_test);
+    }
+}
+
+/// The volume `view_proj` maps to clip space, such as a camera's view
+pub fn frustum(view_proj: Matrix4<f32>, color: [f32; 4], depth_test: bool) {
+    let inverse = match view_proj.invert() {
+        Some(inverse) => inverse,
+        None => return,
+    };
+    // the clip space box, depth running 0..1 as in Vulkan
+    let clip_box = Aabb {
+        min: Point3::new(-1.0, -1.0, 0.0),
+        max: Point3::new(1.0, 1.0, 1.0),
+    };
+    box_edges(
+        clip_box
+            .corners()
+            .map(|corner| inverse.transform_point(corner)),
+        color,
+        depth_test,
+    );
+}
+
+/// The twelve edges of a box with corners ordered like `Aabb::corners`
+fn box_edges(corners: [Point3<f32>; 8], color: [f32; 4], depth_test: bool) {
+    // corners one bit apart share an edge
+    let edges: Vec<_> = (0..8)
+        .flat_map(|i| [1, 2, 4].map(|bit| (i, i | bit)))
+        .filter(|&(i, j)| i != j)
+        .map(|(i, j)| (corners[i], corners[j]))
+        .collect();
+    lines(&edges, color, depth_test);
+}
+
+fn lines(segments: &[(Point3<f32>, Point3<f32>)], color: [f32; 4], depth_test: bool) {
+    let mut queued = LINES.lock().unwrap_or_else(PoisonError::into_inner);
+    let list = if depth_test {
+        &mut queued.depth_tested
+    } else {
+        &mut queued.overlay
+    };
+    list.extend(segments.iter().flat_map(|&(from, to)| {
+        [from, to].map(|position| DebugVertex {
+            position: position.into(),
+            color,
+        })
+    }));
+}
+
+/// Draws the queued lines into the scene, in the subpass particles are drawn in
+pub struct DebugDrawPipelines {
+    /// Set 0 the camera uniforms
+    pub pipeline_layout: vk::PipelineLayout,
+    pub depth_tested_pipeline: vk::Pipeline,
+    pub overlay_pipeline: vk::Pipeline,
+}
+
+impl DebugDrawPipelines {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_pipeline(self.overlay_pipeline, None);
+        device.destroy_pipeline(self.depth_tested_pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+    }
+}
+
+/// A host visible buffer a frame's lines are written into, the depth tested
+/// ones first
+pub struct DebugLineBuffer {
+    pub buffer: vk::Buffer,
+    pub memory: vk::DeviceMemory,
+    pub depth_tested_count: u32,
+    pub overlay_count: u32,
+}
+
+impl DebugLineBuffer {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.memory, None);
+    }
+}
@@ -0,0 +1,38 @@
+use ash::vk;
+
+/// Colour attachments written by the deferred geometry subpass besides the
+/// lit colour, in attachment order: albedo with ambient occlusion in alpha,
+/// world space normal, and metallic and roughness in red and green.
+/// Must match the outputs of gbuffer.frag and the inputs of deferred.frag.
+pub const GBUFFER_FORMATS: [vk::Format; 3] = [
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R8G8B8A8_UNORM,
+];
+
+/// The G-buffer images and the fullscreen pipeline that lights them
+pub struct GBuffer {
+    pub images: Vec<vk::Image>,
+    pub memories: Vec<vk::DeviceMemory>,
+    pub views: Vec<vk::ImageView>,
+    /// The G-buffer followed by the depth buffer, as input attachments
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub lighting_pipeline_layout: vk::PipelineLayout,
+    pub lighting_pipeline: vk::Pipeline,
+}
+
+impl GBuffer {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_pipeline(self.lighting_pipeline, None);
+        device.destroy_pipeline_layout(self.lighting_pipeline_layout, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        for ((&view, &image), &memory) in self.views.iter().zip(&self.images).zip(&self.memories) {
+            device.destroy_image_view(view, None);
+            device.destroy_image(image, None);
+            device.free_memory(memory, None);
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::{Context, Result};
+use ash::vk;
+use cgmath::{Quaternion, Vector3};
+use std::path::Path;
+
+use log::{debug, warn};
+
+use crate::light::{Light, LightKind};
+use crate::mesh::{Mesh, Vertex};
+use crate::scene::{Material, MaterialId, MeshId, NodeId, Scene, Transform};
+use crate::texture::{SamplerDesc, TextureData};
+
+/// A perspective camera found in the file, looking down -Z of its node
+#[derive(Clone, Copy, Debug)]
+pub struct SceneCamera {
+    pub node: NodeId,
+    pub yfov: f32,
+    pub znear: f32,
+    pub zfar: Option<f32>,
+}
+
+/// Everything read from a glTF file, with meshes, materials and textures
+/// referenced by index from the scene's nodes.
+pub struct ImportedScene {
+    pub scene: Scene,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<TextureData>,
+    pub cameras: Vec<SceneCamera>,
+}
+
+/// Loads a `.gltf` (with external or embedded buffers and images) or `.glb` file.
+/// Only the default scene is imported, or the first one if there's no default.
+pub fn load(path: impl AsRef<Path>) -> Result<ImportedScene> {
+    let path = path.as_ref();
+    let (document, buffers, images) = gltf::import(path)
+        .with_context(|| format!("could not import glTF file {}", path.display()))?;
+
+    let mut importer = Importer {
+        buffers: &buffers,
+        primitives: vec![vec![]; document.meshes().len()],
+        imported: ImportedScene {
+            scene: Scene::default(),
+            meshes: vec![],
+            materials: document.materials().map(import_material).collect(),
+            textures: vec![],
+            cameras: vec![],
+        },
+    };
+
+    importer.imported.textures = import_textures(&document, &images)?;
+
+    // primitives without a material use the default one, appended last
+    let default_material = importer.imported.materials.len();
+    importer.imported.materials.push(Material::default());
+
+    for mesh in document.meshes() {
+        importer.import_mesh(&mesh, default_material)?;
+    }
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .context("glTF file contains no scenes")?;
+
+    for node in scene.nodes() {
+        importer.import_node(&node, None);
+    }
+
+    debug!(
+        "Imported {}: {} nodes, {} meshes, {} materials, {} textures, {} cameras",
+        path.display(),
+        importer.imported.scene.len(),
+        importer.imported.meshes.len(),
+        importer.imported.materials.len(),
+        importer.imported.textures.len(),
+        importer.imported.cameras.len(),
+    );
+
+    Ok(importer.imported)
+}
+
+struct Importer<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    /// The (mesh, material) of each primitive, per glTF mesh
+    primitives: Vec<Vec<(MeshId, MaterialId)>>,
+    imported: ImportedScene,
+}
+
+impl Importer<'_> {
+    fn import_mesh(&mut self, mesh: &gltf::Mesh, default_material: MaterialId) -> Result<()> {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warn!(
+                    "skipping {:?} primitive of mesh {:?}, only triangles are supported",
+                    primitive.mode(),
+                    mesh.name()
+                );
+                continue;
+            }
+
+            let imported = import_primitive(&primitive, self.buffers).with_context(|| {
+                format!(
+                    "invalid primitive {} in mesh {:?}",
+                    primitive.index(),
+                    mesh.name()
+                )
+            })?;
+            let material = primitive.material().index().unwrap_or(default_material);
+
+            self.primitives[mesh.index()].push((self.imported.meshes.len(), material));
+            self.imported.meshes.push(imported);
+        }
+
+        Ok(())
+    }
+
+    fn import_node(&mut self, node: &gltf::Node, parent: Option<NodeId>) {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let local = Transform {
+            translation: Vector3::from(translation),
+            // glTF stores quaternions as xyzw
+            rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+            scale: Vector3::from(scale),
+        };
+
+        let name = node.name().unwrap_or("");
+        let id = self.imported.scene.add_node(name, parent, local);
+
+        if let Some(mesh) = node.mesh() {
+            // a scene node draws one mesh, so extra primitives become children
+            match self.primitives[mesh.index()].clone().as_slice() {
+                [] => (),
+                &[(mesh, material)] => self.imported.scene.set_mesh(id, mesh, material),
+                primitives => {
+                    for (i, (mesh, material)) in primitives.iter().copied().enumerate() {
+                        let child = self.imported.scene.add_node(
+                            &format!("{}#{}", name, i),
+                            Some(id),
+                            Transform::default(),
+                        );
+                        self.imported.scene.set_mesh(child, mesh, material);
+                    }
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            match camera.projection() {
+                gltf::camera::Projection::Perspective(perspective) => {
+                    self.imported.cameras.push(SceneCamera {
+                        node: id,
+                        yfov: perspective.yfov(),
+                        znear: perspective.znear(),
+                        zfar: perspective.zfar(),
+                    })
+                }
+                gltf::camera::Projection::Orthographic(_) => {
+                    warn!("skipping orthographic camera {:?}", camera.name())
+                }
+            }
+        }
+
+        if let Some(light) = node.light() {
+            self.imported.scene.set_light(id, import_light(&light));
+        }
+
+        for child in node.children() {
+            self.import_node(&child, Some(id));
+        }
+    }
+}
+
+fn import_primitive(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Result<Mesh> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+    let mut positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .context("primitive has no positions")?
+        .collect();
+    let vertex_count = positions.len();
+
+    let mut indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertex_count as u32).collect(),
+    };
+    if indices.is_empty() {
+        anyhow::bail!("primitive has no vertices");
+    }
+    if let Some(&index) = indices
+        .iter()
+        .find(|&&index| index as usize >= vertex_count)
+    {
+        anyhow::bail!("index {} out of range of {} vertices", index, vertex_count);
+    }
+
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.collect());
+    let mut tangents: Option<Vec<[f32; 4]>> =
+        reader.read_tangents().map(|tangents| tangents.collect());
+    let mut uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+        Some(uvs) => uvs.into_f32().collect(),
+        None => vec![[0.0, 0.0]; vertex_count],
+    };
+    let mut colors: Vec<[f32; 3]> = match reader.read_colors(0) {
+        Some(colors) => colors.into_rgb_f32().collect(),
+        None => vec![[1.0, 1.0, 1.0]; vertex_count],
+    };
+
+    for (attribute, count) in [
+        ("normals", normals.as_ref().map(Vec::len)),
+        ("tangents", tangents.as_ref().map(Vec::len)),
+        ("texture coordinates", Some(uvs.len())),
+        ("colours", Some(colors.len())),
+    ] {
+        if let Some(count) = count.filter(|&count| count != vertex_count) {
+            anyhow::bail!("{} {} for {} positions", count, attribute, vertex_count);
+        }
+    }
+
+    let normals = match normals {
+        Some(normals) => normals,
+        // flat shading needs each triangle to have vertices of its own, or
+        // shared ones would take just one of their triangles' normals
+        None => {
+            positions = unindex(&positions, &indices);
+            tangents = tangents.map(|tangents| unindex(&tangents, &indices));
+            uvs = unindex(&uvs, &indices);
+            colors = unindex(&colors, &indices);
+            indices = (0..indices.len() as u32).collect();
+            flat_normals(&positions)
+        }
+    };
+
+    // normal maps need the file's tangents to line up with the texture, but
+    // the shader still needs some tangent perpendicular to the normal
+    let tangents: Vec<[f32; 4]> =
+        tangents.unwrap_or_else(|| normals.iter().map(|&normal| any_tangent(normal)).collect());
+
+    let vertices = (0..positions.len())
+        .map(|i| Vertex {
+            position: positions[i],
+            normal: normals[i],
+            tangent: tangents[i],
+            uv: uvs[i],
+            color: colors[i],
+        })
+        .collect();
+
+    Ok(Mesh { vertices, indices })
+}
+
+/// The vertex attribute each index refers to, in index order
+fn unindex<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+    indices
+        .iter()
+        .map(|&index| values[index as usize])
+        .collect()
+}
+
+/// Normals for primitives that don't provide them, as the spec asks: flat
+/// shading, each triangle of unindexed `positions` facing its own way
+fn flat_normals(positions: &[[f32; 3]]) -> Vec<[f32; 3]> {
+    use cgmath::InnerSpace;
+
+    let mut normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+    for (triangle, normals) in positions.chunks_exact(3).zip(normals.chunks_exact_mut(3)) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(triangle[i]));
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude2() > 0.0 {
+            normals.fill(normal.normalize().into());
+        }
+    }
+    normals
+}
+
+fn import_light(light: &gltf::khr_lights_punctual::Light) -> Light {
+    use gltf::khr_lights_punctual::Kind;
+
+    let kind = match light.kind() {
+        Kind::Directional => LightKind::Directional,
+        Kind::Point => LightKind::Point,
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+    };
+
+    Light {
+        kind,
+        color: light.color(),
+        intensity: light.intensity(),
+        range: light.range(),
+        casts_shadows: kind != LightKind::Point,
+    }
+}
+
+fn any_tangent(normal: [f32; 3]) -> [f32; 4] {
+    use cgmath::InnerSpace;
+
+    let normal = Vector3::from(normal);
+    let axis = if normal.y.abs() < 0.99 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_x()
+    };
+    axis.cross(normal).normalize().extend(1.0).into()
+}
+
+fn import_material(material: gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+
+    Material {
+        base_color: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|info| info.texture().index()),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|info| info.texture().index()),
+        normal_texture: normal.as_ref().map(|normal| normal.texture().index()),
+        normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+        occlusion_texture: occlusion
+            .as_ref()
+            .map(|occlusion| occlusion.texture().index()),
+        occlusion_strength: occlusion
+            .as_ref()
+            .map_or(1.0, |occlusion| occlusion.strength()),
+        emissive: material.emissive_factor(),
+        emissive_texture: material
+            .emissive_texture()
+            .map(|info| info.texture().index()),
+    }
+}
+
+fn import_textures(
+    document: &gltf::Document,
+    images: &[gltf::image::Data],
+) -> Result<Vec<TextureData>> {
+    // whether a texture holds colour is only known from the materials using it
+    let mut srgb = vec![false; document.textures().len()];
+    for material in document.materials() {
+        let color_textures = [
+            material
+                .pbr_metallic_roughness()
+                .base_color_texture()
+                .map(|info| info.texture().index()),
+            material
+                .emissive_texture()
+                .map(|info| info.texture().index()),
+        ];
+        for index in color_textures.into_iter().flatten() {
+            srgb[index] = true;
+        }
+    }
+
+    document
+        .textures()
+        .map(|texture| {
+            let image = &images[texture.source().index()];
+            Ok(TextureData {
+                width: image.width,
+                height: image.height,
+                pixels: to_rgba8(image).with_context(|| {
+                    format!("unsupported format for texture {:?}", texture.name())
+                })?,
+                srgb: srgb[texture.index()],
+                sampler: import_sampler(&texture.sampler()),
+            })
+        })
+        .collect()
+}
+
+fn to_rgba8(image: &gltf::image::Data) -> Result<Vec<u8>> {
+    use gltf::image::Format;
+
+    // 16 bit channels keep their most significant byte
+    let high_bytes = |pixels: &[u8]| -> Vec<u8> {
+        pixels
+            .chunks_exact(2)
+            .map(|c| (u16::from_le_bytes([c[0], c[1]]) >> 8) as u8)
+            .collect()
+    };
+
+    let (pixels, channels) = match image.format {
+        Format::R8 => (image.pixels.clone(), 1),
+        Format::R8G8 => (image.pixels.clone(), 2),
+        Format::R8G8B8 => (image.pixels.clone(), 3),
+        Format::R8G8B8A8 => (image.pixels.clone(), 4),
+        Format::R16 => (high_bytes(&image.pixels), 1),
+        Format::R16G16 => (high_bytes(&image.pixels), 2),
+        Format::R16G16B16 => (high_bytes(&image.pixels), 3),
+        Format::R16G16B16A16 => (high_bytes(&image.pixels), 4),
+        format => anyhow::bail!("{:?}", format),
+    };
+
+    let rgba = match channels {
+        // decoded greyscale
+        1 => pixels.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+        // decoded greyscale with alpha
+        2 => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        3 => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        _ => pixels,
+    };
+
+    Ok(rgba)
+}
+
+fn import_sampler(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+
+    // textures have no mip chain, so the mipmap part of the min filter is ignored
+    let min_filter = match sampler.min_filter() {
+        Some(MinFilter::Nearest)
+        | Some(MinFilter::NearestMipmapNearest)
+        | Some(MinFilter::NearestMipmapLinear) => vk::Filter::NEAREST,
+        _ => vk::Filter::LINEAR,
+    };
+
+    SamplerDesc {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            _ => vk::Filter::LINEAR,
+        },
+        min_filter,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+    }
+}
@@ -0,0 +1,429 @@
+use anyhow::Result;
+use ash::vk;
+use egui::epaint::textures::{TextureFilter, TextureWrapMode};
+use egui::epaint::{ImageData, ImageDelta, Primitive};
+use egui::{pos2, vec2, ClippedPrimitive, Modifiers, MouseWheelUnit, PointerButton, Pos2};
+use std::collections::HashMap;
+use std::time::Instant;
+
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+use crate::texture::{SamplerDesc, Texture, TextureData};
+use crate::timeline::DeletionQueue;
+
+/// Most gui vertices drawn into a window in a frame, sizing its vertex
+/// buffers. Meshes past it are dropped
+pub const MAX_GUI_VERTICES: usize = 65536;
+
+/// Most gui indices drawn into a window in a frame
+pub const MAX_GUI_INDICES: usize = MAX_GUI_VERTICES * 3;
+
+/// Most textures egui can have at once, sizing the descriptor pool
+pub const MAX_GUI_TEXTURES: u32 = 64;
+
+/// The egui context, and the input gathered for its next frame from the
+/// window it's drawn over
+pub struct Gui {
+    pub context: egui::Context,
+    /// Whether the panels are shown and take input, until toggled
+    pub visible: bool,
+    events: Vec<egui::Event>,
+    modifiers: Modifiers,
+    /// Where the pointer is in points, `None` once it leaves the window
+    pointer: Option<Pos2>,
+    scale_factor: f32,
+    focused: bool,
+    start: Instant,
+}
+
+impl Gui {
+    pub fn new(scale_factor: f64) -> Self {
+        Gui {
+            context: egui::Context::default(),
+            visible: true,
+            events: vec![],
+            modifiers: Modifiers::default(),
+            pointer: None,
+            scale_factor: scale_factor as f32,
+            focused: true,
+            start: Instant::now(),
+        }
+    }
+
+    /// Queues an event for egui, returning whether egui took it so the rest
+    /// of the app should ignore it. Only presses are taken, so nothing is left
+    /// held down when egui lets go
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor as f32;
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = Self::modifiers(*modifiers);
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                self.events.push(egui::Event::WindowFocused(*focused));
+            }
+            _ => (),
+        }
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = pos2(position.x as f32, position.y as f32) / self.scale_factor;
+                self.pointer = Some(position);
+                self.events.push(egui::Event::PointerMoved(position));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.pointer = None;
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let (pos, button) = match (self.pointer, Self::pointer_button(*button)) {
+                    (Some(pos), Some(button)) => (pos, button),
+                    _ => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.events.push(egui::Event::PointerButton {
+                    pos,
+                    button,
+                    pressed,
+                    modifiers: self.modifiers,
+                });
+                pressed && self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (unit, delta) = match *delta {
+                    MouseScrollDelta::LineDelta(x, y) => (MouseWheelUnit::Line, vec2(x, y)),
+                    MouseScrollDelta::PixelDelta(position) => (
+                        MouseWheelUnit::Point,
+                        vec2(position.x as f32, position.y as f32) / self.scale_factor,
+                    ),
+                };
+                self.events.push(egui::Event::MouseWheel {
+                    unit,
+                    delta,
+                    modifiers: self.modifiers,
+                });
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if !c.is_control() {
+                    self.events.push(egui::Event::Text(c.to_string()));
+                }
+                false
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                let key = match Self::key(*key) {
+                    Some(key) => key,
+                    None => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.events.push(egui::Event::Key {
+                    key,
+                    physical_key: None,
+                    pressed,
+                    repeat: false,
+                    modifiers: self.modifiers,
+                });
+                pressed && self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Everything egui needs for a frame drawn into an `extent` sized
+    /// window, taking the events queued since the last one
+    pub fn take_input(&mut self, extent: vk::Extent2D) -> egui::RawInput {
+        let size = vec2(extent.width as f32, extent.height as f32) / self.scale_factor;
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(Pos2::ZERO, size)),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: self.focused,
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(input.viewport_id)
+            .or_default()
+            .native_pixels_per_point = Some(self.scale_factor);
+        input
+    }
+
+    fn modifiers(modifiers: ModifiersState) -> Modifiers {
+        Modifiers {
+            alt: modifiers.alt(),
+            ctrl: modifiers.ctrl(),
+            shift: modifiers.shift(),
+            mac_cmd: cfg!(target_os = "macos") && modifiers.logo(),
+            command: if cfg!(target_os = "macos") {
+                modifiers.logo()
+            } else {
+                modifiers.ctrl()
+            },
+        }
+    }
+
+    fn pointer_button(button: MouseButton) -> Option<PointerButton> {
+        match button {
+            MouseButton::Left => Some(PointerButton::Primary),
+            MouseButton::Right => Some(PointerButton::Secondary),
+            MouseButton::Middle => Some(PointerButton::Middle),
+            MouseButton::Other(_) => None,
+        }
+    }
+
+    /// The keys egui's widgets respond to, text itself arrives as characters
+    fn key(key: VirtualKeyCode) -> Option<egui::Key> {
+        use egui::Key;
+        Some(match key {
+            VirtualKeyCode::Left => Key::ArrowLeft,
+            VirtualKeyCode::Right => Key::ArrowRight,
+            VirtualKeyCode::Up => Key::ArrowUp,
+            VirtualKeyCode::Down => Key::ArrowDown,
+            VirtualKeyCode::Escape => Key::Escape,
+            VirtualKeyCode::Tab => Key::Tab,
+            VirtualKeyCode::Back => Key::Backspace,
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+            VirtualKeyCode::Space => Key::Space,
+            VirtualKeyCode::Insert => Key::Insert,
+            VirtualKeyCode::Delete => Key::Delete,
+            VirtualKeyCode::Home => Key::Home,
+            VirtualKeyCode::End => Key::End,
+            VirtualKeyCode::PageUp => Key::PageUp,
+            VirtualKeyCode::PageDown => Key::PageDown,
+            VirtualKeyCode::A => Key::A,
+            VirtualKeyCode::C => Key::C,
+            VirtualKeyCode::V => Key::V,
+            VirtualKeyCode::X => Key::X,
+            VirtualKeyCode::Y => Key::Y,
+            VirtualKeyCode::Z => Key::Z,
+            _ => return None,
+        })
+    }
+}
+
+/// Vertex layout of the gui pipeline, must match the inputs of gui.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GuiVertex {
+    /// In clip space
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    /// sRGB, premultiplied by alpha
+    pub color: [u8; 4],
+}
+
+impl GuiVertex {
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 8,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R8G8B8A8_UNORM,
+                offset: 16,
+            },
+        ]
+    }
+}
+
+/// The tessellated panels of a frame, ready to be laid out into a window
+pub struct GuiFrame {
+    pub primitives: Vec<ClippedPrimitive>,
+    pub pixels_per_point: f32,
+}
+
+/// One of egui's meshes, laid out in a frame's gui buffers
+pub struct GuiDraw {
+    /// In framebuffer pixels
+    pub clip: vk::Rect2D,
+    pub texture: egui::TextureId,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// Packs the meshes of a tessellated frame into one vertex and one index
+/// list, as many as fit, with positions moved from points into the clip
+/// space of an `extent` sized framebuffer
+pub fn layout(
+    primitives: &[ClippedPrimitive],
+    pixels_per_point: f32,
+    extent: vk::Extent2D,
+    vertices: &mut Vec<GuiVertex>,
+    indices: &mut Vec<u32>,
+    draws: &mut Vec<GuiDraw>,
+) {
+    let scale = [
+        2.0 * pixels_per_point / extent.width as f32,
+        2.0 * pixels_per_point / extent.height as f32,
+    ];
+    for primitive in primitives {
+        let mesh = match &primitive.primitive {
+            Primitive::Mesh(mesh) if !mesh.indices.is_empty() => mesh,
+            _ => continue,
+        };
+        if vertices.len() + mesh.vertices.len() > MAX_GUI_VERTICES
+            || indices.len() + mesh.indices.len() > MAX_GUI_INDICES
+        {
+            break;
+        }
+
+        let clip = primitive.clip_rect * pixels_per_point;
+        let min = clip.min.round();
+        let max = clip.max.round();
+        draws.push(GuiDraw {
+            clip: vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: min.x as i32,
+                    y: min.y as i32,
+                },
+                extent: vk::Extent2D {
+                    width: (max.x - min.x).max(0.0) as u32,
+                    height: (max.y - min.y).max(0.0) as u32,
+                },
+            },
+            texture: mesh.texture_id,
+            first_index: indices.len() as u32,
+            index_count: mesh.indices.len() as u32,
+            vertex_offset: vertices.len() as i32,
+        });
+        indices.extend_from_slice(&mesh.indices);
+        vertices.extend(mesh.vertices.iter().map(|vertex| GuiVertex {
+            position: [vertex.pos.x * scale[0] - 1.0, vertex.pos.y * scale[1] - 1.0],
+            uv: [vertex.uv.x, vertex.uv.y],
+            color: vertex.color.to_array(),
+        }));
+    }
+}
+
+/// A whole egui image, or a patch of one, as RGBA8 texture data
+pub fn texture_data(delta: &ImageDelta) -> TextureData {
+    let ImageData::Color(image) = &delta.image;
+    let filter = |filter| match filter {
+        TextureFilter::Nearest => vk::Filter::NEAREST,
+        TextureFilter::Linear => vk::Filter::LINEAR,
+    };
+    let address_mode = match delta.options.wrap_mode {
+        TextureWrapMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        TextureWrapMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        TextureWrapMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+    };
+    TextureData {
+        width: image.size[0] as u32,
+        height: image.size[1] as u32,
+        pixels: image
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_array())
+            .collect(),
+        // egui blends in sRGB, so its colours are read back as they're stored
+        srgb: false,
+        sampler: SamplerDesc {
+            mag_filter: filter(delta.options.magnification),
+            min_filter: filter(delta.options.minification),
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+        },
+    }
+}
+
+/// Copies `patch` into `data` with its top left corner at `pos`
+pub fn patch_texture_data(data: &mut TextureData, patch: &TextureData, pos: [usize; 2]) {
+    let row = patch.width as usize * 4;
+    for y in 0..patch.height as usize {
+        let start = ((pos[1] + y) * data.width as usize + pos[0]) * 4;
+        data.pixels[start..start + row].copy_from_slice(&patch.pixels[y * row..(y + 1) * row]);
+    }
+}
+
+/// An egui texture on the device, and what it was made from, which partial
+/// updates are patched into before it is uploaded again
+pub struct GuiTexture {
+    pub data: TextureData,
+    pub texture: Texture,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+/// egui's textures, each bound like a post source so the gui pipeline can
+/// share the post pipeline layout
+pub struct GuiRenderer {
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub textures: HashMap<egui::TextureId, GuiTexture>,
+    /// Freed textures that frames still in flight may sample
+    pub deletions: DeletionQueue<GuiTexture>,
+}
+
+impl GuiRenderer {
+    pub unsafe fn free_texture(&self, device: &ash::Device, texture: &GuiTexture) -> Result<()> {
+        texture.texture.destroy(device);
+        device.free_descriptor_sets(self.descriptor_pool, &[texture.descriptor_set])?;
+        Ok(())
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        for texture in self.textures.values().chain(self.deletions.iter()) {
+            texture.texture.destroy(device);
+        }
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.set_layout, None);
+    }
+}
+
+/// Host visible buffers a window's gui meshes are written into each frame
+pub struct GuiBuffer {
+    pub vertex_buffer: vk::Buffer,
+    pub vertex_memory: vk::DeviceMemory,
+    pub index_buffer: vk::Buffer,
+    pub index_memory: vk::DeviceMemory,
+    /// What was written this frame
+    pub draws: Vec<GuiDraw>,
+}
+
+impl GuiBuffer {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_buffer(self.vertex_buffer, None);
+        device.free_memory(self.vertex_memory, None);
+        device.destroy_buffer(self.index_buffer, None);
+        device.free_memory(self.index_memory, None);
+    }
+}
@@ -0,0 +1,236 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+    VirtualKeyCode, WindowEvent,
+};
+
+/// Snapshot of the keyboard and mouse, fed with winit events and reset by
+/// `end_frame` so the "this frame" queries cover everything since the last frame.
+#[derive(Default)]
+pub struct InputState {
+    keys_down: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    buttons_down: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    mouse_delta: (f64, f64),
+    wheel_delta: f32,
+    modifiers: ModifiersState,
+}
+
+impl InputState {
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => match state {
+                // key repeat sends further presses, only the first one counts
+                ElementState::Pressed => {
+                    if self.keys_down.insert(*key) {
+                        self.keys_pressed.insert(*key);
+                    }
+                }
+                ElementState::Released => {
+                    self.keys_down.remove(key);
+                }
+            },
+            WindowEvent::MouseInput { button, state, .. } => match state {
+                ElementState::Pressed => {
+                    self.buttons_down.insert(*button);
+                    self.buttons_pressed.insert(*button);
+                }
+                ElementState::Released => {
+                    self.buttons_down.remove(button);
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.wheel_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // roughly one line per 40 pixels of touchpad scrolling
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            // we won't see the releases once focus has gone
+            WindowEvent::Focused(false) => {
+                self.keys_down.clear();
+                self.buttons_down.clear();
+                self.modifiers = ModifiersState::empty();
+            }
+            _ => (),
+        }
+    }
+
+    /// Raw mouse motion keeps arriving while the cursor is grabbed, unlike `CursorMoved`
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta.0 += delta.0;
+            self.mouse_delta.1 += delta.1;
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.buttons_pressed.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.wheel_delta = 0.0;
+    }
+
+    pub fn key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    pub fn wheel_delta(&self) -> f32 {
+        self.wheel_delta
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+}
+
+/// Something an action can be bound to, written in the config file as
+/// `{ key = "W" }`, `{ alt_key = "Return" }` or `{ mouse = "Left" }` using
+/// winit's variant names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    /// A key while Alt is held
+    #[serde(rename = "alt_key")]
+    AltKey(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    fn down(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key) => input.key_down(key),
+            Binding::AltKey(key) => input.key_down(key) && input.modifiers().alt(),
+            Binding::Mouse(button) => input.button_down(button),
+        }
+    }
+
+    fn pressed(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key) => input.key_pressed(key),
+            Binding::AltKey(key) => input.key_pressed(key) && input.modifiers().alt(),
+            Binding::Mouse(button) => input.button_pressed(button),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ActionMapConfig {
+    bindings: HashMap<String, Vec<Binding>>,
+}
+
+/// Maps named actions like "move_forward" to any number of bindings, so
+/// application code never refers to physical keys directly. Read from a TOML
+/// file with a `[bindings]` table, e.g.
+///
+/// ```toml
+/// [bindings]
+/// move_forward = [{ key = "W" }, { key = "Up" }]
+/// look = [{ mouse = "Right" }]
+/// ```
+///
+/// Actions missing from the file keep their default bindings.
+#[derive(Debug, Deserialize)]
+#[serde(from = "ActionMapConfig")]
+pub struct ActionMap {
+    bindings: HashMap<String, Vec<Binding>>,
+}
+
+impl From<ActionMapConfig> for ActionMap {
+    fn from(config: ActionMapConfig) -> Self {
+        let mut actions = Self::default();
+        actions.bindings.extend(config.bindings);
+        actions
+    }
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        use Binding::{AltKey, Key, Mouse};
+
+        let mut actions = ActionMap {
+            bindings: HashMap::new(),
+        };
+
+        actions.bind("move_forward", Key(VirtualKeyCode::W));
+        actions.bind("move_backward", Key(VirtualKeyCode::S));
+        actions.bind("move_left", Key(VirtualKeyCode::A));
+        actions.bind("move_right", Key(VirtualKeyCode::D));
+        actions.bind("move_up", Key(VirtualKeyCode::Space));
+        actions.bind("move_down", Key(VirtualKeyCode::LShift));
+        actions.bind("look", Mouse(MouseButton::Left));
+        actions.bind("release_cursor", Key(VirtualKeyCode::Escape));
+        actions.bind("toggle_camera", Key(VirtualKeyCode::C));
+        actions.bind("toggle_bloom", Key(VirtualKeyCode::B));
+        actions.bind("toggle_tonemap", Key(VirtualKeyCode::T));
+        actions.bind("toggle_vignette", Key(VirtualKeyCode::V));
+        actions.bind("toggle_gamma", Key(VirtualKeyCode::G));
+        actions.bind("cycle_present_mode", Key(VirtualKeyCode::P));
+        actions.bind("open_window", Key(VirtualKeyCode::N));
+        actions.bind("toggle_fullscreen", AltKey(VirtualKeyCode::Return));
+        actions.bind("toggle_fullscreen", Key(VirtualKeyCode::F11));
+        actions.bind("toggle_stats", Key(VirtualKeyCode::F3));
+        actions.bind("toggle_debug_draw", Key(VirtualKeyCode::F4));
+        actions.bind("toggle_gui", Key(VirtualKeyCode::F1));
+
+        actions
+    }
+}
+
+impl ActionMap {
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.bindings.entry(action.to_owned()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    fn bindings(&self, action: &str) -> &[Binding] {
+        self.bindings.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any binding for `action` is currently held
+    pub fn down(&self, action: &str, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.down(input))
+    }
+
+    /// Whether any binding for `action` went down this frame
+    pub fn pressed(&self, action: &str, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.pressed(input))
+    }
+
+    /// 1.0, -1.0 or 0.0 depending on which of two opposing actions is held
+    pub fn axis(&self, positive: &str, negative: &str, input: &InputState) -> f32 {
+        let value = |action| if self.down(action, input) { 1.0 } else { 0.0 };
+        value(positive) - value(negative)
+    }
+}
@@ -0,0 +1,129 @@
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+
+use crate::shadow::{ShadowLayout, MAX_SHADOW_MAPS, SHADOW_CASCADES};
+
+/// Lights beyond this many are dropped, must match `MAX_LIGHTS` in shader.frag
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    /// Cone angles in radians from the spot direction, full intensity inside
+    /// `inner_cone_angle` falling off to nothing at `outer_cone_angle`
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+/// A `KHR_lights_punctual` style light. It shines down -Z of the node it is
+/// attached to; intensity is in lux for directional lights and candela otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which the light's influence reaches zero, unlimited if `None`
+    pub range: Option<f32>,
+    /// Only directional and spot lights have shadows
+    pub casts_shadows: bool,
+}
+
+/// One light as the shaders see it
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GpuLight {
+    /// xyz world position, w the light type (0 directional, 1 point, 2 spot)
+    pub position_type: [f32; 4],
+    /// xyz world direction the light shines in, w the range (0 for unlimited)
+    pub direction_range: [f32; 4],
+    /// rgb colour, a intensity
+    pub color_intensity: [f32; 4],
+    /// cosines of the inner and outer cone angles for spot lights
+    pub cone: [f32; 4],
+    /// x the first shadow map layer or -1 without shadows, y the layer count
+    pub shadow: [f32; 4],
+}
+
+impl GpuLight {
+    pub fn new(world: Matrix4<f32>, light: &Light, shadow_layer: Option<usize>) -> Self {
+        let position = world.w.truncate();
+        let direction = -world.z.truncate();
+        let direction = if direction.magnitude2() > 0.0 {
+            direction.normalize()
+        } else {
+            Vector3::new(0.0, 0.0, -1.0)
+        };
+
+        let (light_type, cone) = match light.kind {
+            LightKind::Directional => (0.0, [0.0; 4]),
+            LightKind::Point => (1.0, [0.0; 4]),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (
+                2.0,
+                [inner_cone_angle.cos(), outer_cone_angle.cos(), 0.0, 0.0],
+            ),
+        };
+
+        let [r, g, b] = light.color;
+        GpuLight {
+            position_type: position.extend(light_type).into(),
+            direction_range: direction.extend(light.range.unwrap_or(0.0)).into(),
+            color_intensity: Vector4::new(r, g, b, light.intensity).into(),
+            cone,
+            shadow: match (shadow_layer, light.kind) {
+                (None, _) => [-1.0, 0.0, 0.0, 0.0],
+                (Some(layer), LightKind::Directional) => {
+                    [layer as f32, SHADOW_CASCADES as f32, 0.0, 0.0]
+                }
+                (Some(layer), _) => [layer as f32, 1.0, 0.0, 0.0],
+            },
+        }
+    }
+}
+
+/// The per-frame light list, must match the `LightUniforms` block in shader.frag
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightUniforms {
+    pub count: u32,
+    _padding: [u32; 3],
+    pub lights: [GpuLight; MAX_LIGHTS],
+    pub shadow_matrices: [Matrix4<f32>; MAX_SHADOW_MAPS],
+    /// View space distance at which each directional light cascade ends
+    pub cascade_splits: [f32; SHADOW_CASCADES],
+}
+
+impl LightUniforms {
+    pub fn new(
+        lights: &[(Matrix4<f32>, Light)],
+        shadows: &ShadowLayout,
+        cascade_splits: [f32; SHADOW_CASCADES],
+    ) -> Self {
+        let mut uniforms = LightUniforms {
+            count: lights.len().min(MAX_LIGHTS) as u32,
+            _padding: [0; 3],
+            lights: [GpuLight::default(); MAX_LIGHTS],
+            shadow_matrices: [Matrix4::from_scale(1.0); MAX_SHADOW_MAPS],
+            cascade_splits,
+        };
+
+        for ((gpu_light, (world, light)), &shadow_layer) in uniforms
+            .lights
+            .iter_mut()
+            .zip(lights)
+            .zip(&shadows.first_layers)
+        {
+            *gpu_light = GpuLight::new(*world, light, shadow_layer);
+        }
+
+        for (matrix, &shadow_matrix) in uniforms.shadow_matrices.iter_mut().zip(&shadows.matrices) {
+            *matrix = shadow_matrix;
+        }
+
+        uniforms
+    }
+}
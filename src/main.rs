@@ -1,60 +1,282 @@
+mod camera;
+mod config;
+mod culling;
+mod debug_draw;
+mod gbuffer;
+mod gltf_import;
+mod gui;
+mod input;
+mod light;
+mod mesh;
+mod particles;
+mod post;
+mod scene;
+mod settings;
+mod shadow;
+mod text;
+mod texture;
+mod timeline;
+
 use anyhow::{Context, Result};
 
 use lazy_static::lazy_static;
 use libc::c_char;
 use std::{
     borrow::Cow,
+    collections::HashMap,
     ffi::{CStr, CString},
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
 use log::debug;
 
+use cgmath::{Deg, Matrix4, Point3, Quaternion, Rotation3, SquareMatrix, Vector3};
+
 use winit::{
-    dpi::LogicalSize,
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
     event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window, WindowBuilder, WindowId},
 };
 
 use ash::extensions::{
     ext::DebugUtils,
-    khr::{Surface, Swapchain},
+    khr::{DrawIndirectCount, Surface, Swapchain, TimelineSemaphore},
 };
 use ash::vk::{self, DebugUtilsMessengerCreateInfoEXTBuilder};
 //use ash::vk::{ApplicationInfo, StructureType};
 
+use camera::{Camera, CameraUniforms, FlyCamera, Frustum, OrbitCamera, Projection};
+use culling::{
+    CpuCullStats, CullBatch, CullBuffers, CullDraw, CullFrame, CullParams, CullStats, GpuCulling,
+};
+use debug_draw::{
+    DebugDrawPipelines, DebugLineBuffer, DebugLines, DebugVertex, MAX_DEBUG_VERTICES,
+};
+use gbuffer::{GBuffer, GBUFFER_FORMATS};
+use gltf_import::ImportedScene;
+use gui::{
+    Gui, GuiBuffer, GuiFrame, GuiRenderer, GuiTexture, GuiVertex, MAX_GUI_INDICES,
+    MAX_GUI_TEXTURES, MAX_GUI_VERTICES,
+};
+use input::{ActionMap, InputState};
+use light::{Light, LightKind, LightUniforms, MAX_LIGHTS};
+use mesh::{GpuMesh, Instance, InstanceBuffer, InstanceObject, Mesh, MeshBuffers, Vertex};
+use particles::{
+    Particle, ParticleSystem, SimulationParams, PARTICLE_COUNT, PARTICLE_WORKGROUP_SIZE,
+};
+use post::{
+    OutputSpace, PostEffect, PostProcessing, PostSettings, PostUniforms, RenderTarget, HDR_FORMAT,
+};
+use scene::{Draw, Material, MaterialId, ObjectUniforms, Scene, Transform};
+use settings::{DynamicRange, FullscreenMode, PresentMode, RenderPath, RenderSettings};
+use shadow::{ShadowLayout, ShadowMaps, ShadowPassConstants, ShadowSettings, MAX_SHADOW_MAPS};
+use text::{FontAtlas, TextRenderer, TextSettings, TextVertex, TextVertexBuffer, MAX_GLYPHS};
+use texture::{SamplerDesc, Texture, TextureData};
+use timeline::{DeletionQueue, Timeline};
+
 #[allow(dead_code)]
 struct VulkanApp {
-    //name: String,
-    window: Window,
+    name: String,
+    /// The main window comes first, closing it ends the app
+    windows: Vec<RenderWindow>,
     event_loop: Option<EventLoop<()>>,
     instance: ash::Instance,
     entry: ash::Entry,
     physical_device: vk::PhysicalDevice,
     logical_device: ash::Device,
+    queue_family_indices: QueueFamilyIndices,
     graphics_queue: vk::Queue,
     presentation_queue: vk::Queue,
+    transfer_queue: vk::Queue,
+    /// Only where the device has timeline semaphores, which then retire
+    /// frames instead of fences
+    graphics_timeline: Option<Rc<Timeline>>,
+    upload_context: UploadContext,
     debug_callback: Option<vk::DebugUtilsMessengerEXT>,
     debug_utils_loader: Option<DebugUtils>,
-    surface: vk::SurfaceKHR,
     surface_loader: Surface,
-    swapchain: vk::SwapchainKHR,
     swapchain_loader: Swapchain,
-    swapchain_extent: vk::Extent2D,
-    swapchain_format: vk::Format,
-    swapchain_images: Vec<vk::Image>,
-    swapchain_image_views: Vec<vk::ImageView>,
-    framebuffers: Vec<vk::Framebuffer>,
+    /// Only loaded when HDR is preferred and the device has VK_EXT_hdr_metadata
+    hdr_metadata: Option<vk::ExtHdrMetadataFn>,
+    depth_format: vk::Format,
+    render_settings: RenderSettings,
     render_pass: vk::RenderPass,
+    /// Shades the scene on the forward path, or fills the G-buffer on the deferred one
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+    post_settings: PostSettings,
+    text_settings: TextSettings,
+    /// `None` when the font couldn't be loaded, leaving the overlay out
+    text: Option<TextRenderer>,
+    /// The tools panels, drawn over the main window
+    gui: Gui,
+    gui_renderer: GuiRenderer,
     command_pool: vk::CommandPool,
+    particle_system: ParticleSystem,
+    /// Only created when the device can draw many draws indirectly at once,
+    /// each from any first instance
+    gpu_culling: Option<GpuCulling>,
+    debug_pipelines: DebugDrawPipelines,
+    /// Whether the scene's transforms, bounds and lights are debug drawn
+    debug_draw_scene: bool,
+    last_frame_time: Instant,
+    /// Seconds per frame, smoothed over the last few dozen for the stats
+    frame_time: f32,
+    input: InputState,
+    actions: ActionMap,
+    camera_uniforms_layout: UniformsLayout,
+    object_uniforms_layout: UniformsLayout,
+    light_uniforms_layout: UniformsLayout,
+    shadow_settings: ShadowSettings,
+    shadow_maps: ShadowMaps,
+    post_uniforms_layout: UniformsLayout,
+    scene: Scene,
+    mesh_buffers: MeshBuffers,
+    meshes: Vec<GpuMesh>,
+    materials: Vec<Material>,
+    textures: Vec<Texture>,
+    material_textures: MaterialTextures,
+    animate_demo_scene: bool,
+    /// Seconds the demo field has been animated for, wrapped to its period
+    demo_time: f32,
+}
+
+/// A window and everything needed to render into it, apart from the device
+/// and scene that all windows share. Each has its own camera, so each can
+/// show a different view.
+struct RenderWindow {
+    window: Window,
+    surface: vk::SurfaceKHR,
+    swapchain: vk::SwapchainKHR,
+    swapchain_extent: vk::Extent2D,
+    swapchain_format: vk::Format,
+    /// The colour space the output pass encodes the swapchain image for
+    output_space: OutputSpace,
+    swapchain_images: Vec<vk::Image>,
+    swapchain_image_views: Vec<vk::ImageView>,
+    depth_image: vk::Image,
+    depth_image_memory: vk::DeviceMemory,
+    depth_image_view: vk::ImageView,
+    views: Vec<vk::Rect2D>,
+    /// Only on the deferred path
+    gbuffer: Option<GBuffer>,
+    post_processing: PostProcessing,
+    camera: Camera,
+    projection: Projection,
+    camera_uniforms: PerImageUniforms,
+    object_uniforms: PerImageUniforms,
+    light_uniforms: PerImageUniforms,
+    post_uniforms: PerImageUniforms,
+    /// One per frame in flight
+    instance_buffers: Vec<InstanceBuffer>,
+    /// Present whenever the app has GPU culling
+    culling: Option<CullBuffers>,
+    /// What GPU culling left of the last frame it finished, if it culled it
+    cull_stats: Option<CullStats>,
+    /// What CPU culling left of the latest frame, if it's on
+    cpu_cull_stats: Option<CpuCullStats>,
+    /// One per frame in flight
+    text_buffers: Vec<TextVertexBuffer>,
+    /// One per frame in flight
+    debug_buffers: Vec<DebugLineBuffer>,
+    /// One per frame in flight, only filled for the main window
+    gui_buffers: Vec<GuiBuffer>,
     command_buffers: Vec<vk::CommandBuffer>,
     current_frame: usize,
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
-    in_flight_fences: Vec<vk::Fence>,
-    images_in_flight: Vec<vk::Fence>,
+    frame_sync: FrameSync,
+    /// Set while the window is minimised, when no images are acquired
+    paused: bool,
+    /// Swapchains in a row found suboptimal or out of date without the
+    /// window changing size, see `present_failed`
+    stale_presents: u32,
+    /// When the window was last found occluded, rendering only retries now
+    /// and then while it's set
+    occluded_since: Option<Instant>,
+    /// Set when the size changed since the swapchain was last created
+    resized: bool,
+    /// Where the window was and how big, to go back to when leaving
+    /// fullscreen. `None` while windowed
+    windowed_geometry: Option<(Option<PhysicalPosition<i32>>, PhysicalSize<u32>)>,
+    /// Only the focused window's camera follows the input
+    focused: bool,
+}
+
+impl RenderWindow {
+    /// Whether anything rendered to the window would be seen
+    fn shown(&self) -> bool {
+        !self.paused && self.occluded_since.is_none()
+    }
+
+    /// Destroys what's kept per swapchain image, which `recreate_swapchain`
+    /// rebuilds when the image count changes
+    unsafe fn destroy_image_resources(&self, device: &ash::Device, command_pool: vk::CommandPool) {
+        device.free_command_buffers(command_pool, &self.command_buffers);
+        for uniforms in [
+            &self.camera_uniforms,
+            &self.object_uniforms,
+            &self.light_uniforms,
+            &self.post_uniforms,
+        ] {
+            uniforms.destroy(device);
+        }
+    }
+
+    /// Destroys what `recreate_swapchain` rebuilds, but not the swapchain itself
+    unsafe fn destroy_swapchain_resources(&self, device: &ash::Device) {
+        self.post_processing.destroy(device);
+        if let Some(gbuffer) = &self.gbuffer {
+            gbuffer.destroy(device);
+        }
+
+        device.destroy_image_view(self.depth_image_view, None);
+        device.destroy_image(self.depth_image, None);
+        device.free_memory(self.depth_image_memory, None);
+
+        for &image_view in self.swapchain_image_views.iter() {
+            device.destroy_image_view(image_view, None);
+        }
+    }
+
+    /// The device must be idle, or at least done with this window's frames
+    unsafe fn destroy(
+        &self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        swapchain_loader: &Swapchain,
+        surface_loader: &Surface,
+    ) {
+        for i in 0..MAX_FRAMES_IN_FLIGHT {
+            device.destroy_semaphore(self.image_available_semaphores[i], None);
+            device.destroy_semaphore(self.render_finished_semaphores[i], None);
+        }
+        self.frame_sync.destroy(device);
+        self.destroy_image_resources(device, command_pool);
+        for instance_buffer in &self.instance_buffers {
+            instance_buffer.destroy(device);
+        }
+        if let Some(culling) = &self.culling {
+            culling.destroy(device);
+        }
+        for text_buffer in &self.text_buffers {
+            text_buffer.destroy(device);
+        }
+        for debug_buffer in &self.debug_buffers {
+            debug_buffer.destroy(device);
+        }
+        for gui_buffer in &self.gui_buffers {
+            gui_buffer.destroy(device);
+        }
+
+        self.destroy_swapchain_resources(device);
+        swapchain_loader.destroy_swapchain(self.swapchain, None);
+        surface_loader.destroy_surface(self.surface, None);
+    }
 }
 
 lazy_static! {
@@ -70,9 +292,264 @@ lazy_static! {
 
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
+/// Consecutive suboptimal or out of date swapchains, at an unchanged size,
+/// after which a window counts as occluded
+const STALE_PRESENTS_BEFORE_OCCLUDED: u32 = 3;
+
+/// How often an occluded window tries presenting again
+const OCCLUDED_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Capacity of each frame's object storage buffer
+const MAX_OBJECTS: usize = 1024;
+
+/// Capacity of each frame's instance buffer, instances past it aren't drawn
+const MAX_INSTANCES: usize = 65536;
+
+/// Cubes along each side of the demo scene's instanced field
+const DEMO_FIELD_SIZE: usize = 24;
+
+/// Seconds after which every cube of the demo field has turned a whole number
+/// of times, see `demo_field_instance`
+const DEMO_FIELD_PERIOD: f32 = 36.0;
+
+/// Textures in a material's descriptor set, see `Material::textures`
+const MATERIAL_TEXTURES: u32 = 5;
+
+/// Size of the bindless texture arrays, must match `MAX_TEXTURES` in the
+/// `BINDLESS` variants of the fragment shaders
+const MAX_BINDLESS_TEXTURES: u32 = 1024;
+
+#[derive(Clone, Copy, Default)]
 struct QueueFamilyIndices {
     graphics_family: Option<u32>,
     presentation_family: Option<u32>,
+    /// A transfer-only family (usually a DMA engine), or the graphics family if there is none
+    transfer_family: Option<u32>,
+}
+
+/// What a window waits on before reusing a frame in flight's resources, or
+/// rendering to a swapchain image again
+enum FrameSync {
+    /// A fence per frame in flight, and the fence of the frame that last
+    /// rendered to each image
+    Fences {
+        in_flight: Vec<vk::Fence>,
+        images_in_flight: Vec<vk::Fence>,
+    },
+    /// The graphics timeline's value signalled by each frame in flight's last
+    /// submission, and by the last one rendering to each image
+    Timeline {
+        timeline: Rc<Timeline>,
+        in_flight: Vec<u64>,
+        images_in_flight: Vec<u64>,
+    },
+}
+
+impl FrameSync {
+    /// Blocks until the frame's last submission has finished
+    fn wait_for_frame(&self, device: &ash::Device, frame: usize) -> Result<()> {
+        match self {
+            FrameSync::Fences { in_flight, .. } => unsafe {
+                device.wait_for_fences(&[in_flight[frame]], true, u64::MAX)?;
+            },
+            FrameSync::Timeline {
+                timeline,
+                in_flight,
+                ..
+            } => timeline.wait(device, in_flight[frame])?,
+        }
+        Ok(())
+    }
+
+    /// Blocks until whichever frame last rendered to the image has finished
+    fn wait_for_image(&self, device: &ash::Device, image: usize) -> Result<()> {
+        match self {
+            FrameSync::Fences {
+                images_in_flight, ..
+            } => {
+                if images_in_flight[image] != vk::Fence::null() {
+                    unsafe { device.wait_for_fences(&[images_in_flight[image]], true, u64::MAX)? };
+                }
+            }
+            FrameSync::Timeline {
+                timeline,
+                images_in_flight,
+                ..
+            } => timeline.wait(device, images_in_flight[image])?,
+        }
+        Ok(())
+    }
+
+    /// Forgets which frames rendered to the images, for a new swapchain
+    /// made once the device is idle
+    fn reset_images(&mut self, image_count: usize) {
+        match self {
+            FrameSync::Fences {
+                images_in_flight, ..
+            } => *images_in_flight = vec![vk::Fence::null(); image_count],
+            FrameSync::Timeline {
+                images_in_flight, ..
+            } => *images_in_flight = vec![0; image_count],
+        }
+    }
+
+    unsafe fn destroy(&self, device: &ash::Device) {
+        if let FrameSync::Fences { in_flight, .. } = self {
+            for &fence in in_flight {
+                device.destroy_fence(fence, None);
+            }
+        }
+    }
+}
+
+/// Buffer uploads are recorded on the transfer queue; when that belongs to a
+/// different family the destination buffer is then handed over to the
+/// graphics family with a release/acquire barrier pair.
+struct UploadContext {
+    transfer_family: u32,
+    transfer_command_pool: vk::CommandPool,
+    transfer_queue: vk::Queue,
+    /// The graphics timeline when the transfer queue is the graphics queue
+    transfer_timeline: Option<Rc<Timeline>>,
+    graphics_family: u32,
+    graphics_command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    graphics_timeline: Option<Rc<Timeline>>,
+}
+
+/// What `create_logical_device` enables besides the extensions
+struct DeviceFeatures {
+    core: vk::PhysicalDeviceFeatures,
+    /// See `descriptor_indexing_support`
+    descriptor_indexing: bool,
+    /// See `timeline_semaphore_support`
+    timeline_semaphore: bool,
+}
+
+/// A uniform buffer per swapchain image, each bound at binding 0 of its own
+/// descriptor set, so an image's buffer can be rewritten as soon as the
+/// previous frame rendered to that image has finished. As a storage buffer
+/// it holds an array of elements, which the shaders index.
+/// Images shared by every frame, such as shadow maps, follow at bindings 1 onwards.
+struct PerImageUniforms {
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    buffers: Vec<vk::Buffer>,
+    memories: Vec<vk::DeviceMemory>,
+}
+
+impl PerImageUniforms {
+    unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        for (&buffer, &memory) in self.buffers.iter().zip(&self.memories) {
+            device.destroy_buffer(buffer, None);
+            device.free_memory(memory, None);
+        }
+    }
+}
+
+/// The descriptor set layout of one kind of `PerImageUniforms` and what its
+/// sets hold, shared by every window's copy so they all fit the same pipelines
+struct UniformsLayout {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_type: vk::DescriptorType,
+    /// Size of each image's buffer
+    size: vk::DeviceSize,
+    /// What each descriptor sees, see `create_uniforms_layout`
+    range: vk::DeviceSize,
+    shared_images: Vec<(vk::DescriptorType, vk::DescriptorImageInfo)>,
+}
+
+impl UniformsLayout {
+    unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
+
+/// One descriptor set per material holding its textures, with a 1x1 white
+/// texture standing in for any the material doesn't have.
+struct MaterialDescriptors {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl MaterialDescriptors {
+    unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
+
+/// A single descriptor set holding every texture, each at its `TextureId`.
+/// Draws pick their material's textures by index from the object uniforms,
+/// so the set is bound once per pass rather than once per draw.
+struct BindlessTextures {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+}
+
+impl BindlessTextures {
+    unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
+
+/// How the scene pass binds material textures, set 2 of the mesh pipelines
+enum MaterialTextures {
+    Sets(MaterialDescriptors),
+    /// Where the device supports descriptor indexing
+    Bindless(BindlessTextures),
+}
+
+impl MaterialTextures {
+    unsafe fn destroy(&self, device: &ash::Device) {
+        match self {
+            MaterialTextures::Sets(descriptors) => descriptors.destroy(device),
+            MaterialTextures::Bindless(bindless) => bindless.destroy(device),
+        }
+    }
+}
+
+struct GraphicsPipelineDesc<'a> {
+    vertex_shader: &'a str,
+    /// `None` for depth only pipelines, whose render pass has no colour attachment
+    fragment_shader: Option<&'a str>,
+    set_layouts: &'a [vk::DescriptorSetLayout],
+    /// Made with `push_constant_range`, which checks they fit the device
+    push_constant_ranges: &'a [vk::PushConstantRange],
+    vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    topology: vk::PrimitiveTopology,
+    depth_test: bool,
+    /// Only with `depth_test`, which otherwise leaves the depth as it is
+    depth_write: bool,
+    /// Constant and slope scaled depth bias
+    depth_bias: Option<(f32, f32)>,
+    subpass: u32,
+    /// Colour attachments of the subpass, all written the same way
+    color_attachments: usize,
+    blend: Blend,
+}
+
+/// How a pipeline's output combines with what is already in the colour
+/// attachments
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Blend {
+    Replace,
+    Additive,
+    /// Over it, the output's colour already multiplied by its alpha
+    Premultiplied,
+}
+
+/// One subpass of a render pass, as references into the pass's attachments
+struct SubpassDesc<'a> {
+    color_attachments: &'a [vk::AttachmentReference],
+    input_attachments: &'a [vk::AttachmentReference],
+    depth_attachment: Option<vk::AttachmentReference>,
 }
 
 struct SwapChainSupportDetails {
@@ -85,6 +562,23 @@ impl QueueFamilyIndices {
     fn is_complete(&self) -> bool {
         self.graphics_family.is_some() && self.presentation_family.is_some()
     }
+
+    /// Each family may only appear in one `DeviceQueueCreateInfo`
+    fn unique_families(&self) -> Vec<u32> {
+        let mut families: Vec<u32> = [
+            self.graphics_family,
+            self.presentation_family,
+            self.transfer_family,
+        ]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+
+        families.sort_unstable();
+        families.dedup();
+        families
+    }
 }
 
 // copied from ash/examples/src/lib.rs
@@ -122,11 +616,18 @@ unsafe extern "system" fn vulkan_debug_callback(
 }
 
 impl VulkanApp {
-    pub fn new(name: &str, width: u32, height: u32) -> Result<Self> {
+    /// Shows the glTF file at `scene_path`, or a small demo scene without one
+    pub fn new(name: &str, width: u32, height: u32, scene_path: Option<&Path>) -> Result<Self> {
         let enable_validation_layer = true;
 
+        let imported = match scene_path {
+            Some(path) => gltf_import::load(path)?,
+            None => Self::create_demo_scene(),
+        };
+
         let (window, event_loop) = Self::init_window(name, (width, height), true)?;
-        let (entry, instance) = Self::create_instance(&window, enable_validation_layer)?;
+        let (entry, instance, instance_version) =
+            Self::create_instance(&window, enable_validation_layer)?;
         let (surface, surface_loader) = Self::create_surface(&entry, &instance, &window)?;
 
         let mut debug_callback = None;
@@ -143,100 +644,537 @@ impl VulkanApp {
         let queue_family_indices =
             Self::find_queue_families(&instance, physical_device, surface, &surface_loader)?;
 
-        let (logical_device, graphics_queue, presentation_queue) = Self::create_logical_device(
-            &instance,
-            physical_device,
-            enable_validation_layer,
-            &queue_family_indices,
-        )?;
+        // the version both the instance and the device were made for
+        let device_version =
+            unsafe { instance.get_physical_device_properties(physical_device) }.api_version;
+        let api_version = instance_version.min(device_version);
 
-        let (swapchain, swapchain_loader, swapchain_format, swapchain_extent) =
-            Self::create_swapchain(
+        let render_settings =
+            config::load_or_default::<RenderSettings>("config/render.toml", "render");
+
+        // HDR metadata is only a hint to the display, so it's fine without
+        let hdr_metadata_supported = render_settings.dynamic_range == DynamicRange::Hdr
+            && Self::device_supports_extension(
                 &instance,
-                &logical_device,
                 physical_device,
-                surface,
-                &surface_loader,
-                &window,
+                vk::ExtHdrMetadataFn::name(),
+            )?;
+
+        // GPU culling draws each material's draws with one indirect draw,
+        // writing each draw's first instance into its command, which not every
+        // device allows; without it the draws are always recorded directly
+        let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let gpu_culling_supported = supported_features.multi_draw_indirect == vk::TRUE
+            && supported_features.draw_indirect_first_instance == vk::TRUE;
+        let draw_indirect_count_supported = gpu_culling_supported
+            && Self::device_supports_extension(
+                &instance,
+                physical_device,
+                DrawIndirectCount::name(),
+            )?;
+
+        // one array of every texture indexed from the object uniforms, rather
+        // than a descriptor set per material
+        let descriptor_indexing = if render_settings.bindless {
+            let support =
+                Self::descriptor_indexing_support(&instance, api_version, physical_device)?;
+            // the scene's textures, plus the white and flat normal fallbacks
+            let texture_count = imported.textures.len() + 2;
+            if support.is_none() {
+                log::warn!("no bindless textures: descriptor indexing is unsupported");
+                None
+            } else if texture_count > MAX_BINDLESS_TEXTURES as usize {
+                log::warn!(
+                    "no bindless textures: {} textures, but only {} fit the arrays",
+                    texture_count,
+                    MAX_BINDLESS_TEXTURES
+                );
+                None
+            } else {
+                support
+            }
+        } else {
+            None
+        };
+        let bindless = descriptor_indexing.is_some();
+
+        // tracks each queue's progress with a counter rather than fences
+        let timeline_semaphore = if render_settings.timeline_semaphores {
+            let support =
+                Self::timeline_semaphore_support(&instance, api_version, physical_device)?;
+            if support.is_none() {
+                log::warn!("no timeline semaphores: frames are retired with fences");
+            }
+            support
+        } else {
+            None
+        };
+
+        let mut optional_extensions = vec![];
+        if descriptor_indexing == Some(true) {
+            optional_extensions.push(vk::ExtDescriptorIndexingFn::name());
+        }
+        if timeline_semaphore == Some(true) {
+            optional_extensions.push(TimelineSemaphore::name());
+        }
+        if hdr_metadata_supported {
+            optional_extensions.push(vk::ExtHdrMetadataFn::name());
+        }
+        if draw_indirect_count_supported {
+            optional_extensions.push(DrawIndirectCount::name());
+        }
+        let features = DeviceFeatures {
+            core: vk::PhysicalDeviceFeatures {
+                multi_draw_indirect: gpu_culling_supported as vk::Bool32,
+                draw_indirect_first_instance: gpu_culling_supported as vk::Bool32,
+                shader_sampled_image_array_dynamic_indexing: bindless as vk::Bool32,
+                ..Default::default()
+            },
+            descriptor_indexing: bindless,
+            timeline_semaphore: timeline_semaphore.is_some(),
+        };
+
+        let (logical_device, graphics_queue, presentation_queue, transfer_queue) =
+            Self::create_logical_device(
+                &instance,
+                physical_device,
+                enable_validation_layer,
                 &queue_family_indices,
+                &optional_extensions,
+                &features,
             )?;
 
-        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
+        let hdr_metadata = hdr_metadata_supported.then(|| {
+            vk::ExtHdrMetadataFn::load(|name| unsafe {
+                std::mem::transmute(
+                    instance.get_device_proc_addr(logical_device.handle(), name.as_ptr()),
+                )
+            })
+        });
+
+        let swapchain_loader = Swapchain::new(&instance, &logical_device);
+
+        // a timeline per queue, the transfer queue sharing the graphics
+        // queue's when they're the same queue
+        let (graphics_timeline, transfer_timeline) = match timeline_semaphore {
+            Some(needs_extension) => {
+                let create_timeline = || {
+                    Timeline::new(
+                        &logical_device,
+                        needs_extension.then(|| TimelineSemaphore::new(&entry, &instance)),
+                    )
+                    .map(Rc::new)
+                };
+                let graphics_timeline = create_timeline()?;
+                let transfer_timeline = if transfer_queue == graphics_queue {
+                    graphics_timeline.clone()
+                } else {
+                    create_timeline()?
+                };
+                (Some(graphics_timeline), Some(transfer_timeline))
+            }
+            None => (None, None),
+        };
 
-        let swapchain_image_views =
-            Self::create_image_views(&logical_device, &swapchain_images, swapchain_format)?;
+        let depth_format = Self::find_depth_format(
+            &instance,
+            physical_device,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        )?;
+
+        let render_pass = match render_settings.render_path {
+            RenderPath::Forward => Self::create_forward_render_pass(&logical_device, depth_format)?,
+            RenderPath::Deferred => {
+                Self::create_deferred_render_pass(&logical_device, depth_format)?
+            }
+        };
 
-        let render_pass = Self::create_render_pass(&logical_device, swapchain_format)?;
-        let (pipeline_layout, pipeline) =
-            Self::create_graphics_pipeline(&logical_device, render_pass, swapchain_extent)?;
+        let camera_uniforms_layout = Self::create_uniforms_layout(
+            &logical_device,
+            std::mem::size_of::<CameraUniforms>() as vk::DeviceSize,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::WHOLE_SIZE,
+            vk::ShaderStageFlags::VERTEX,
+            &[],
+        )?;
 
-        let framebuffers = Self::create_frame_buffers(
+        // every draw's object uniforms in one array, which the shaders index
+        // with each instance's object
+        let object_uniforms_layout = Self::create_uniforms_layout(
             &logical_device,
-            &swapchain_image_views,
-            &render_pass,
-            swapchain_extent,
+            (std::mem::size_of::<ObjectUniforms>() * MAX_OBJECTS) as vk::DeviceSize,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::WHOLE_SIZE,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            &[],
         )?;
 
-        let command_pool = Self::create_command_pool(&logical_device, &queue_family_indices)?;
+        let shadow_settings =
+            config::load_or_default::<ShadowSettings>("config/shadows.toml", "shadow");
+
+        let shadow_maps = Self::create_shadow_maps(
+            &instance,
+            &logical_device,
+            physical_device,
+            &shadow_settings,
+            object_uniforms_layout.descriptor_set_layout,
+        )?;
+
+        // every light's uniforms sample the same shadow map array
+        let light_uniforms_layout = Self::create_uniforms_layout(
+            &logical_device,
+            std::mem::size_of::<LightUniforms>() as vk::DeviceSize,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::WHOLE_SIZE,
+            vk::ShaderStageFlags::FRAGMENT,
+            &[
+                (
+                    vk::DescriptorType::SAMPLED_IMAGE,
+                    vk::DescriptorImageInfo {
+                        sampler: vk::Sampler::null(),
+                        image_view: shadow_maps.array_view,
+                        image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                    },
+                ),
+                (
+                    vk::DescriptorType::SAMPLER,
+                    vk::DescriptorImageInfo {
+                        sampler: shadow_maps.sampler,
+                        image_view: vk::ImageView::null(),
+                        image_layout: vk::ImageLayout::UNDEFINED,
+                    },
+                ),
+            ],
+        )?;
+
+        let material_set_layout = if bindless {
+            Self::create_bindless_set_layout(&logical_device)?
+        } else {
+            Self::create_material_set_layout(&logical_device)?
+        };
+
+        // the vertices, the instances, then the object each instance is drawn with
+        let mesh_bindings = [
+            Vertex::binding_descriptions()[0],
+            Instance::binding_descriptions()[0],
+            InstanceObject::binding_descriptions()[0],
+        ];
+        let mesh_attributes = [
+            &Vertex::attribute_descriptions()[..],
+            &Instance::attribute_descriptions(),
+            &InstanceObject::attribute_descriptions(),
+        ]
+        .concat();
+        let (pipeline_layout, pipeline) = match render_settings.render_path {
+            RenderPath::Forward => Self::create_graphics_pipeline(
+                &logical_device,
+                render_pass,
+                &GraphicsPipelineDesc {
+                    vertex_shader: "shaders/vert.spv",
+                    fragment_shader: Some(if bindless {
+                        "shaders/shader_bindless_frag.spv"
+                    } else {
+                        "shaders/frag.spv"
+                    }),
+                    set_layouts: &[
+                        camera_uniforms_layout.descriptor_set_layout,
+                        object_uniforms_layout.descriptor_set_layout,
+                        material_set_layout,
+                        light_uniforms_layout.descriptor_set_layout,
+                    ],
+                    push_constant_ranges: &[],
+                    vertex_bindings: &mesh_bindings,
+                    vertex_attributes: &mesh_attributes,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    depth_test: true,
+                    depth_write: true,
+                    depth_bias: None,
+                    subpass: 0,
+                    color_attachments: 1,
+                    blend: Blend::Replace,
+                },
+            )?,
+            // the same set numbers as the forward pipeline, minus the lights
+            RenderPath::Deferred => Self::create_graphics_pipeline(
+                &logical_device,
+                render_pass,
+                &GraphicsPipelineDesc {
+                    vertex_shader: "shaders/vert.spv",
+                    fragment_shader: Some(if bindless {
+                        "shaders/gbuffer_bindless_frag.spv"
+                    } else {
+                        "shaders/gbuffer_frag.spv"
+                    }),
+                    set_layouts: &[
+                        camera_uniforms_layout.descriptor_set_layout,
+                        object_uniforms_layout.descriptor_set_layout,
+                        material_set_layout,
+                    ],
+                    push_constant_ranges: &[],
+                    vertex_bindings: &mesh_bindings,
+                    vertex_attributes: &mesh_attributes,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    depth_test: true,
+                    depth_write: true,
+                    depth_bias: None,
+                    subpass: 0,
+                    color_attachments: 1 + GBUFFER_FORMATS.len(),
+                    blend: Blend::Replace,
+                },
+            )?,
+        };
+
+        let post_settings =
+            config::load_or_default::<PostSettings>("config/post.toml", "post-processing");
+
+        let post_uniforms_layout = Self::create_uniforms_layout(
+            &logical_device,
+            std::mem::size_of::<PostUniforms>() as vk::DeviceSize,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::WHOLE_SIZE,
+            vk::ShaderStageFlags::FRAGMENT,
+            &[],
+        )?;
+
+        // command buffers are re-recorded every frame as the scene changes
+        let command_pool = Self::create_command_pool(
+            &logical_device,
+            queue_family_indices.graphics_family.unwrap(),
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        )?;
+
+        let upload_context = UploadContext {
+            transfer_family: queue_family_indices.transfer_family.unwrap(),
+            transfer_command_pool: Self::create_command_pool(
+                &logical_device,
+                queue_family_indices.transfer_family.unwrap(),
+                vk::CommandPoolCreateFlags::TRANSIENT,
+            )?,
+            transfer_queue,
+            transfer_timeline,
+            graphics_family: queue_family_indices.graphics_family.unwrap(),
+            graphics_command_pool: command_pool,
+            graphics_queue,
+            graphics_timeline: graphics_timeline.clone(),
+        };
+
+        Self::init_shadow_map_layout(&logical_device, &upload_context, &shadow_maps)?;
 
-        let command_buffers = Self::create_command_buffers(
+        // particles and debug lines are drawn after the scene is lit
+        let lit_subpass = match render_settings.render_path {
+            RenderPath::Forward => 0,
+            RenderPath::Deferred => 1,
+        };
+        let particle_system = Self::create_particle_system(
+            &instance,
             &logical_device,
-            &command_pool,
+            physical_device,
+            &upload_context,
             render_pass,
-            &framebuffers,
-            swapchain_extent,
-            pipeline,
+            lit_subpass,
+        )?;
+        let gpu_culling = if gpu_culling_supported {
+            let draw_indirect_count = draw_indirect_count_supported
+                .then(|| DrawIndirectCount::new(&instance, &logical_device));
+            Some(Self::create_gpu_culling(
+                &logical_device,
+                draw_indirect_count,
+            )?)
+        } else {
+            log::warn!("no GPU culling: it needs multiDrawIndirect and drawIndirectFirstInstance");
+            None
+        };
+        let debug_pipelines = Self::create_debug_draw_pipelines(
+            &logical_device,
+            render_pass,
+            lit_subpass,
+            camera_uniforms_layout.descriptor_set_layout,
         )?;
 
-        let (
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        ) = Self::create_sync_objects(&logical_device, &swapchain_images)?;
+        let text_settings = config::load_or_default::<TextSettings>("config/text.toml", "text");
+        // the overlay is only for diagnostics, so can go without a font
+        let text = match FontAtlas::load(&text_settings) {
+            Ok((atlas, atlas_texture)) => Some(Self::create_text_renderer(
+                &instance,
+                &logical_device,
+                physical_device,
+                &upload_context,
+                atlas,
+                &atlas_texture,
+            )?),
+            Err(e) => {
+                log::warn!("no text overlay: {:#}", e);
+                None
+            }
+        };
 
-        let app = VulkanApp {
-            window,
+        let gui = Gui::new(window.scale_factor());
+        let gui_renderer = Self::create_gui_renderer(&logical_device)?;
+
+        let ImportedScene {
+            mut scene,
+            meshes,
+            materials,
+            textures,
+            cameras,
+        } = imported;
+
+        let light_count = scene.lights().len();
+        if light_count > MAX_LIGHTS {
+            log::warn!(
+                "only using {} of the scene's {} lights",
+                MAX_LIGHTS,
+                light_count
+            );
+        }
+
+        let (mesh_buffers, meshes) = Self::upload_meshes(
+            &instance,
+            &logical_device,
+            physical_device,
+            &upload_context,
+            &meshes,
+        )?;
+
+        // the fallback textures go last, after those the materials refer to
+        let fallback_textures = [
+            TextureData::solid([255, 255, 255, 255]),
+            TextureData::solid([128, 128, 255, 255]),
+        ];
+        let textures = textures
+            .iter()
+            .chain(fallback_textures.iter())
+            .map(|texture| {
+                Self::create_texture(
+                    &instance,
+                    &logical_device,
+                    physical_device,
+                    &upload_context,
+                    texture,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let material_textures = if bindless {
+            MaterialTextures::Bindless(Self::create_bindless_textures(
+                &logical_device,
+                material_set_layout,
+                &textures,
+            )?)
+        } else {
+            MaterialTextures::Sets(Self::create_material_descriptors(
+                &logical_device,
+                material_set_layout,
+                &materials,
+                &textures,
+            )?)
+        };
+
+        let mut projection = Projection::new(width, height);
+        let camera = match cameras.first() {
+            // start from the file's first camera, but keep the window's aspect ratio
+            Some(scene_camera) => {
+                scene.update_world_transforms();
+                let world = scene.node(scene_camera.node).world_matrix();
+                projection.fovy = cgmath::Rad(scene_camera.yfov);
+                projection.near = scene_camera.znear;
+                projection.far = scene_camera.zfar.unwrap_or(projection.far);
+                Camera::Fly(FlyCamera::looking_along(
+                    Point3::from_homogeneous(world.w),
+                    -world.z.truncate(),
+                ))
+            }
+            None => Camera::Orbit(OrbitCamera::new(Point3::new(0.0, 0.0, 0.0), 8.0, 0.0, -0.4)),
+        };
+
+        let mut app = VulkanApp {
+            name: name.to_owned(),
+            windows: vec![],
             event_loop: Some(event_loop),
             instance,
             entry,
-            debug_callback,
-            debug_utils_loader,
-            logical_device,
             physical_device,
+            logical_device,
+            queue_family_indices,
             graphics_queue,
             presentation_queue,
-            surface,
+            transfer_queue,
+            graphics_timeline,
+            upload_context,
+            debug_callback,
+            debug_utils_loader,
             surface_loader,
-            swapchain,
-            swapchain_images,
             swapchain_loader,
-            swapchain_extent,
-            swapchain_format,
-            swapchain_image_views,
-            framebuffers,
+            hdr_metadata,
+            depth_format,
+            render_settings,
             render_pass,
             pipeline_layout,
             pipeline,
+            post_settings,
+            text_settings,
+            text,
+            gui,
+            gui_renderer,
             command_pool,
-            command_buffers,
-            current_frame: 0,
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
+            particle_system,
+            gpu_culling,
+            debug_pipelines,
+            debug_draw_scene: false,
+            last_frame_time: Instant::now(),
+            frame_time: 1.0 / 60.0,
+            input: InputState::default(),
+            actions: config::load_or_default::<ActionMap>("config/input.toml", "input"),
+            camera_uniforms_layout,
+            object_uniforms_layout,
+            light_uniforms_layout,
+            shadow_settings,
+            shadow_maps,
+            post_uniforms_layout,
+            scene,
+            mesh_buffers,
+            meshes,
+            materials,
+            textures,
+            material_textures,
+            animate_demo_scene: scene_path.is_none(),
+            demo_time: 0.0,
         };
+
+        let main_window = app.create_render_window(window, surface, camera, projection)?;
+        app.windows.push(main_window);
+        if app.render_settings.start_fullscreen {
+            Self::toggle_fullscreen(&mut app.windows[0], &app.render_settings);
+        }
+
         Ok(app)
     }
 
     pub fn run(mut self) -> Result<()> {
-        let id = self.window.id();
+        let main_id = self.windows[0].window.id();
         if let Some(event_loop) = self.event_loop.take() {
-            event_loop.run(move |event, _, control_flow| {
-                *control_flow = ControlFlow::Wait;
+            event_loop.run(move |event, target, control_flow| {
+                // keep rendering between events, the scene animates, unless
+                // there's nothing to render to
+                *control_flow = if self.windows.iter().any(RenderWindow::shown) {
+                    ControlFlow::Poll
+                } else {
+                    match self
+                        .windows
+                        .iter()
+                        .filter_map(|window| window.occluded_since)
+                        .min()
+                    {
+                        Some(since) => ControlFlow::WaitUntil(since + OCCLUDED_RETRY_INTERVAL),
+                        None => ControlFlow::Wait,
+                    }
+                };
 
                 match event {
                     Event::MainEventsCleared => {
+                        if self.actions.pressed("open_window", &self.input) {
+                            self.open_window(target).expect("failed opening window");
+                        }
                         self.draw_frame().expect("failed drawing frame");
                     }
 
@@ -244,418 +1182,4603 @@ impl VulkanApp {
                         event: WindowEvent::CloseRequested,
                         window_id,
                     } => {
-                        if window_id == id {
+                        if window_id == main_id {
                             *control_flow = ControlFlow::Exit
+                        } else {
+                            self.close_window(window_id).expect("failed closing window");
                         }
                     }
-                    _ => (),
-                }
-            });
-        } else {
-            anyhow::bail!("event loop uninitialised")
+
+                    Event::WindowEvent {
+                        event: WindowEvent::Resized(size),
+                        window_id,
+                    } => {
+                        if let Some(window) = self.window_mut(window_id) {
+                            if size.width == 0 || size.height == 0 {
+                                window.paused = true;
+                            } else {
+                                window.resized = true;
+                                window.projection.resize(size.width, size.height);
+                            }
+                        }
+                    }
+
+                    Event::WindowEvent { event, window_id } => {
+                        // the gui is only over the main window, and what it
+                        // takes isn't for the cameras
+                        let gui_took = window_id == main_id && self.gui.handle_window_event(&event);
+                        if let Some(window) = self.window_mut(window_id) {
+                            if let WindowEvent::Focused(focused) = event {
+                                window.focused = focused;
+                                if !focused {
+                                    window.camera.release_cursor(&window.window);
+                                }
+                            }
+                            if !gui_took {
+                                self.input.handle_window_event(&event)
+                            }
+                        }
+                    }
+
+                    Event::DeviceEvent { event, .. } => self.input.handle_device_event(&event),
+
+                    _ => (),
+                }
+            });
+        } else {
+            anyhow::bail!("event loop uninitialised")
+        }
+    }
+
+    fn window_mut(&mut self, id: WindowId) -> Option<&mut RenderWindow> {
+        self.windows
+            .iter_mut()
+            .find(|window| window.window.id() == id)
+    }
+
+    /// Opens another window onto the scene, orbiting it a quarter turn further
+    /// round than the last one opened
+    fn open_window(&mut self, target: &EventLoopWindowTarget<()>) -> Result<()> {
+        let size = self.windows[0].window.inner_size();
+        let window = WindowBuilder::new()
+            .with_title(format!("{} {}", self.name, self.windows.len() + 1))
+            .with_inner_size(size)
+            .build(target)
+            .context("Failed to create window")?;
+        let surface =
+            unsafe { ash_window::create_surface(&self.entry, &self.instance, &window, None)? };
+
+        let yaw = self.windows.len() as f32 * std::f32::consts::FRAC_PI_2;
+        let camera = Camera::Orbit(OrbitCamera::new(Point3::new(0.0, 0.0, 0.0), 8.0, yaw, -0.4));
+        let projection = Projection::new(size.width, size.height);
+
+        let render_window = self.create_render_window(window, surface, camera, projection)?;
+        self.windows.push(render_window);
+        Ok(())
+    }
+
+    /// Switches a window between windowed and the configured fullscreen mode,
+    /// putting it back where it was on the way out
+    fn toggle_fullscreen(window: &mut RenderWindow, settings: &RenderSettings) {
+        if let Some((position, size)) = window.windowed_geometry.take() {
+            window.window.set_fullscreen(None);
+            if let Some(position) = position {
+                window.window.set_outer_position(position);
+            }
+            window.window.set_inner_size(size);
+            log::info!("Windowed");
+            return;
+        }
+
+        let Some(monitor) = Self::fullscreen_monitor(&window.window, settings.monitor) else {
+            log::warn!("No monitor to go fullscreen on");
+            return;
+        };
+        window.windowed_geometry = Some((
+            window.window.outer_position().ok(),
+            window.window.inner_size(),
+        ));
+
+        let exclusive = match settings.fullscreen {
+            FullscreenMode::Borderless => None,
+            FullscreenMode::Exclusive => {
+                let mode = Self::choose_video_mode(&monitor, settings);
+                if mode.is_none() {
+                    log::warn!(
+                        "{} has no {:?} video mode at {:?} Hz, going borderless instead",
+                        monitor.name().unwrap_or_default(),
+                        settings.resolution,
+                        settings.refresh_rate
+                    );
+                }
+                mode
+            }
+        };
+        match exclusive {
+            Some(mode) => {
+                log::info!(
+                    "Exclusive fullscreen on {} at {}x{} {} Hz",
+                    monitor.name().unwrap_or_default(),
+                    mode.size().width,
+                    mode.size().height,
+                    mode.refresh_rate()
+                );
+                window
+                    .window
+                    .set_fullscreen(Some(Fullscreen::Exclusive(mode)));
+            }
+            None => {
+                log::info!(
+                    "Borderless fullscreen on {}",
+                    monitor.name().unwrap_or_default()
+                );
+                window
+                    .window
+                    .set_fullscreen(Some(Fullscreen::Borderless(Some(monitor))));
+            }
+        }
+    }
+
+    /// The configured monitor, or the one the window is on
+    fn fullscreen_monitor(window: &Window, index: Option<usize>) -> Option<MonitorHandle> {
+        if let Some(index) = index {
+            match window.available_monitors().nth(index) {
+                Some(monitor) => return Some(monitor),
+                None => log::warn!("No monitor {}, using the window's own", index),
+            }
+        }
+        window
+            .current_monitor()
+            .or_else(|| window.primary_monitor())
+    }
+
+    /// The monitor's video mode matching the configured resolution and refresh
+    /// rate, the largest and fastest of them for whichever is left out
+    fn choose_video_mode(monitor: &MonitorHandle, settings: &RenderSettings) -> Option<VideoMode> {
+        monitor
+            .video_modes()
+            .filter(|mode| {
+                settings
+                    .resolution
+                    .is_none_or(|[width, height]| mode.size() == PhysicalSize::new(width, height))
+            })
+            .filter(|mode| {
+                settings
+                    .refresh_rate
+                    .is_none_or(|rate| mode.refresh_rate() == rate)
+            })
+            .max_by_key(|mode| {
+                (
+                    mode.size().width * mode.size().height,
+                    mode.refresh_rate(),
+                    mode.bit_depth(),
+                )
+            })
+    }
+
+    fn close_window(&mut self, id: WindowId) -> Result<()> {
+        if let Some(index) = self
+            .windows
+            .iter()
+            .position(|window| window.window.id() == id)
+        {
+            unsafe {
+                self.logical_device.device_wait_idle()?;
+                self.windows.remove(index).destroy(
+                    &self.logical_device,
+                    self.command_pool,
+                    &self.swapchain_loader,
+                    &self.surface_loader,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the swapchain and per-image resources for a window whose surface
+    /// has already been created
+    fn create_render_window(
+        &self,
+        window: Window,
+        surface: vk::SurfaceKHR,
+        camera: Camera,
+        mut projection: Projection,
+    ) -> Result<RenderWindow> {
+        let presentation_family = self.queue_family_indices.presentation_family.unwrap();
+        let supported = unsafe {
+            self.surface_loader.get_physical_device_surface_support(
+                self.physical_device,
+                presentation_family,
+                surface,
+            )?
+        };
+        if !supported {
+            anyhow::bail!("the presentation queue can't present to the new window");
+        }
+
+        let (swapchain, swapchain_format, output_space, swapchain_extent) = Self::create_swapchain(
+            self.physical_device,
+            surface,
+            &self.surface_loader,
+            &self.swapchain_loader,
+            &window,
+            &self.queue_family_indices,
+            &self.render_settings,
+            vk::SwapchainKHR::null(),
+        )?;
+        log::info!("Presenting {:?}", output_space);
+        self.set_swapchain_hdr_metadata(swapchain, output_space);
+
+        let swapchain_images = unsafe { self.swapchain_loader.get_swapchain_images(swapchain)? };
+        let image_count = swapchain_images.len();
+
+        let swapchain_image_views =
+            Self::create_image_views(&self.logical_device, &swapchain_images, swapchain_format)?;
+
+        let (depth_image, depth_image_memory, depth_image_view, gbuffer, post_processing) = self
+            .create_extent_resources(swapchain_format, &swapchain_image_views, swapchain_extent)?;
+
+        let instance_buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                let (buffer, memory) = Self::create_buffer(
+                    &self.instance,
+                    &self.logical_device,
+                    self.physical_device,
+                    (MAX_INSTANCES * std::mem::size_of::<Instance>()) as vk::DeviceSize,
+                    // also read by the culling pass
+                    vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )?;
+                let (object_buffer, object_memory) = Self::create_buffer(
+                    &self.instance,
+                    &self.logical_device,
+                    self.physical_device,
+                    (MAX_INSTANCES * std::mem::size_of::<InstanceObject>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )?;
+                Ok(InstanceBuffer {
+                    buffer,
+                    memory,
+                    object_buffer,
+                    object_memory,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let culling = self
+            .gpu_culling
+            .as_ref()
+            .map(|gpu_culling| self.create_cull_buffers(gpu_culling, &instance_buffers))
+            .transpose()?;
+
+        let text_buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                let (buffer, memory) = Self::create_buffer(
+                    &self.instance,
+                    &self.logical_device,
+                    self.physical_device,
+                    (MAX_GLYPHS * 6 * std::mem::size_of::<TextVertex>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )?;
+                Ok(TextVertexBuffer {
+                    buffer,
+                    memory,
+                    vertex_count: 0,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let debug_buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                let (buffer, memory) = Self::create_buffer(
+                    &self.instance,
+                    &self.logical_device,
+                    self.physical_device,
+                    (MAX_DEBUG_VERTICES * std::mem::size_of::<DebugVertex>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )?;
+                Ok(DebugLineBuffer {
+                    buffer,
+                    memory,
+                    depth_tested_count: 0,
+                    overlay_count: 0,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let gui_buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                let create_buffer = |size, usage| {
+                    Self::create_buffer(
+                        &self.instance,
+                        &self.logical_device,
+                        self.physical_device,
+                        size as vk::DeviceSize,
+                        usage,
+                        vk::MemoryPropertyFlags::HOST_VISIBLE
+                            | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    )
+                };
+                let (vertex_buffer, vertex_memory) = create_buffer(
+                    MAX_GUI_VERTICES * std::mem::size_of::<GuiVertex>(),
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                )?;
+                let (index_buffer, index_memory) = create_buffer(
+                    MAX_GUI_INDICES * std::mem::size_of::<u32>(),
+                    vk::BufferUsageFlags::INDEX_BUFFER,
+                )?;
+                Ok(GuiBuffer {
+                    vertex_buffer,
+                    vertex_memory,
+                    index_buffer,
+                    index_memory,
+                    draws: vec![],
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let command_buffers =
+            Self::create_command_buffers(&self.logical_device, &self.command_pool, image_count)?;
+
+        let (image_available_semaphores, render_finished_semaphores, frame_sync) =
+            Self::create_sync_objects(
+                &self.logical_device,
+                &swapchain_images,
+                self.graphics_timeline.as_ref(),
+            )?;
+
+        projection.resize(swapchain_extent.width, swapchain_extent.height);
+
+        Ok(RenderWindow {
+            window,
+            surface,
+            swapchain,
+            swapchain_extent,
+            swapchain_format,
+            output_space,
+            swapchain_images,
+            swapchain_image_views,
+            depth_image,
+            depth_image_memory,
+            depth_image_view,
+            views: vec![Self::full_extent(swapchain_extent)],
+            gbuffer,
+            post_processing,
+            camera,
+            projection,
+            camera_uniforms: self
+                .create_image_uniforms(&self.camera_uniforms_layout, image_count)?,
+            object_uniforms: self
+                .create_image_uniforms(&self.object_uniforms_layout, image_count)?,
+            light_uniforms: self.create_image_uniforms(&self.light_uniforms_layout, image_count)?,
+            post_uniforms: self.create_image_uniforms(&self.post_uniforms_layout, image_count)?,
+            instance_buffers,
+            culling,
+            cull_stats: None,
+            cpu_cull_stats: None,
+            text_buffers,
+            debug_buffers,
+            gui_buffers,
+            command_buffers,
+            current_frame: 0,
+            image_available_semaphores,
+            render_finished_semaphores,
+            frame_sync,
+            paused: false,
+            stale_presents: 0,
+            occluded_since: None,
+            resized: false,
+            windowed_geometry: None,
+            focused: true,
+        })
+    }
+
+    /// The depth image, G-buffer and post-processing targets, which are all
+    /// the size of the swapchain
+    fn create_extent_resources(
+        &self,
+        swapchain_format: vk::Format,
+        swapchain_image_views: &[vk::ImageView],
+        extent: vk::Extent2D,
+    ) -> Result<(
+        vk::Image,
+        vk::DeviceMemory,
+        vk::ImageView,
+        Option<GBuffer>,
+        PostProcessing,
+    )> {
+        let (depth_image, depth_image_memory, depth_image_view) = Self::create_depth_resources(
+            &self.instance,
+            &self.logical_device,
+            self.physical_device,
+            self.depth_format,
+            extent,
+        )?;
+
+        let gbuffer = match self.render_settings.render_path {
+            RenderPath::Forward => None,
+            RenderPath::Deferred => Some(Self::create_gbuffer(
+                &self.instance,
+                &self.logical_device,
+                self.physical_device,
+                extent,
+                depth_image_view,
+                self.render_pass,
+                self.camera_uniforms_layout.descriptor_set_layout,
+                self.light_uniforms_layout.descriptor_set_layout,
+            )?),
+        };
+
+        // the scene's colour target shares its framebuffer with these
+        let scene_attachments: Vec<vk::ImageView> = std::iter::once(depth_image_view)
+            .chain(
+                gbuffer
+                    .iter()
+                    .flat_map(|gbuffer| gbuffer.views.iter().copied()),
+            )
+            .collect();
+
+        let post_processing = Self::create_post_processing(
+            &self.instance,
+            &self.logical_device,
+            self.physical_device,
+            swapchain_format,
+            swapchain_image_views,
+            extent,
+            &self.post_settings,
+            self.render_pass,
+            &scene_attachments,
+            self.post_uniforms_layout.descriptor_set_layout,
+        )?;
+
+        Ok((
+            depth_image,
+            depth_image_memory,
+            depth_image_view,
+            gbuffer,
+            post_processing,
+        ))
+    }
+
+    fn set_swapchain_hdr_metadata(&self, swapchain: vk::SwapchainKHR, output_space: OutputSpace) {
+        if let Some(hdr_metadata) = &self.hdr_metadata {
+            Self::set_hdr_metadata(
+                &self.logical_device,
+                hdr_metadata,
+                swapchain,
+                output_space,
+                &self.render_settings,
+            );
+        }
+    }
+
+    fn draw_frame(&mut self) -> Result<()> {
+        let present_mode = self.render_settings.present_mode;
+        if self.actions.pressed("cycle_present_mode", &self.input) {
+            self.render_settings.present_mode = self.render_settings.present_mode.next();
+            log::info!("Preferring {:?}", self.render_settings.present_mode);
+        }
+
+        if self.actions.pressed("toggle_fullscreen", &self.input) {
+            if let Some(window) = self.windows.iter_mut().find(|window| window.focused) {
+                Self::toggle_fullscreen(window, &self.render_settings);
+            }
+        }
+
+        if self.actions.pressed("toggle_stats", &self.input) {
+            self.text_settings.show_stats = !self.text_settings.show_stats;
+        }
+
+        if self.actions.pressed("toggle_gui", &self.input) {
+            self.gui.visible = !self.gui.visible;
+        }
+
+        let now = Instant::now();
+        // nothing moves while no window shows it, so a window coming back
+        // carries on from where it was hidden
+        if !self.windows.iter().any(RenderWindow::shown) {
+            self.last_frame_time = now;
+        }
+        let elapsed = (now - self.last_frame_time).as_secs_f32();
+        // clamp so a long stall (e.g. dragging the window) doesn't fling everything
+        let delta_time = elapsed.min(0.1);
+        self.last_frame_time = now;
+        self.frame_time += (elapsed - self.frame_time) * 0.05;
+
+        self.post_settings.update(&self.input, &self.actions);
+
+        if self.animate_demo_scene {
+            self.animate_scene(delta_time);
+        }
+        self.scene.update_world_transforms();
+        // every node, culled by each window against its own camera below
+        let (draws, _) = self.scene.draws(None, &self.meshes);
+
+        if self.actions.pressed("toggle_debug_draw", &self.input) {
+            self.debug_draw_scene = !self.debug_draw_scene;
+        }
+        if self.debug_draw_scene {
+            self.debug_draw_scene(&draws);
+        }
+        // everything drawn this frame, which every window shows
+        let debug_lines = debug_draw::take();
+
+        let gui = self.run_gui(draws.len())?;
+        let present_mode_changed = self.render_settings.present_mode != present_mode;
+
+        // taken out so each window can change while the rest of the app draws it
+        let mut windows = std::mem::take(&mut self.windows);
+        let result = windows.iter_mut().enumerate().try_for_each(|(i, window)| {
+            // an occluded window only tries presenting again now and then
+            if let Some(since) = window.occluded_since {
+                if since.elapsed() < OCCLUDED_RETRY_INTERVAL {
+                    return Ok(());
+                }
+                window.occluded_since = None;
+                window.resized = true;
+            }
+            // any event might be a minimised window coming back
+            if present_mode_changed || window.paused || window.resized {
+                window.resized = false;
+                self.recreate_swapchain(window)?;
+            }
+            if window.paused {
+                return Ok(());
+            }
+
+            if window.focused {
+                window
+                    .camera
+                    .update(&self.input, &self.actions, &window.window, delta_time);
+            }
+
+            let frustum = self
+                .render_settings
+                .cpu_culling
+                .then(|| Self::view_frustum(window));
+            let (draws, cpu_cull_stats) = self.scene.draws(frustum.as_ref(), &self.meshes);
+            window.cpu_cull_stats = cpu_cull_stats;
+
+            // the particles are stepped once a frame, and the gui drawn, by
+            // the main window
+            let main = i == 0;
+            self.draw_window(
+                window,
+                &draws,
+                &debug_lines,
+                gui.as_ref().filter(|_| main),
+                main.then_some(delta_time),
+            )
+        });
+        self.windows = windows;
+
+        self.input.end_frame();
+        result
+    }
+
+    /// Renders and presents one window's frame, stepping the particle
+    /// simulation first if given a time step
+    fn draw_window(
+        &self,
+        window: &mut RenderWindow,
+        draws: &[Draw],
+        debug_lines: &DebugLines,
+        gui: Option<&GuiFrame>,
+        simulate: Option<f32>,
+    ) -> Result<()> {
+        window
+            .frame_sync
+            .wait_for_frame(&self.logical_device, window.current_frame)?;
+        window.cull_stats = self.read_cull_stats(window)?;
+
+        let acquired = unsafe {
+            self.swapchain_loader.acquire_next_image(
+                window.swapchain,
+                u64::MAX,
+                window.image_available_semaphores[window.current_frame],
+                vk::Fence::null(),
+            )
+        };
+        let image_index = match acquired {
+            Ok((image_index, _)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return self.present_failed(window),
+            Err(e) => return Err(e.into()),
+        };
+
+        window
+            .frame_sync
+            .wait_for_image(&self.logical_device, image_index as usize)?;
+
+        if let Some(delta_time) = simulate {
+            self.update_simulation_params(window.current_frame, delta_time)?;
+        }
+        self.update_camera_uniforms(window, image_index as usize)?;
+        self.update_object_uniforms(window, image_index as usize, draws)?;
+        self.update_instances(window, draws)?;
+        self.update_cull_buffers(window, draws)?;
+        let shadow_matrices = self.update_light_uniforms(window, image_index as usize)?;
+        self.update_post_uniforms(window, image_index as usize)?;
+        window.text_buffers[window.current_frame].vertex_count =
+            self.update_text(window, draws.len())?;
+        self.update_debug_lines(window, debug_lines)?;
+        self.update_gui(window, gui)?;
+        self.record_command_buffer(
+            window,
+            image_index as usize,
+            draws,
+            &shadow_matrices,
+            simulate.is_some(),
+        )?;
+
+        let wait_semaphores = [window.image_available_semaphores[window.current_frame]];
+
+        let signal_semaphores = [window.render_finished_semaphores[window.current_frame]];
+
+        let command_buffers = [window.command_buffers[image_index as usize]];
+
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
+            .command_buffers(&command_buffers);
+
+        match &mut window.frame_sync {
+            FrameSync::Fences {
+                in_flight,
+                images_in_flight,
+            } => {
+                let fence = in_flight[window.current_frame];
+                images_in_flight[image_index as usize] = fence;
+                let submit_info = submit_info.signal_semaphores(&signal_semaphores);
+                unsafe {
+                    self.logical_device.reset_fences(&[fence])?;
+                    self.logical_device.queue_submit(
+                        self.graphics_queue,
+                        &[*submit_info],
+                        fence,
+                    )?;
+                }
+            }
+            // the binary semaphore is still what presentation waits on, its
+            // value is ignored
+            FrameSync::Timeline {
+                timeline,
+                in_flight,
+                images_in_flight,
+            } => {
+                let value = timeline.next_value();
+                in_flight[window.current_frame] = value;
+                images_in_flight[image_index as usize] = value;
+                let signal_semaphores = [signal_semaphores[0], timeline.semaphore];
+                let signal_values = [0, value];
+                let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+                    .wait_semaphore_values(&[0])
+                    .signal_semaphore_values(&signal_values);
+                let submit_info = submit_info
+                    .signal_semaphores(&signal_semaphores)
+                    .push_next(&mut timeline_info);
+                unsafe {
+                    self.logical_device.queue_submit(
+                        self.graphics_queue,
+                        &[*submit_info],
+                        vk::Fence::null(),
+                    )?;
+                }
+            }
+        }
+
+        let swapchains = [window.swapchain];
+
+        let image_indices = [image_index];
+
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+
+        let presented = unsafe {
+            self.swapchain_loader
+                .queue_present(self.presentation_queue, &present_info)
+        };
+
+        window.current_frame = (window.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+
+        match presented {
+            Ok(false) => {
+                if window.stale_presents >= STALE_PRESENTS_BEFORE_OCCLUDED {
+                    log::info!("Window visible again, resuming");
+                }
+                window.stale_presents = 0;
+                Ok(())
+            }
+            // suboptimal or out of date, the surface changed under the swapchain
+            // or the window is covered
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.present_failed(window),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces a window's swapchain after it was found suboptimal or out of
+    /// date. The window system sends no event when a window is covered, but
+    /// some platforms keep failing presents while it is, without it changing
+    /// size, so that is the signal; after a few times in a row the window
+    /// counts as occluded and stops rendering, besides the odd retry.
+    fn present_failed(&self, window: &mut RenderWindow) -> Result<()> {
+        let extent = window.swapchain_extent;
+        self.recreate_swapchain(window)?;
+        if window.paused || window.swapchain_extent != extent {
+            window.stale_presents = 0;
+            return Ok(());
+        }
+
+        window.stale_presents += 1;
+        if window.stale_presents == STALE_PRESENTS_BEFORE_OCCLUDED {
+            log::info!("Window occluded, pausing");
+        }
+        if window.stale_presents >= STALE_PRESENTS_BEFORE_OCCLUDED {
+            window.occluded_since = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Replaces a window's swapchain, after the surface changed or to switch
+    /// present mode, along with the images sized to it, and everything kept
+    /// per swapchain image if the new one has a different number of images.
+    ///
+    /// A minimised window has no area to make a swapchain for, so this pauses
+    /// rendering instead until a later call finds it restored.
+    fn recreate_swapchain(&self, window: &mut RenderWindow) -> Result<()> {
+        let support_details = Self::query_swap_chain_support(
+            self.physical_device,
+            window.surface,
+            &self.surface_loader,
+        )?;
+        let extent = Self::choose_swap_extent(support_details.capabilities, &window.window)?;
+        if extent.width == 0 || extent.height == 0 {
+            if !window.paused {
+                log::info!("Window minimised, pausing");
+                window.paused = true;
+            }
+            return Ok(());
+        }
+        if window.paused {
+            log::info!("Window restored, resuming");
+            window.paused = false;
+        }
+
+        unsafe { self.logical_device.device_wait_idle()? };
+
+        let (swapchain, swapchain_format, output_space, swapchain_extent) = Self::create_swapchain(
+            self.physical_device,
+            window.surface,
+            &self.surface_loader,
+            &self.swapchain_loader,
+            &window.window,
+            &self.queue_family_indices,
+            &self.render_settings,
+            window.swapchain,
+        )?;
+
+        unsafe {
+            window.destroy_swapchain_resources(&self.logical_device);
+            self.swapchain_loader
+                .destroy_swapchain(window.swapchain, None);
+        }
+        window.swapchain = swapchain;
+        window.swapchain_format = swapchain_format;
+        window.output_space = output_space;
+        window.swapchain_extent = swapchain_extent;
+
+        window.swapchain_images = unsafe { self.swapchain_loader.get_swapchain_images(swapchain)? };
+        // some drivers give one present mode more images than another
+        let image_count = window.swapchain_images.len();
+        if image_count != window.command_buffers.len() {
+            log::info!(
+                "Swapchain went from {} to {} images",
+                window.command_buffers.len(),
+                image_count
+            );
+            unsafe { window.destroy_image_resources(&self.logical_device, self.command_pool) };
+            window.command_buffers = Self::create_command_buffers(
+                &self.logical_device,
+                &self.command_pool,
+                image_count,
+            )?;
+            window.camera_uniforms =
+                self.create_image_uniforms(&self.camera_uniforms_layout, image_count)?;
+            window.object_uniforms =
+                self.create_image_uniforms(&self.object_uniforms_layout, image_count)?;
+            window.light_uniforms =
+                self.create_image_uniforms(&self.light_uniforms_layout, image_count)?;
+            window.post_uniforms =
+                self.create_image_uniforms(&self.post_uniforms_layout, image_count)?;
+        }
+        window.frame_sync.reset_images(image_count);
+
+        self.set_swapchain_hdr_metadata(swapchain, output_space);
+
+        window.swapchain_image_views = Self::create_image_views(
+            &self.logical_device,
+            &window.swapchain_images,
+            swapchain_format,
+        )?;
+
+        (
+            window.depth_image,
+            window.depth_image_memory,
+            window.depth_image_view,
+            window.gbuffer,
+            window.post_processing,
+        ) = self.create_extent_resources(
+            swapchain_format,
+            &window.swapchain_image_views,
+            swapchain_extent,
+        )?;
+
+        window.views = vec![Self::full_extent(swapchain_extent)];
+        window
+            .projection
+            .resize(swapchain_extent.width, swapchain_extent.height);
+
+        Ok(())
+    }
+
+    fn update_simulation_params(&self, frame: usize, delta_time: f32) -> Result<()> {
+        let params = SimulationParams {
+            delta_time,
+            particle_count: PARTICLE_COUNT,
+        };
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                self.particle_system.param_buffer_memories[frame],
+                std::slice::from_ref(&params),
+            )
+        }
+    }
+
+    fn update_camera_uniforms(&self, window: &RenderWindow, image_index: usize) -> Result<()> {
+        let view = window.camera.view_matrix();
+        let proj = window.projection.matrix();
+        let uniforms = CameraUniforms {
+            view,
+            proj,
+            position: window.camera.position().to_homogeneous(),
+            inverse_view_proj: (proj * view).invert().unwrap_or_else(Matrix4::identity),
+        };
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                window.camera_uniforms.memories[image_index],
+                std::slice::from_ref(&uniforms),
+            )
+        }
+    }
+
+    fn update_post_uniforms(&self, window: &RenderWindow, image_index: usize) -> Result<()> {
+        let uniforms = PostUniforms::new(
+            &self.post_settings,
+            window.output_space,
+            &self.render_settings,
+        );
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                window.post_uniforms.memories[image_index],
+                std::slice::from_ref(&uniforms),
+            )
+        }
+    }
+
+    /// Copies the frame's debug lines into the window's buffer for it, as many
+    /// as fit
+    fn update_debug_lines(&self, window: &mut RenderWindow, lines: &DebugLines) -> Result<()> {
+        let depth_tested = &lines.depth_tested[..lines.depth_tested.len().min(MAX_DEBUG_VERTICES)];
+        let overlay = &lines.overlay[..lines
+            .overlay
+            .len()
+            .min(MAX_DEBUG_VERTICES - depth_tested.len())];
+
+        let debug_buffer = &mut window.debug_buffers[window.current_frame];
+        debug_buffer.depth_tested_count = depth_tested.len() as u32;
+        debug_buffer.overlay_count = overlay.len() as u32;
+        if depth_tested.is_empty() && overlay.is_empty() {
+            return Ok(());
+        }
+
+        let vertices: Vec<DebugVertex> = depth_tested.iter().chain(overlay).copied().collect();
+        unsafe { Self::write_to_memory(&self.logical_device, debug_buffer.memory, &vertices) }
+    }
+
+    /// Queues the axes and bounds of every draw, where the lights are and how
+    /// far they reach, and the volumes the main window's shadow maps cover
+    fn debug_draw_scene(&self, draws: &[Draw]) {
+        let instances = self.scene.instances();
+        for draw in draws {
+            debug_draw::axes(draw.world, 0.5, false);
+            let first = draw.first_instance as usize;
+            for instance in &instances[first..first + draw.instance_count as usize] {
+                debug_draw::aabb(
+                    &self.meshes[draw.mesh]
+                        .bounds
+                        .transformed(draw.world * instance.model),
+                    [1.0, 1.0, 0.0, 1.0],
+                    true,
+                );
+            }
+        }
+
+        let lights = self.lights();
+        for &(world, light) in &lights {
+            debug_draw::axes(world, 0.5, false);
+            let color = [light.color[0], light.color[1], light.color[2], 1.0];
+            let position = Point3::from_homogeneous(world.w);
+            match light.kind {
+                LightKind::Directional => {
+                    debug_draw::line(position, position - world.z.truncate() * 2.0, color, true);
+                }
+                LightKind::Point | LightKind::Spot { .. } => {
+                    debug_draw::sphere(position, light.range.unwrap_or(0.25), color, true);
+                }
+            }
+        }
+
+        if let Some(window) = self.windows.first() {
+            let shadows = ShadowLayout::new(
+                &lights,
+                window.camera.view_matrix(),
+                &window.projection,
+                &self.shadow_settings,
+            );
+            for &matrix in &shadows.matrices {
+                debug_draw::frustum(matrix, [1.0, 0.5, 0.0, 1.0], true);
+            }
+        }
+    }
+
+    /// Lays the window's overlay out into this frame's text buffer, returning
+    /// how many vertices to draw
+    fn update_text(&self, window: &RenderWindow, draw_count: usize) -> Result<u32> {
+        let text = match &self.text {
+            Some(text) if self.text_settings.show_stats => text,
+            _ => return Ok(0),
+        };
+
+        let extent = window.swapchain_extent;
+        let mut stats = format!(
+            "{:.0} fps  {:.2} ms\n{}x{}  {:?}  {:?}\n{:?}  {} draws",
+            1.0 / self.frame_time,
+            self.frame_time * 1000.0,
+            extent.width,
+            extent.height,
+            self.render_settings.present_mode,
+            window.output_space,
+            self.render_settings.render_path,
+            draw_count,
+        );
+        if let Some(cpu_cull_stats) = window.cpu_cull_stats {
+            stats += &format!(
+                "  {} visible  {} culled",
+                cpu_cull_stats.visible, cpu_cull_stats.culled
+            );
+        }
+
+        let settings = &self.text_settings;
+        let margin = settings.size * 0.5;
+        let mut vertices = vec![];
+        text.atlas.layout(
+            &stats,
+            [margin, margin],
+            settings.size,
+            settings.color,
+            Some(extent.width as f32 - 2.0 * margin),
+            &mut vertices,
+        );
+        vertices.truncate(MAX_GLYPHS * 6);
+        if vertices.is_empty() {
+            return Ok(0);
+        }
+        TextVertex::to_clip_space(&mut vertices, extent);
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                window.text_buffers[window.current_frame].memory,
+                &vertices,
+            )?
+        };
+        Ok(vertices.len() as u32)
+    }
+
+    /// Runs a frame of the tools panels over the main window, uploading any
+    /// textures egui changed, and returns their meshes. `None` while they're
+    /// hidden
+    fn run_gui(&mut self, draw_count: usize) -> Result<Option<GuiFrame>> {
+        let main_window = &self.windows[0];
+        let input = self.gui.take_input(main_window.swapchain_extent);
+        if !self.gui.visible || main_window.paused {
+            return Ok(None);
+        }
+
+        let context = self.gui.context.clone();
+        let output = context.run(input, |context| self.tools_ui(context, draw_count));
+        self.update_gui_textures(output.textures_delta)?;
+
+        Ok(Some(GuiFrame {
+            primitives: context.tessellate(output.shapes, output.pixels_per_point),
+            pixels_per_point: output.pixels_per_point,
+        }))
+    }
+
+    /// The panels for adjusting the renderer while it runs
+    fn tools_ui(&mut self, context: &egui::Context, draw_count: usize) {
+        let main_window = &self.windows[0];
+        let extent = main_window.swapchain_extent;
+        let mut stats = vec![
+            format!(
+                "{:.0} fps  {:.2} ms",
+                1.0 / self.frame_time,
+                self.frame_time * 1000.0
+            ),
+            format!("{}x{}", extent.width, extent.height),
+            format!(
+                "{:?}  {:?}",
+                main_window.output_space, self.render_settings.render_path
+            ),
+            format!(
+                "{} draws  {} instances  {} lights  {} windows",
+                draw_count,
+                self.scene.instances().len() - 1,
+                self.lights().len(),
+                self.windows.len()
+            ),
+        ];
+        if let Some(cpu_cull_stats) = main_window.cpu_cull_stats {
+            stats.push(format!(
+                "{} draws visible  {} culled",
+                cpu_cull_stats.visible, cpu_cull_stats.culled
+            ));
+        }
+        if let Some(cull_stats) = main_window.cull_stats {
+            stats.push(format!(
+                "{} draws  {} instances visible",
+                cull_stats.visible_draws, cull_stats.visible_instances
+            ));
+        }
+
+        egui::Window::new("Renderer").show(context, |ui| {
+            egui::CollapsingHeader::new("Stats")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for line in &stats {
+                        ui.label(line);
+                    }
+                    ui.checkbox(&mut self.text_settings.show_stats, "Overlay");
+                });
+
+            egui::CollapsingHeader::new("Display").show(ui, |ui| {
+                egui::ComboBox::from_label("Present mode")
+                    .selected_text(format!("{:?}", self.render_settings.present_mode))
+                    .show_ui(ui, |ui| {
+                        for mode in PresentMode::ALL {
+                            ui.selectable_value(
+                                &mut self.render_settings.present_mode,
+                                mode,
+                                format!("{:?}", mode),
+                            );
+                        }
+                    });
+            });
+
+            egui::CollapsingHeader::new("Scene").show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.render_settings.clear_color);
+                    ui.label("Clear colour");
+                });
+                ui.checkbox(&mut self.animate_demo_scene, "Animate");
+                ui.checkbox(&mut self.debug_draw_scene, "Debug draw");
+                ui.checkbox(&mut self.render_settings.cpu_culling, "CPU culling");
+                ui.add_enabled(
+                    self.gpu_culling.is_some(),
+                    egui::Checkbox::new(&mut self.render_settings.gpu_culling, "GPU culling"),
+                );
+            });
+
+            egui::CollapsingHeader::new("Post-processing").show(ui, |ui| {
+                for effect in self.post_settings.chain.clone() {
+                    let mut enabled = !self.post_settings.disabled.contains(&effect);
+                    if ui.checkbox(&mut enabled, format!("{:?}", effect)).changed() {
+                        self.post_settings.toggle(effect);
+                    }
+                }
+                ui.add(
+                    egui::Slider::new(&mut self.post_settings.exposure, 0.1..=10.0)
+                        .logarithmic(true)
+                        .text("Exposure"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.post_settings.bloom_intensity, 0.0..=0.5)
+                        .text("Bloom"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.post_settings.vignette_strength, 0.0..=1.0)
+                        .text("Vignette"),
+                );
+            });
+        });
+    }
+
+    /// Creates, updates and frees egui's textures as it asks. Updates are
+    /// rare enough, mostly once at startup for the font, to simply wait for
+    /// the device rather than track which frames still use them. With the
+    /// graphics timeline, freed textures are kept until the frames submitted
+    /// so far are done with them instead
+    fn update_gui_textures(&mut self, delta: egui::TexturesDelta) -> Result<()> {
+        let device = &self.logical_device;
+        if let Some(timeline) = &self.graphics_timeline {
+            for old in self
+                .gui_renderer
+                .deletions
+                .retire(timeline.completed(device)?)
+            {
+                unsafe { self.gui_renderer.free_texture(device, &old)? };
+            }
+        }
+
+        if delta.set.is_empty() && delta.free.is_empty() {
+            return Ok(());
+        }
+        match &self.graphics_timeline {
+            Some(timeline) if !delta.set.is_empty() => {
+                timeline.wait(device, timeline.submitted())?
+            }
+            Some(_) => {}
+            None => unsafe { device.device_wait_idle()? },
+        }
+
+        for (id, image) in delta.set {
+            let patch = gui::texture_data(&image);
+            let old = self.gui_renderer.textures.remove(&id);
+            let descriptor_set = match &old {
+                Some(old) => old.descriptor_set,
+                None => {
+                    let set_layouts = [self.gui_renderer.set_layout];
+                    let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(self.gui_renderer.descriptor_pool)
+                        .set_layouts(&set_layouts);
+                    unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] }
+                }
+            };
+            // a patch is copied into the whole image, which is uploaded again
+            let data = match (old, image.pos) {
+                (Some(old), Some(pos)) => {
+                    unsafe { old.texture.destroy(device) };
+                    let mut data = old.data;
+                    gui::patch_texture_data(&mut data, &patch, pos);
+                    data
+                }
+                (Some(old), None) => {
+                    unsafe { old.texture.destroy(device) };
+                    patch
+                }
+                (None, _) => patch,
+            };
+
+            let texture = Self::create_texture(
+                &self.instance,
+                device,
+                self.physical_device,
+                &self.upload_context,
+                &data,
+            )?;
+            Self::write_source_set(device, descriptor_set, texture.view, texture.sampler);
+            self.gui_renderer.textures.insert(
+                id,
+                GuiTexture {
+                    data,
+                    texture,
+                    descriptor_set,
+                },
+            );
+        }
+
+        for id in delta.free {
+            if let Some(old) = self.gui_renderer.textures.remove(&id) {
+                match &self.graphics_timeline {
+                    Some(timeline) => self.gui_renderer.deletions.push(timeline.submitted(), old),
+                    None => unsafe { self.gui_renderer.free_texture(device, &old)? },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lays the frame's gui meshes out into the window's buffers for it, or
+    /// clears them when the window has no gui
+    fn update_gui(&self, window: &mut RenderWindow, gui: Option<&GuiFrame>) -> Result<()> {
+        let gui_buffer = &mut window.gui_buffers[window.current_frame];
+        gui_buffer.draws.clear();
+        let gui = match gui {
+            Some(gui) => gui,
+            None => return Ok(()),
+        };
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        gui::layout(
+            &gui.primitives,
+            gui.pixels_per_point,
+            window.swapchain_extent,
+            &mut vertices,
+            &mut indices,
+            &mut gui_buffer.draws,
+        );
+        if gui_buffer.draws.is_empty() {
+            return Ok(());
+        }
+
+        unsafe {
+            Self::write_to_memory(&self.logical_device, gui_buffer.vertex_memory, &vertices)?;
+            Self::write_to_memory(&self.logical_device, gui_buffer.index_memory, &indices)
+        }
+    }
+
+    fn update_object_uniforms(
+        &self,
+        window: &RenderWindow,
+        image_index: usize,
+        draws: &[Draw],
+    ) -> Result<()> {
+        if draws.len() > MAX_OBJECTS {
+            log::warn!("only drawing {} of {} objects", MAX_OBJECTS, draws.len());
+        }
+
+        // the fallback textures come last, see `create_material_descriptors`
+        let (white, flat_normal) = (self.textures.len() - 2, self.textures.len() - 1);
+        let uniforms: Vec<ObjectUniforms> = draws
+            .iter()
+            .take(MAX_OBJECTS)
+            .map(|draw| {
+                let material = &self.materials[draw.material];
+                ObjectUniforms::new(draw.world, material, material.textures(white, flat_normal))
+            })
+            .collect();
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                window.object_uniforms.memories[image_index],
+                &uniforms,
+            )
+        }
+    }
+
+    /// Copies the scene's instances into the window's buffers for this frame,
+    /// as many as fit, along with the draw each belongs to
+    fn update_instances(&self, window: &RenderWindow, draws: &[Draw]) -> Result<()> {
+        let instances = self.scene.instances();
+        if instances.len() > MAX_INSTANCES {
+            log::warn!(
+                "only drawing {} of {} instances",
+                MAX_INSTANCES,
+                instances.len()
+            );
+        }
+        let instances = &instances[..instances.len().min(MAX_INSTANCES)];
+
+        let mut objects = vec![InstanceObject(0); instances.len()];
+        for (i, draw) in draws.iter().take(MAX_OBJECTS).enumerate() {
+            let first = draw.first_instance as usize;
+            let drawn = Self::drawn_instances(draw) as usize;
+            objects[first..first + drawn].fill(InstanceObject(i as u32));
+        }
+
+        let instance_buffer = &window.instance_buffers[window.current_frame];
+        unsafe {
+            Self::write_to_memory(&self.logical_device, instance_buffer.memory, instances)?;
+            Self::write_to_memory(
+                &self.logical_device,
+                instance_buffer.object_memory,
+                &objects,
+            )
+        }
+    }
+
+    /// What the window's camera sees
+    fn view_frustum(window: &RenderWindow) -> Frustum {
+        Frustum::from_matrix(window.projection.matrix() * window.camera.view_matrix())
+    }
+
+    /// Gives the culling pass this frame's draws and the camera's frustum, or
+    /// leaves the frame to draw directly when GPU culling is off. Draws culled
+    /// on the CPU are passed on with no instances
+    fn update_cull_buffers(&self, window: &mut RenderWindow, draws: &[Draw]) -> Result<()> {
+        let frustum = Self::view_frustum(window);
+        let Some(culling) = &mut window.culling else {
+            return Ok(());
+        };
+        let frame = &mut culling.frames[window.current_frame];
+        frame.batches.clear();
+        if !self.render_settings.gpu_culling {
+            frame.draw_count = 0;
+            return Ok(());
+        }
+
+        // grouped by material, each group's commands drawn at once, or all in
+        // one group with bindless textures; the draws keep their index in the
+        // object uniforms
+        let bindless = matches!(self.material_textures, MaterialTextures::Bindless(_));
+        let mut order: Vec<usize> = (0..draws.len().min(MAX_OBJECTS)).collect();
+        if !bindless {
+            order.sort_by_key(|&object| draws[object].material);
+        }
+        let mut cull_draws = Vec::with_capacity(order.len());
+        for (i, &object) in order.iter().enumerate() {
+            let draw = &draws[object];
+            match frame.batches.last_mut() {
+                Some(batch) if bindless || batch.material == draw.material => batch.draw_count += 1,
+                _ => frame.batches.push(CullBatch {
+                    material: draw.material,
+                    first_command: i as u32,
+                    draw_count: 1,
+                }),
+            }
+            let batch = frame.batches.len() - 1;
+            cull_draws.push(CullDraw::new(
+                draw.world,
+                &self.meshes[draw.mesh],
+                draw.first_instance,
+                if draw.visible {
+                    Self::drawn_instances(draw)
+                } else {
+                    0
+                },
+                object as u32,
+                batch as u32,
+                frame.batches[batch].first_command,
+            ));
+        }
+        // without an indirect count draw every command in a batch is drawn, so
+        // each draw must keep its own
+        let compact = self
+            .gpu_culling
+            .as_ref()
+            .is_some_and(|gpu_culling| gpu_culling.draw_indirect_count.is_some());
+        let params = CullParams::new(&frustum, cull_draws.len() as u32, compact);
+        let counts = vec![0u32; 1 + frame.batches.len()];
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                frame.params_memory,
+                std::slice::from_ref(&params),
+            )?;
+            Self::write_to_memory(&self.logical_device, frame.draw_memory, &cull_draws)?;
+            Self::write_to_memory(&self.logical_device, frame.count_memory, &counts)?;
+        }
+        frame.draw_count = params.draw_count;
+        Ok(())
+    }
+
+    /// Reads back what the culling pass counted in the current frame's
+    /// buffers, so the frame's fence must have signalled
+    fn read_cull_stats(&self, window: &RenderWindow) -> Result<Option<CullStats>> {
+        let frame = match &window.culling {
+            Some(culling) if culling.frames[window.current_frame].draw_count > 0 => {
+                &culling.frames[window.current_frame]
+            }
+            _ => return Ok(None),
+        };
+
+        // the visible instances, then each batch's visible draws
+        let counts: Vec<u32> = unsafe {
+            Self::read_from_memory(
+                &self.logical_device,
+                frame.count_memory,
+                1 + frame.batches.len(),
+            )?
+        };
+        Ok(Some(CullStats {
+            visible_draws: counts[1..].iter().map(|&count| count as usize).sum(),
+            visible_instances: counts[0] as usize,
+        }))
+    }
+
+    /// How many of a draw's instances made it into the instance buffer
+    fn drawn_instances(draw: &Draw) -> u32 {
+        draw.instance_count
+            .min((MAX_INSTANCES as u32).saturating_sub(draw.first_instance))
+    }
+
+    /// Writes the light list and the shadow map matrices, returning the
+    /// matrices of the shadow map layers that need rendering this frame
+    fn update_light_uniforms(
+        &self,
+        window: &RenderWindow,
+        image_index: usize,
+    ) -> Result<Vec<Matrix4<f32>>> {
+        let lights = self.lights();
+        let shadows = ShadowLayout::new(
+            &lights,
+            window.camera.view_matrix(),
+            &window.projection,
+            &self.shadow_settings,
+        );
+        let uniforms = LightUniforms::new(&lights, &shadows, self.shadow_settings.cascade_splits);
+
+        unsafe {
+            Self::write_to_memory(
+                &self.logical_device,
+                window.light_uniforms.memories[image_index],
+                std::slice::from_ref(&uniforms),
+            )?;
+        }
+
+        Ok(shadows.matrices)
+    }
+
+    /// The scene's lights, or a sun for a scene without any
+    fn lights(&self) -> Vec<(Matrix4<f32>, Light)> {
+        let mut lights = self.scene.lights();
+
+        // a scene without lights would be black apart from emissive surfaces
+        if lights.is_empty() {
+            let sun = Transform {
+                rotation: Quaternion::from_angle_x(Deg(-60.0))
+                    * Quaternion::from_angle_y(Deg(30.0)),
+                ..Default::default()
+            };
+            lights.push((
+                sun.matrix(),
+                Light {
+                    kind: LightKind::Directional,
+                    color: [1.0, 1.0, 1.0],
+                    intensity: 3.0,
+                    range: None,
+                    casts_shadows: true,
+                },
+            ));
+        }
+
+        lights
+    }
+
+    /// The demo field's `i`th cube, `time` seconds into its tumbling
+    fn demo_field_instance(i: usize, time: f32) -> Instance {
+        let size = DEMO_FIELD_SIZE as f32;
+        let (x, z) = ((i % DEMO_FIELD_SIZE) as f32, (i / DEMO_FIELD_SIZE) as f32);
+        // all multiples of 10, so whole turns every `DEMO_FIELD_PERIOD`
+        let degrees_per_second = 30.0 + (i % 7) as f32 * 20.0;
+        let axis = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()][i % 3];
+        Instance {
+            model: Matrix4::from_translation(Vector3::new(
+                (x - (size - 1.0) * 0.5) * 0.75,
+                0.0,
+                (z - (size - 1.0) * 0.5) * 0.75,
+            )) * Matrix4::from_scale(0.3)
+                * Matrix4::from_axis_angle(axis, Deg(degrees_per_second * time)),
+            color: [x / size, 0.5, z / size],
+        }
+    }
+
+    fn animate_scene(&mut self, delta_time: f32) {
+        for (name, degrees_per_second) in [("sun", 20.0), ("planet", 60.0), ("moon", 120.0)] {
+            if let Some(node) = self.scene.find(name) {
+                self.scene.update_local_transform(node, |local| {
+                    local.rotation = Quaternion::from_angle_y(Deg(degrees_per_second * delta_time))
+                        * local.rotation;
+                });
+            }
+        }
+
+        // rebuilt from the time rather than rotated a little more each frame,
+        // which would slowly skew the matrices as rounding errors add up
+        self.demo_time = (self.demo_time + delta_time) % DEMO_FIELD_PERIOD;
+        if let Some(node) = self.scene.find("field") {
+            for (i, instance) in self.scene.instances_mut(node).iter_mut().enumerate() {
+                *instance = Self::demo_field_instance(i, self.demo_time);
+            }
+        }
+    }
+
+    /// A small solar system of cubes: the planet orbits because it is a child
+    /// of the spinning sun, and the moon in turn orbits the planet.
+    fn create_demo_scene() -> ImportedScene {
+        let materials = vec![
+            Material {
+                emissive: [1.0, 0.8, 0.3],
+                ..Material::from_color([1.0, 0.8, 0.3, 1.0])
+            },
+            Material::from_color([0.3, 0.5, 1.0, 1.0]),
+            Material {
+                metallic: 1.0,
+                roughness: 0.3,
+                ..Material::from_color([0.8, 0.8, 0.8, 1.0])
+            },
+            Material::from_color([0.5, 0.5, 0.5, 1.0]),
+        ];
+
+        let mut scene = Scene::default();
+
+        let sun = scene.add_node("sun", None, Transform::default());
+        scene.set_mesh(sun, 0, 0);
+        scene.set_light(
+            sun,
+            Light {
+                kind: LightKind::Point,
+                color: [1.0, 0.9, 0.7],
+                intensity: 40.0,
+                range: None,
+                casts_shadows: false,
+            },
+        );
+
+        // faint fill light so the far sides aren't pitch black, slanted so
+        // the cubes cast visible shadows onto the ground
+        let fill = scene.add_node(
+            "fill light",
+            None,
+            Transform {
+                rotation: Quaternion::from_angle_y(Deg(30.0))
+                    * Quaternion::from_angle_x(Deg(-60.0)),
+                ..Default::default()
+            },
+        );
+        scene.set_light(
+            fill,
+            Light {
+                kind: LightKind::Directional,
+                color: [0.6, 0.7, 1.0],
+                intensity: 0.5,
+                range: None,
+                casts_shadows: true,
+            },
+        );
+
+        let ground = scene.add_node(
+            "ground",
+            None,
+            Transform {
+                scale: Vector3::new(20.0, 0.2, 20.0),
+                ..Transform::from_translation(Vector3::new(0.0, -2.0, 0.0))
+            },
+        );
+        scene.set_mesh(ground, 0, 3);
+
+        let planet = scene.add_node(
+            "planet",
+            Some(sun),
+            Transform {
+                scale: Vector3::new(0.5, 0.5, 0.5),
+                ..Transform::from_translation(Vector3::new(3.0, 0.0, 0.0))
+            },
+        );
+        scene.set_mesh(planet, 0, 1);
+
+        // scale is inherited, so this is relative to the planet's half size
+        let moon = scene.add_node(
+            "moon",
+            Some(planet),
+            Transform {
+                scale: Vector3::new(0.4, 0.4, 0.4),
+                ..Transform::from_translation(Vector3::new(2.0, 0.0, 0.0))
+            },
+        );
+        scene.set_mesh(moon, 0, 2);
+
+        // a field of small cubes over the ground, all drawn at once, each
+        // tumbling at its own speed in animate_scene
+        let field = scene.add_node(
+            "field",
+            None,
+            Transform::from_translation(Vector3::new(0.0, -1.5, 0.0)),
+        );
+        scene.set_mesh(field, 0, 3);
+        let instances: Vec<Instance> = (0..DEMO_FIELD_SIZE * DEMO_FIELD_SIZE)
+            .map(|i| Self::demo_field_instance(i, 0.0))
+            .collect();
+        scene.set_instances(field, &instances);
+
+        ImportedScene {
+            scene,
+            meshes: vec![Mesh::cube()],
+            materials,
+            textures: vec![],
+            cameras: vec![],
+        }
+    }
+
+    /// Uploads the meshes one after another into shared vertex and index
+    /// buffers, so any of them can be drawn without rebinding
+    fn upload_meshes(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        upload: &UploadContext,
+        meshes: &[Mesh],
+    ) -> Result<(MeshBuffers, Vec<GpuMesh>)> {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let gpu_meshes = meshes
+            .iter()
+            .map(|mesh| {
+                let gpu_mesh = GpuMesh {
+                    first_index: indices.len() as u32,
+                    index_count: mesh.indices.len() as u32,
+                    vertex_offset: vertices.len() as i32,
+                    bounds: mesh.bounds(),
+                    bounding_sphere: mesh.bounding_sphere(),
+                };
+                vertices.extend_from_slice(&mesh.vertices);
+                indices.extend_from_slice(&mesh.indices);
+                gpu_mesh
+            })
+            .collect();
+
+        let (vertex_buffer, vertex_buffer_memory) = Self::create_device_local_buffer(
+            instance,
+            device,
+            physical_device,
+            upload,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            &vertices,
+        )?;
+
+        let (index_buffer, index_buffer_memory) = Self::create_device_local_buffer(
+            instance,
+            device,
+            physical_device,
+            upload,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+            &indices,
+        )?;
+
+        let mesh_buffers = MeshBuffers {
+            vertex_buffer,
+            vertex_buffer_memory,
+            index_buffer,
+            index_buffer_memory,
+        };
+        Ok((mesh_buffers, gpu_meshes))
+    }
+
+    fn create_texture(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        upload: &UploadContext,
+        data: &TextureData,
+    ) -> Result<Texture> {
+        let size = data.pixels.len() as vk::DeviceSize;
+
+        let (staging_buffer, staging_memory) = Self::create_buffer(
+            instance,
+            device,
+            physical_device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        unsafe { Self::write_to_memory(device, staging_memory, &data.pixels)? };
+
+        let extent = vk::Extent2D {
+            width: data.width,
+            height: data.height,
+        };
+
+        let (image, memory) = Self::create_image(
+            instance,
+            device,
+            physical_device,
+            extent,
+            1,
+            data.format(),
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        Self::copy_buffer_to_image(device, upload, staging_buffer, image, extent)?;
+
+        unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_memory, None);
+        }
+
+        let view =
+            Self::create_image_view(device, image, data.format(), vk::ImageAspectFlags::COLOR)?;
+        let sampler = Self::create_sampler(device, &data.sampler)?;
+
+        Ok(Texture {
+            image,
+            memory,
+            view,
+            sampler,
+        })
+    }
+
+    fn create_sampler(device: &ash::Device, desc: &SamplerDesc) -> Result<vk::Sampler> {
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(desc.mag_filter)
+            .min_filter(desc.min_filter)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(desc.address_mode_u)
+            .address_mode_v(desc.address_mode_v)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .anisotropy_enable(false)
+            .compare_enable(false)
+            .min_lod(0.0)
+            .max_lod(0.0)
+            .unnormalized_coordinates(false);
+
+        let sampler = unsafe { device.create_sampler(&create_info, None)? };
+        Ok(sampler)
+    }
+
+    fn create_material_set_layout(device: &ash::Device) -> Result<vk::DescriptorSetLayout> {
+        // each texture takes a pair of bindings, the image then its sampler
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..MATERIAL_TEXTURES)
+            .flat_map(|i| {
+                [
+                    *vk::DescriptorSetLayoutBinding::builder()
+                        .binding(2 * i)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+                    *vk::DescriptorSetLayoutBinding::builder()
+                        .binding(2 * i + 1)
+                        .descriptor_type(vk::DescriptorType::SAMPLER)
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+                ]
+            })
+            .collect();
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let layout = unsafe { device.create_descriptor_set_layout(&create_info, None)? };
+        Ok(layout)
+    }
+
+    /// `textures` must end with the white and flat normal fallback textures
+    fn create_material_descriptors(
+        device: &ash::Device,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        materials: &[Material],
+        textures: &[Texture],
+    ) -> Result<MaterialDescriptors> {
+        let descriptor_count = MATERIAL_TEXTURES * materials.len() as u32;
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count,
+            },
+        ];
+
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(materials.len() as u32);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = vec![descriptor_set_layout; materials.len()];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info)? };
+
+        let (white, flat_normal) = (textures.len() - 2, textures.len() - 1);
+        for (&set, material) in descriptor_sets.iter().zip(materials) {
+            let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = material
+                .textures(white, flat_normal)
+                .iter()
+                .map(|&texture| {
+                    let texture = &textures[texture];
+                    [vk::DescriptorImageInfo {
+                        sampler: texture.sampler,
+                        image_view: texture.view,
+                        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    }]
+                })
+                .collect();
+
+            let writes: Vec<vk::WriteDescriptorSet> = image_infos
+                .iter()
+                .zip(0u32..)
+                .flat_map(|(image_info, i)| {
+                    [
+                        *vk::WriteDescriptorSet::builder()
+                            .dst_set(set)
+                            .dst_binding(2 * i)
+                            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                            .image_info(image_info),
+                        *vk::WriteDescriptorSet::builder()
+                            .dst_set(set)
+                            .dst_binding(2 * i + 1)
+                            .descriptor_type(vk::DescriptorType::SAMPLER)
+                            .image_info(image_info),
+                    ]
+                })
+                .collect();
+
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        Ok(MaterialDescriptors {
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+        })
+    }
+
+    /// Arrays of every texture's image and sampler, which can be partially
+    /// filled and updated while bound
+    fn create_bindless_set_layout(device: &ash::Device) -> Result<vk::DescriptorSetLayout> {
+        let bindings = [
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(MAX_BINDLESS_TEXTURES)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(MAX_BINDLESS_TEXTURES)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND; 2];
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .bindings(&bindings)
+            .push_next(&mut binding_flags_info);
+        let layout = unsafe { device.create_descriptor_set_layout(&create_info, None)? };
+        Ok(layout)
+    }
+
+    /// Registers each of `textures` at its index, which is the `TextureId`
+    /// the materials refer to it by
+    fn create_bindless_textures(
+        device: &ash::Device,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        textures: &[Texture],
+    ) -> Result<BindlessTextures> {
+        // scenes with more fall back to per-material sets when the device is created
+        debug_assert!(textures.len() <= MAX_BINDLESS_TEXTURES as usize);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: MAX_BINDLESS_TEXTURES,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: MAX_BINDLESS_TEXTURES,
+            },
+        ];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = [descriptor_set_layout];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] };
+
+        let image_infos: Vec<vk::DescriptorImageInfo> = textures
+            .iter()
+            .map(|texture| vk::DescriptorImageInfo {
+                sampler: texture.sampler,
+                image_view: texture.view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+            .collect();
+        let writes = [
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_infos),
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&image_infos),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(BindlessTextures {
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+        })
+    }
+
+    /// Binary semaphores for acquiring and presenting each frame in flight,
+    /// and fences to retire the frames unless given the graphics timeline
+    fn create_sync_objects(
+        device: &ash::Device,
+        swapchain_images: &Vec<vk::Image>,
+        graphics_timeline: Option<&Rc<Timeline>>,
+    ) -> Result<(Vec<vk::Semaphore>, Vec<vk::Semaphore>, FrameSync)> {
+        let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
+        let fence_create_info =
+            vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+        let mut image_available_semaphores = vec![];
+        let mut render_finished_semaphores = vec![];
+        unsafe {
+            for _ in 0..MAX_FRAMES_IN_FLIGHT {
+                image_available_semaphores
+                    .push(device.create_semaphore(&semaphore_create_info, None)?);
+                render_finished_semaphores
+                    .push(device.create_semaphore(&semaphore_create_info, None)?);
+            }
+
+            let frame_sync = match graphics_timeline {
+                Some(timeline) => FrameSync::Timeline {
+                    timeline: timeline.clone(),
+                    in_flight: vec![0; MAX_FRAMES_IN_FLIGHT],
+                    images_in_flight: vec![0; swapchain_images.len()],
+                },
+                None => FrameSync::Fences {
+                    in_flight: (0..MAX_FRAMES_IN_FLIGHT)
+                        .map(|_| device.create_fence(&fence_create_info, None))
+                        .collect::<Result<_, _>>()?,
+                    images_in_flight: vec![vk::Fence::null(); swapchain_images.len()],
+                },
+            };
+
+            Ok((
+                image_available_semaphores,
+                render_finished_semaphores,
+                frame_sync,
+            ))
+        }
+    }
+
+    /// Also returns the Vulkan version the instance was created for, the
+    /// loader's own up to 1.2
+    fn create_instance(
+        window: &Window,
+        enable_validation_layer: bool,
+    ) -> Result<(ash::Entry, ash::Instance, u32)> {
+        let entry = unsafe { ash::Entry::new()? };
+
+        // a 1.0 loader doesn't have vkEnumerateInstanceVersion
+        let api_version = entry
+            .try_enumerate_instance_version()?
+            .unwrap_or(vk::API_VERSION_1_0)
+            .min(vk::API_VERSION_1_2);
+
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&APP_NAME)
+            .application_version(vk::make_api_version(1, 0, 0, 0))
+            .engine_name(&ENGINE_NAME)
+            .engine_version(vk::make_api_version(1, 0, 0, 0))
+            .api_version(api_version);
+
+        //let extensions = ash_window::enumerate_required_extensions(self.window.as_ref().unwrap())?;
+        let mut extensions = Self::get_required_extension(window, enable_validation_layer)?;
+
+        let mut extension_ptrs: Vec<*const c_char> =
+            extensions.iter().map(|s| s.as_ptr()).collect();
+
+        Self::check_extension_support(&entry, &extension_ptrs)?;
+
+        // lets surfaces report HDR colour spaces, presenting SDR without it
+        let colorspace_extension = vk::ExtSwapchainColorspaceFn::name();
+        if Self::check_extension_support(&entry, &vec![colorspace_extension.as_ptr()]).is_ok() {
+            extensions.push(colorspace_extension);
+            extension_ptrs.push(colorspace_extension.as_ptr());
+        }
+
+        let validation_layers = Self::get_required_validation_layers(enable_validation_layer)?;
+
+        let validation_layer_ptrs = validation_layers.iter().map(|l| l.as_ptr()).collect();
+
+        Self::check_validation_layer_support(&entry, &validation_layer_ptrs)?;
+
+        let mut instance_info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_extension_names(&extension_ptrs)
+            .enabled_layer_names(&validation_layer_ptrs);
+
+        let mut debug_create_info;
+
+        if enable_validation_layer {
+            debug_create_info = Self::populate_debug_messenger_create_info()?;
+            instance_info = instance_info.push_next(&mut debug_create_info);
+        }
+
+        let instance = unsafe { entry.create_instance(&instance_info, None)? };
+
+        Ok((entry, instance, api_version))
+    }
+
+    fn create_command_pool(
+        device: &ash::Device,
+        queue_family_index: u32,
+        flags: vk::CommandPoolCreateFlags,
+    ) -> Result<vk::CommandPool> {
+        let create_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(flags);
+
+        let command_pool = unsafe { device.create_command_pool(&create_info, None)? };
+        Ok(command_pool)
+    }
+
+    fn create_command_buffers(
+        device: &ash::Device,
+        command_pool: &vk::CommandPool,
+        count: usize,
+    ) -> Result<Vec<vk::CommandBuffer>> {
+        let alloc_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(*command_pool)
+            .command_buffer_count(count as u32)
+            .level(vk::CommandBufferLevel::PRIMARY);
+
+        let command_buffers = unsafe { device.allocate_command_buffers(&alloc_info)? };
+        Ok(command_buffers)
+    }
+
+    fn record_command_buffer(
+        &self,
+        window: &RenderWindow,
+        image_index: usize,
+        draws: &[Draw],
+        shadow_matrices: &[Matrix4<f32>],
+        simulate: bool,
+    ) -> Result<()> {
+        let device = &self.logical_device;
+        let command = window.command_buffers[image_index];
+        // the scene's draws come from the culling pass when it ran this frame
+        let cull_frame = window
+            .culling
+            .as_ref()
+            .map(|culling| &culling.frames[window.current_frame])
+            .filter(|frame| frame.draw_count > 0);
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.reset_command_buffer(command, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(command, &begin_info)?;
+            if simulate {
+                Self::record_particle_simulation(
+                    device,
+                    command,
+                    &self.particle_system,
+                    window.current_frame,
+                );
+            }
+            if let (Some(gpu_culling), Some(frame)) = (&self.gpu_culling, cull_frame) {
+                Self::record_culling(device, command, gpu_culling, frame);
+            }
+            self.record_shadow_passes(window, command, image_index, draws, shadow_matrices);
+        }
+
+        let [r, g, b] = self.render_settings.clear_color;
+        let mut clear_values = vec![
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [r, g, b, 1.0],
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        if let Some(gbuffer) = &window.gbuffer {
+            clear_values.extend(gbuffer.views.iter().map(|_| vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 0.0],
+                },
+            }));
+        }
+
+        let render_pass_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(window.post_processing.scene.framebuffer)
+            .render_area(Self::full_extent(window.swapchain_extent))
+            .clear_values(&clear_values);
+
+        unsafe {
+            device.cmd_begin_render_pass(command, &render_pass_info, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(command, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                command,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[
+                    window.camera_uniforms.descriptor_sets[image_index],
+                    window.object_uniforms.descriptor_sets[image_index],
+                ],
+                &[],
+            );
+            if window.gbuffer.is_none() {
+                device.cmd_bind_descriptor_sets(
+                    command,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    3,
+                    &[window.light_uniforms.descriptor_sets[image_index]],
+                    &[],
+                );
+            }
+            if let MaterialTextures::Bindless(bindless) = &self.material_textures {
+                device.cmd_bind_descriptor_sets(
+                    command,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    2,
+                    &[bindless.descriptor_set],
+                    &[],
+                );
+            }
+
+            let (instance_buffer, object_buffer) = match cull_frame {
+                Some(frame) => (frame.visible_instance_buffer, frame.visible_object_buffer),
+                None => {
+                    let instance_buffer = &window.instance_buffers[window.current_frame];
+                    (instance_buffer.buffer, instance_buffer.object_buffer)
+                }
+            };
+            device.cmd_bind_vertex_buffers(
+                command,
+                0,
+                &[
+                    self.mesh_buffers.vertex_buffer,
+                    instance_buffer,
+                    object_buffer,
+                ],
+                &[0, 0, 0],
+            );
+            device.cmd_bind_index_buffer(
+                command,
+                self.mesh_buffers.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            // viewport and scissor are dynamic state, so each view (e.g. one
+            // half of a split screen) just resets them before drawing
+            for &view in &window.views {
+                Self::cmd_set_viewport(device, command, view);
+                Self::cmd_set_scissor(device, command, view, window.swapchain_extent);
+
+                match cull_frame {
+                    Some(frame) => {
+                        for (i, batch) in frame.batches.iter().enumerate() {
+                            self.cmd_bind_material(command, batch.material);
+                            self.cmd_draw_culled(command, frame, i);
+                        }
+                    }
+                    None => {
+                        // culled draws are still in the object uniforms, for
+                        // the shadow pass
+                        for draw in draws.iter().take(MAX_OBJECTS).filter(|draw| draw.visible) {
+                            self.cmd_bind_material(command, draw.material);
+                            self.cmd_draw_mesh(command, draw);
+                        }
+                    }
+                }
+            }
+
+            if let Some(gbuffer) = &window.gbuffer {
+                device.cmd_next_subpass(command, vk::SubpassContents::INLINE);
+                device.cmd_bind_pipeline(
+                    command,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    gbuffer.lighting_pipeline,
+                );
+                device.cmd_bind_descriptor_sets(
+                    command,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    gbuffer.lighting_pipeline_layout,
+                    0,
+                    &[
+                        window.camera_uniforms.descriptor_sets[image_index],
+                        gbuffer.descriptor_set,
+                        window.light_uniforms.descriptor_sets[image_index],
+                    ],
+                    &[],
+                );
+                for &view in &window.views {
+                    Self::cmd_set_viewport(device, command, view);
+                    Self::cmd_set_scissor(device, command, view, window.swapchain_extent);
+                    device.cmd_draw(command, 3, 1, 0, 0);
+                }
+            }
+
+            device.cmd_bind_pipeline(
+                command,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.particle_system.graphics_pipeline,
+            );
+            device.cmd_bind_vertex_buffers(command, 0, &[self.particle_system.buffer], &[0]);
+            for &view in &window.views {
+                Self::cmd_set_viewport(device, command, view);
+                Self::cmd_set_scissor(device, command, view, window.swapchain_extent);
+                device.cmd_draw(command, PARTICLE_COUNT, 1, 0, 0);
+            }
+
+            self.record_debug_lines(window, command, image_index);
+
+            device.cmd_end_render_pass(command);
+            self.record_post_processing(window, command, image_index);
+            device.end_command_buffer(command)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws this frame's debug lines, the depth tested ones then the rest, in
+    /// every view
+    unsafe fn record_debug_lines(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        let debug_buffer = &window.debug_buffers[window.current_frame];
+        let device = &self.logical_device;
+        let pipelines = &self.debug_pipelines;
+
+        device.cmd_bind_descriptor_sets(
+            command,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipelines.pipeline_layout,
+            0,
+            &[window.camera_uniforms.descriptor_sets[image_index]],
+            &[],
+        );
+        device.cmd_bind_vertex_buffers(command, 0, &[debug_buffer.buffer], &[0]);
+
+        for (pipeline, first, count) in [
+            (
+                pipelines.depth_tested_pipeline,
+                0,
+                debug_buffer.depth_tested_count,
+            ),
+            (
+                pipelines.overlay_pipeline,
+                debug_buffer.depth_tested_count,
+                debug_buffer.overlay_count,
+            ),
+        ] {
+            if count == 0 {
+                continue;
+            }
+            device.cmd_bind_pipeline(command, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            for &view in &window.views {
+                Self::cmd_set_viewport(device, command, view);
+                Self::cmd_set_scissor(device, command, view, window.swapchain_extent);
+                device.cmd_draw(command, count, 1, first, 0);
+            }
+        }
+    }
+
+    /// Renders the depth of every draw into each used shadow map layer, from
+    /// that layer's light
+    unsafe fn record_shadow_passes(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+        draws: &[Draw],
+        matrices: &[Matrix4<f32>],
+    ) {
+        let device = &self.logical_device;
+        let shadow_maps = &self.shadow_maps;
+        let area = Self::full_extent(vk::Extent2D {
+            width: shadow_maps.resolution,
+            height: shadow_maps.resolution,
+        });
+
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+
+        let instance_buffer = &window.instance_buffers[window.current_frame];
+        for (layer, &light_view_proj) in matrices.iter().enumerate() {
+            let render_pass_info = vk::RenderPassBeginInfo::builder()
+                .render_pass(shadow_maps.render_pass)
+                .framebuffer(shadow_maps.framebuffers[layer])
+                .render_area(area)
+                .clear_values(&clear_values);
+
+            device.cmd_begin_render_pass(command, &render_pass_info, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(
+                command,
+                vk::PipelineBindPoint::GRAPHICS,
+                shadow_maps.pipeline,
+            );
+            Self::cmd_set_viewport(device, command, area);
+            Self::cmd_set_scissor(device, command, area, area.extent);
+            Self::cmd_push_constants(
+                device,
+                command,
+                shadow_maps.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                &ShadowPassConstants { light_view_proj },
+            );
+            device.cmd_bind_descriptor_sets(
+                command,
+                vk::PipelineBindPoint::GRAPHICS,
+                shadow_maps.pipeline_layout,
+                0,
+                &[window.object_uniforms.descriptor_sets[image_index]],
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(
+                command,
+                0,
+                &[
+                    self.mesh_buffers.vertex_buffer,
+                    instance_buffer.buffer,
+                    instance_buffer.object_buffer,
+                ],
+                &[0, 0, 0],
+            );
+            device.cmd_bind_index_buffer(
+                command,
+                self.mesh_buffers.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            for draw in draws.iter().take(MAX_OBJECTS) {
+                self.cmd_draw_mesh(command, draw);
+            }
+
+            device.cmd_end_render_pass(command);
+        }
+    }
+
+    /// Runs the enabled effects over the scene colour, ping-ponging between
+    /// two targets, then copies the result to the swapchain image
+    unsafe fn record_post_processing(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        let post = &window.post_processing;
+        let mut source = &post.scene;
+        let mut next = 0;
+
+        for effect in self.post_settings.active(window.output_space) {
+            let target = &post.ping_pong[next];
+            let mut sources = vec![source.descriptor_set];
+
+            if effect == PostEffect::Bloom {
+                // each level is a filtered half size copy of the one above, the
+                // first keeping only what's bright enough to bloom
+                let mut level_source = source;
+                for (level, level_target) in post.bloom.iter().enumerate() {
+                    let pipeline = if level == 0 {
+                        post.bloom_prefilter_pipeline
+                    } else {
+                        post.bloom_downsample_pipeline
+                    };
+                    self.record_post_pass(
+                        window,
+                        command,
+                        image_index,
+                        post.render_pass,
+                        level_target,
+                        pipeline,
+                        &[level_source.descriptor_set],
+                    );
+                    level_source = level_target;
+                }
+
+                // then each level is blurred back onto the one above, summing
+                // the pyramid into the first
+                for pair in post.bloom.windows(2).rev() {
+                    self.record_post_pass(
+                        window,
+                        command,
+                        image_index,
+                        post.blend_render_pass,
+                        &pair[0],
+                        post.bloom_upsample_pipeline,
+                        &[pair[1].descriptor_set],
+                    );
+                }
+
+                if let Some(bloom) = post.bloom.first() {
+                    sources.push(bloom.descriptor_set);
+                }
+            }
+
+            self.record_post_pass(
+                window,
+                command,
+                image_index,
+                post.render_pass,
+                target,
+                post.effect_pipeline(effect),
+                &sources,
+            );
+            source = target;
+            next = 1 - next;
+        }
+
+        let area = Self::full_extent(window.swapchain_extent);
+        let render_pass_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(post.output_render_pass)
+            .framebuffer(post.output_framebuffers[image_index])
+            .render_area(area);
+        self.logical_device.cmd_begin_render_pass(
+            command,
+            &render_pass_info,
+            vk::SubpassContents::INLINE,
+        );
+        self.record_fullscreen_draw(
+            window,
+            command,
+            image_index,
+            area,
+            post.output_pipeline,
+            &[source.descriptor_set],
+        );
+        self.record_text(window, command, image_index);
+        self.record_gui(window, command, image_index);
+        self.logical_device.cmd_end_render_pass(command);
+    }
+
+    /// Draws this frame's glyph quads over the output, inside the output pass
+    unsafe fn record_text(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        let text_buffer = &window.text_buffers[window.current_frame];
+        let text = match &self.text {
+            Some(text) if text_buffer.vertex_count > 0 => text,
+            _ => return,
+        };
+        let device = &self.logical_device;
+
+        // the viewport and scissor carry over from the output draw
+        device.cmd_bind_pipeline(
+            command,
+            vk::PipelineBindPoint::GRAPHICS,
+            window.post_processing.text_pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command,
+            vk::PipelineBindPoint::GRAPHICS,
+            window.post_processing.pipeline_layout,
+            0,
+            &[
+                window.post_uniforms.descriptor_sets[image_index],
+                text.descriptor_set,
+            ],
+            &[],
+        );
+        device.cmd_bind_vertex_buffers(command, 0, &[text_buffer.buffer], &[0]);
+        device.cmd_draw(command, text_buffer.vertex_count, 1, 0, 0);
+    }
+
+    /// Draws this frame's gui meshes over the output, inside the output pass,
+    /// each clipped to its rectangle
+    unsafe fn record_gui(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        let gui_buffer = &window.gui_buffers[window.current_frame];
+        if gui_buffer.draws.is_empty() {
+            return;
+        }
+        let device = &self.logical_device;
+        let post = &window.post_processing;
+
+        device.cmd_bind_pipeline(command, vk::PipelineBindPoint::GRAPHICS, post.gui_pipeline);
+        device.cmd_bind_descriptor_sets(
+            command,
+            vk::PipelineBindPoint::GRAPHICS,
+            post.pipeline_layout,
+            0,
+            &[window.post_uniforms.descriptor_sets[image_index]],
+            &[],
+        );
+        device.cmd_bind_vertex_buffers(command, 0, &[gui_buffer.vertex_buffer], &[0]);
+        device.cmd_bind_index_buffer(command, gui_buffer.index_buffer, 0, vk::IndexType::UINT32);
+
+        for draw in &gui_buffer.draws {
+            let texture = match self.gui_renderer.textures.get(&draw.texture) {
+                Some(texture) => texture,
+                None => continue,
+            };
+            Self::cmd_set_scissor(device, command, draw.clip, window.swapchain_extent);
+            device.cmd_bind_descriptor_sets(
+                command,
+                vk::PipelineBindPoint::GRAPHICS,
+                post.pipeline_layout,
+                1,
+                &[texture.descriptor_set],
+                &[],
+            );
+            device.cmd_draw_indexed(
+                command,
+                draw.index_count,
+                1,
+                draw.first_index,
+                draw.vertex_offset,
+                0,
+            );
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn record_post_pass(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+        render_pass: vk::RenderPass,
+        target: &RenderTarget,
+        pipeline: vk::Pipeline,
+        sources: &[vk::DescriptorSet],
+    ) {
+        let area = Self::full_extent(target.extent);
+        let render_pass_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(target.framebuffer)
+            .render_area(area);
+
+        self.logical_device.cmd_begin_render_pass(
+            command,
+            &render_pass_info,
+            vk::SubpassContents::INLINE,
+        );
+        self.record_fullscreen_draw(window, command, image_index, area, pipeline, sources);
+        self.logical_device.cmd_end_render_pass(command);
+    }
+
+    /// Draws a post pipeline's full-screen triangle, with the post uniforms at
+    /// set 0 and `sources` from set 1 on
+    unsafe fn record_fullscreen_draw(
+        &self,
+        window: &RenderWindow,
+        command: vk::CommandBuffer,
+        image_index: usize,
+        area: vk::Rect2D,
+        pipeline: vk::Pipeline,
+        sources: &[vk::DescriptorSet],
+    ) {
+        let device = &self.logical_device;
+        let layout = window.post_processing.pipeline_layout;
+
+        device.cmd_bind_pipeline(command, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        Self::cmd_set_viewport(device, command, area);
+        Self::cmd_set_scissor(device, command, area, area.extent);
+
+        let sets: Vec<vk::DescriptorSet> =
+            std::iter::once(window.post_uniforms.descriptor_sets[image_index])
+                .chain(sources.iter().copied())
+                .collect();
+        device.cmd_bind_descriptor_sets(
+            command,
+            vk::PipelineBindPoint::GRAPHICS,
+            layout,
+            0,
+            &sets,
+            &[],
+        );
+        device.cmd_draw(command, 3, 1, 0, 0);
+    }
+
+    unsafe fn record_particle_simulation(
+        device: &ash::Device,
+        command: vk::CommandBuffer,
+        particle_system: &ParticleSystem,
+        frame: usize,
+    ) {
+        let whole_buffer = |src_access, dst_access| {
+            Self::buffer_barrier(
+                particle_system.buffer,
+                src_access,
+                dst_access,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            )
+        };
+
+        // the previous frame's dispatch must finish writing, and its draw finish
+        // reading, before this frame integrates the particles in place
+        device.cmd_pipeline_barrier(
+            command,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[whole_buffer(
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            )],
+            &[],
+        );
+
+        device.cmd_bind_pipeline(
+            command,
+            vk::PipelineBindPoint::COMPUTE,
+            particle_system.compute_pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command,
+            vk::PipelineBindPoint::COMPUTE,
+            particle_system.compute_pipeline_layout,
+            0,
+            &[particle_system.descriptor_sets[frame]],
+            &[],
+        );
+        device.cmd_dispatch(
+            command,
+            PARTICLE_COUNT.div_ceil(PARTICLE_WORKGROUP_SIZE),
+            1,
+            1,
+        );
+
+        // make the new positions visible to the vertex input stage
+        device.cmd_pipeline_barrier(
+            command,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[whole_buffer(
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            )],
+            &[],
+        );
+    }
+
+    /// Frustum culls the frame's draws, one workgroup each, into indirect
+    /// commands drawing just their visible instances
+    unsafe fn record_culling(
+        device: &ash::Device,
+        command: vk::CommandBuffer,
+        gpu_culling: &GpuCulling,
+        frame: &CullFrame,
+    ) {
+        device.cmd_bind_pipeline(
+            command,
+            vk::PipelineBindPoint::COMPUTE,
+            gpu_culling.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command,
+            vk::PipelineBindPoint::COMPUTE,
+            gpu_culling.pipeline_layout,
+            0,
+            &[frame.descriptor_set],
+            &[],
+        );
+        device.cmd_dispatch(command, frame.draw_count, 1, 1);
+
+        // the scene pass reads the commands, counts, instances and their
+        // objects, and the host the counts once the frame's fence signals
+        let barrier = |buffer, dst_access| {
+            Self::buffer_barrier(
+                buffer,
+                vk::AccessFlags::SHADER_WRITE,
+                dst_access,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            )
+        };
+        device.cmd_pipeline_barrier(
+            command,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT
+                | vk::PipelineStageFlags::VERTEX_INPUT
+                | vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[
+                barrier(
+                    frame.visible_instance_buffer,
+                    vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                ),
+                barrier(
+                    frame.visible_object_buffer,
+                    vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                ),
+                barrier(frame.command_buffer, vk::AccessFlags::INDIRECT_COMMAND_READ),
+                barrier(
+                    frame.count_buffer,
+                    vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::HOST_READ,
+                ),
+            ],
+            &[],
+        );
+    }
+
+    /// Draws every draw of the frame's `batch`th batch the culling pass left
+    /// visible with one indirect draw
+    unsafe fn cmd_draw_culled(&self, command: vk::CommandBuffer, frame: &CullFrame, batch: usize) {
+        let first_command = frame.batches[batch].first_command;
+        let draw_count = frame.batches[batch].draw_count;
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        let offset = first_command as vk::DeviceSize * stride as vk::DeviceSize;
+
+        match self
+            .gpu_culling
+            .as_ref()
+            .and_then(|gpu_culling| gpu_culling.draw_indirect_count.as_ref())
+        {
+            // the batch's visible draws' commands are packed at its start, as
+            // many as the pass counted after the visible instances
+            Some(draw_indirect_count) => draw_indirect_count.cmd_draw_indexed_indirect_count(
+                command,
+                frame.command_buffer,
+                offset,
+                frame.count_buffer,
+                ((1 + batch) * std::mem::size_of::<u32>()) as vk::DeviceSize,
+                draw_count,
+                stride,
+            ),
+            // every draw keeps its command, culled ones drawing no instances
+            None => self.logical_device.cmd_draw_indexed_indirect(
+                command,
+                frame.command_buffer,
+                offset,
+                draw_count,
+                stride,
+            ),
+        }
+    }
+
+    /// Draws `draw`'s mesh from the shared buffers with as many of its
+    /// instances as made it into the instance buffer
+    unsafe fn cmd_draw_mesh(&self, command: vk::CommandBuffer, draw: &Draw) {
+        let mesh = &self.meshes[draw.mesh];
+        self.logical_device.cmd_draw_indexed(
+            command,
+            mesh.index_count,
+            Self::drawn_instances(draw),
+            mesh.first_index,
+            mesh.vertex_offset,
+            draw.first_instance,
+        );
+    }
+
+    /// Binds the textures of `material` for the scene pipeline, unless the
+    /// bindless textures are bound already for the whole pass
+    unsafe fn cmd_bind_material(&self, command: vk::CommandBuffer, material: MaterialId) {
+        if let MaterialTextures::Sets(descriptors) = &self.material_textures {
+            self.logical_device.cmd_bind_descriptor_sets(
+                command,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                2,
+                &[descriptors.descriptor_sets[material]],
+                &[],
+            );
+        }
+    }
+
+    /// Pushes `constants`, a `#[repr(C)]` struct laid out like the shader's
+    /// push constant block, at `offset` into one of the layout's ranges
+    unsafe fn cmd_push_constants<T: Copy>(
+        device: &ash::Device,
+        command: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &T,
+    ) {
+        let bytes = std::slice::from_raw_parts(
+            (constants as *const T).cast::<u8>(),
+            std::mem::size_of::<T>(),
+        );
+        device.cmd_push_constants(command, layout, stages, offset, bytes);
+    }
+
+    fn buffer_barrier(
+        buffer: vk::Buffer,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+        src_queue_family: u32,
+        dst_queue_family: u32,
+    ) -> vk::BufferMemoryBarrier {
+        *vk::BufferMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(src_queue_family)
+            .dst_queue_family_index(dst_queue_family)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn image_barrier(
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+        src_queue_family: u32,
+        dst_queue_family: u32,
+    ) -> vk::ImageMemoryBarrier {
+        *vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(src_queue_family)
+            .dst_queue_family_index(dst_queue_family)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+    }
+
+    fn full_extent(extent: vk::Extent2D) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        }
+    }
+
+    unsafe fn cmd_set_viewport(device: &ash::Device, command: vk::CommandBuffer, area: vk::Rect2D) {
+        let viewport = [vk::Viewport {
+            x: area.offset.x as f32,
+            y: area.offset.y as f32,
+            width: area.extent.width as f32,
+            height: area.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        device.cmd_set_viewport(command, 0, &viewport);
+    }
+
+    /// Restricts subsequent draws to `clip`, which is clamped to the framebuffer
+    /// so callers can pass UI clip rectangles straight through.
+    unsafe fn cmd_set_scissor(
+        device: &ash::Device,
+        command: vk::CommandBuffer,
+        clip: vk::Rect2D,
+        framebuffer_extent: vk::Extent2D,
+    ) {
+        let x = clip.offset.x.clamp(0, framebuffer_extent.width as i32);
+        let y = clip.offset.y.clamp(0, framebuffer_extent.height as i32);
+        // in i64, as an extent like u32::MAX for "everything" would overflow i32
+        let right = (clip.offset.x as i64 + clip.extent.width as i64)
+            .clamp(x as i64, framebuffer_extent.width as i64) as i32;
+        let bottom = (clip.offset.y as i64 + clip.extent.height as i64)
+            .clamp(y as i64, framebuffer_extent.height as i64) as i32;
+
+        let scissor = [vk::Rect2D {
+            offset: vk::Offset2D { x, y },
+            extent: vk::Extent2D {
+                width: (right - x) as u32,
+                height: (bottom - y) as u32,
+            },
+        }];
+
+        device.cmd_set_scissor(command, 0, &scissor);
+    }
+
+    fn create_forward_render_pass(
+        device: &ash::Device,
+        depth_format: vk::Format,
+    ) -> Result<vk::RenderPass> {
+        let attachment_descriptions = [
+            Self::scene_color_attachment(),
+            *vk::AttachmentDescription::builder()
+                .format(depth_format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        ];
+
+        let subpasses = [SubpassDesc {
+            color_attachments: &[vk::AttachmentReference {
+                attachment: 0,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            }],
+            input_attachments: &[],
+            depth_attachment: Some(vk::AttachmentReference {
+                attachment: 1,
+                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            }),
+        }];
+
+        // the depth and colour images are shared by all frames in flight, so
+        // the previous frame's depth writes and post-processing reads must
+        // finish before this one clears them
+        let subpass_deps = [
+            *vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                )
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ),
+            Self::scene_color_dependency(0),
+        ];
+
+        Self::create_render_pass(device, &attachment_descriptions, &subpasses, &subpass_deps)
+    }
+
+    /// A geometry subpass filling the G-buffer, then a lighting subpass reading
+    /// it back as input attachments. The G-buffer never leaves tile memory on
+    /// GPUs that have it, since nothing needs it after the render pass.
+    fn create_deferred_render_pass(
+        device: &ash::Device,
+        depth_format: vk::Format,
+    ) -> Result<vk::RenderPass> {
+        let transient_attachment = |format, final_layout| {
+            *vk::AttachmentDescription::builder()
+                .format(format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(final_layout)
+        };
+
+        // scene colour, depth, then the G-buffer
+        let mut attachment_descriptions = vec![
+            Self::scene_color_attachment(),
+            transient_attachment(
+                depth_format,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+        ];
+        attachment_descriptions.extend(GBUFFER_FORMATS.iter().map(|&format| {
+            transient_attachment(format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        }));
+
+        let gbuffer_attachments = 2..2 + GBUFFER_FORMATS.len() as u32;
+        let geometry_colors: Vec<vk::AttachmentReference> = std::iter::once(0)
+            .chain(gbuffer_attachments.clone())
+            .map(|attachment| vk::AttachmentReference {
+                attachment,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect();
+        let lighting_inputs: Vec<vk::AttachmentReference> = gbuffer_attachments
+            .map(|attachment| vk::AttachmentReference {
+                attachment,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+            .chain(std::iter::once(vk::AttachmentReference {
+                attachment: 1,
+                layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            }))
+            .collect();
+
+        let subpasses = [
+            SubpassDesc {
+                color_attachments: &geometry_colors,
+                input_attachments: &[],
+                depth_attachment: Some(vk::AttachmentReference {
+                    attachment: 1,
+                    layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                }),
+            },
+            // depth stays attached, read only, for what's drawn after lighting
+            SubpassDesc {
+                color_attachments: &geometry_colors[..1],
+                input_attachments: &lighting_inputs,
+                depth_attachment: Some(vk::AttachmentReference {
+                    attachment: 1,
+                    layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                }),
+            },
+        ];
+
+        let subpass_deps = [
+            // as in the forward pass, but the previous frame's lighting
+            // subpass must also be done reading the G-buffer
+            *vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                )
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ),
+            // each pixel is lit from its own G-buffer texels only
+            *vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(1)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                )
+                .src_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::INPUT_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                )
+                .dependency_flags(vk::DependencyFlags::BY_REGION),
+            Self::scene_color_dependency(1),
+        ];
+
+        Self::create_render_pass(device, &attachment_descriptions, &subpasses, &subpass_deps)
+    }
+
+    /// The HDR scene colour, cleared and left ready for post-processing
+    fn scene_color_attachment() -> vk::AttachmentDescription {
+        *vk::AttachmentDescription::builder()
+            .format(HDR_FORMAT)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    /// Makes the colour written by `last_subpass` visible to post-processing
+    fn scene_color_dependency(last_subpass: u32) -> vk::SubpassDependency {
+        *vk::SubpassDependency::builder()
+            .src_subpass(last_subpass)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+    }
+
+    /// A single colour attachment pass for full-screen effects. The previous
+    /// pass's output is read in the fragment shader, and this target may
+    /// still be being read by an earlier pass or frame.
+    fn create_post_render_pass(
+        device: &ash::Device,
+        format: vk::Format,
+        load_op: vk::AttachmentLoadOp,
+        initial_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    ) -> Result<vk::RenderPass> {
+        let attachment_descriptions = [*vk::AttachmentDescription::builder()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(initial_layout)
+            .final_layout(final_layout)];
+
+        let subpasses = [SubpassDesc {
+            color_attachments: &[vk::AttachmentReference {
+                attachment: 0,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            }],
+            input_attachments: &[],
+            depth_attachment: None,
+        }];
+
+        let subpass_deps = [
+            *vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                )
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::SHADER_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ),
+            Self::scene_color_dependency(0),
+        ];
+
+        Self::create_render_pass(device, &attachment_descriptions, &subpasses, &subpass_deps)
+    }
+
+    fn create_render_pass(
+        device: &ash::Device,
+        attachments: &[vk::AttachmentDescription],
+        subpasses: &[SubpassDesc],
+        dependencies: &[vk::SubpassDependency],
+    ) -> Result<vk::RenderPass> {
+        let subpass_descriptions: Vec<vk::SubpassDescription> = subpasses
+            .iter()
+            .map(|subpass| {
+                let mut description = vk::SubpassDescription::builder()
+                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                    .color_attachments(subpass.color_attachments)
+                    .input_attachments(subpass.input_attachments);
+                if let Some(depth_attachment) = &subpass.depth_attachment {
+                    description = description.depth_stencil_attachment(depth_attachment);
+                }
+                *description
+            })
+            .collect();
+
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(attachments)
+            .dependencies(dependencies)
+            .subpasses(&subpass_descriptions);
+
+        let render_pass = unsafe { device.create_render_pass(&create_info, None)? };
+
+        Ok(render_pass)
+    }
+
+    fn create_shadow_maps(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        settings: &ShadowSettings,
+        object_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<ShadowMaps> {
+        let format = Self::find_depth_format(
+            instance,
+            physical_device,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        )?;
+        let resolution = settings.resolution;
+        let extent = vk::Extent2D {
+            width: resolution,
+            height: resolution,
+        };
+
+        let (image, memory) = Self::create_image(
+            instance,
+            device,
+            physical_device,
+            extent,
+            MAX_SHADOW_MAPS as u32,
+            format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        let array_view = Self::create_image_layers_view(
+            device,
+            image,
+            format,
+            vk::ImageAspectFlags::DEPTH,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            0,
+            MAX_SHADOW_MAPS as u32,
+        )?;
+        let layer_views = (0..MAX_SHADOW_MAPS as u32)
+            .map(|layer| {
+                Self::create_image_layers_view(
+                    device,
+                    image,
+                    format,
+                    vk::ImageAspectFlags::DEPTH,
+                    vk::ImageViewType::TYPE_2D,
+                    layer,
+                    1,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // outside the map counts as lit
+        let sampler_create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .anisotropy_enable(false)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .min_lod(0.0)
+            .max_lod(0.0)
+            .unnormalized_coordinates(false);
+        let sampler = unsafe { device.create_sampler(&sampler_create_info, None)? };
+
+        let render_pass = Self::create_shadow_render_pass(device, format)?;
+
+        let framebuffers = layer_views
+            .iter()
+            .map(|&view| Self::create_framebuffer(device, render_pass, &[view], extent))
+            .collect::<Result<Vec<_>>>()?;
+
+        // each layer's light matrix is pushed as it's rendered
+        let push_constant_range = Self::push_constant_range::<ShadowPassConstants>(
+            instance,
+            physical_device,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+        )?;
+
+        // only positions matter for depth, of the vertices and the instances,
+        // and the object each instance is drawn with
+        let (pipeline_layout, pipeline) = Self::create_graphics_pipeline(
+            device,
+            render_pass,
+            &GraphicsPipelineDesc {
+                vertex_shader: "shaders/shadow_vert.spv",
+                fragment_shader: None,
+                set_layouts: &[object_set_layout],
+                push_constant_ranges: &[push_constant_range],
+                vertex_bindings: &[
+                    Vertex::binding_descriptions()[0],
+                    Instance::binding_descriptions()[0],
+                    InstanceObject::binding_descriptions()[0],
+                ],
+                vertex_attributes: &[
+                    &Vertex::attribute_descriptions()[..1],
+                    &Instance::attribute_descriptions()[..4],
+                    &InstanceObject::attribute_descriptions(),
+                ]
+                .concat(),
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                depth_test: true,
+                depth_write: true,
+                depth_bias: Some((settings.depth_bias_constant, settings.depth_bias_slope)),
+                subpass: 0,
+                color_attachments: 0,
+                blend: Blend::Replace,
+            },
+        )?;
+
+        Ok(ShadowMaps {
+            resolution,
+            image,
+            memory,
+            array_view,
+            layer_views,
+            framebuffers,
+            sampler,
+            render_pass,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    /// Depth only pass leaving the shadow map ready for sampling
+    fn create_shadow_render_pass(
+        device: &ash::Device,
+        depth_format: vk::Format,
+    ) -> Result<vk::RenderPass> {
+        let attachment_descriptions = [*vk::AttachmentDescription::builder()
+            .format(depth_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)];
+
+        let subpasses = [SubpassDesc {
+            color_attachments: &[],
+            input_attachments: &[],
+            depth_attachment: Some(vk::AttachmentReference {
+                attachment: 0,
+                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            }),
+        }];
+
+        // the maps are shared by all frames in flight: the previous frame must
+        // be done sampling before they are overwritten, and this frame's main
+        // pass must wait for them to be written
+        let subpass_deps = [
+            *vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                )
+                .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            *vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ),
+        ];
+
+        Self::create_render_pass(device, &attachment_descriptions, &subpasses, &subpass_deps)
+    }
+
+    /// Layers no light uses are still bound for sampling, so every layer starts
+    /// out in the layout the shadow pass leaves them in
+    fn init_shadow_map_layout(
+        device: &ash::Device,
+        upload: &UploadContext,
+        shadow_maps: &ShadowMaps,
+    ) -> Result<()> {
+        let command = Self::begin_single_time_commands(device, upload.graphics_command_pool)?;
+
+        let barrier = vk::ImageMemoryBarrier {
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: MAX_SHADOW_MAPS as u32,
+            },
+            ..Self::image_barrier(
+                shadow_maps.image,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::SHADER_READ,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            )
+        };
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+
+        Self::end_single_time_commands(
+            device,
+            upload.graphics_command_pool,
+            upload.graphics_queue,
+            upload.graphics_timeline.as_deref(),
+            command,
+        )
+    }
+
+    fn create_framebuffer(
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        attachments: &[vk::ImageView],
+        extent: vk::Extent2D,
+    ) -> Result<vk::Framebuffer> {
+        let create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+
+        let framebuffer = unsafe { device.create_framebuffer(&create_info, None)? };
+        Ok(framebuffer)
+    }
+
+    fn create_graphics_pipeline(
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        desc: &GraphicsPipelineDesc,
+    ) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+        let vert_shader_module = Self::create_shader_module(device, desc.vertex_shader)?;
+        let frag_shader_module = desc
+            .fragment_shader
+            .map(|shader| Self::create_shader_module(device, shader))
+            .transpose()?;
+
+        let mut shader_stages = vec![*vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vert_shader_module)
+            .name(&SHADER_ENTRYPOINT)];
+        if let Some(frag_shader_module) = frag_shader_module {
+            shader_stages.push(
+                *vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .module(frag_shader_module)
+                    .name(&SHADER_ENTRYPOINT),
+            );
+        }
+
+        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(desc.vertex_bindings)
+            .vertex_attribute_descriptions(desc.vertex_attributes);
+
+        let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(desc.topology)
+            .primitive_restart_enable(false);
+
+        // the actual viewport and scissor are set at record time
+        let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth_bias_enable(desc.depth_bias.is_some())
+            .depth_bias_constant_factor(desc.depth_bias.map_or(0.0, |(constant, _)| constant))
+            .depth_bias_clamp(0.0)
+            .depth_bias_slope_factor(desc.depth_bias.map_or(0.0, |(_, slope)| slope));
+
+        let multisampling_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .min_sample_shading(1.0)
+            .alpha_to_coverage_enable(false)
+            .alpha_to_one_enable(false);
+
+        let depth_stencil_state_create_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(desc.depth_test)
+            .depth_write_enable(desc.depth_test && desc.depth_write)
+            .depth_compare_op(vk::CompareOp::LESS)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false);
+
+        let dst_blend_factor = match desc.blend {
+            Blend::Replace => vk::BlendFactor::ZERO,
+            Blend::Additive => vk::BlendFactor::ONE,
+            Blend::Premultiplied => vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        };
+        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(dst_blend_factor)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(dst_blend_factor)
+            .alpha_blend_op(vk::BlendOp::ADD);
+        let color_blend_attachment_states =
+            vec![*color_blend_attachment_state; desc.color_attachments];
+
+        let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(&color_blend_attachment_states);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+
+        let dynamic_state_create_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(desc.set_layouts)
+            .push_constant_ranges(desc.push_constant_ranges);
+
+        let pipeline_layout =
+            unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None)? };
+
+        let pipeline_create_info = [*vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state_create_info)
+            .input_assembly_state(&input_assembly_state_create_info)
+            .viewport_state(&viewport_state_create_info)
+            .rasterization_state(&rasterization_state_create_info)
+            .multisample_state(&multisampling_state_create_info)
+            .depth_stencil_state(&depth_stencil_state_create_info)
+            .color_blend_state(&color_blend_state_create_info)
+            .dynamic_state(&dynamic_state_create_info)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(desc.subpass)
+            .base_pipeline_handle(vk::Pipeline::null())
+            .base_pipeline_index(-1)];
+
+        let graphics_pipelines = unsafe {
+            device
+                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_create_info, None)
+                .map_err(|(_, e)| e)?
+        };
+
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            if let Some(frag_shader_module) = frag_shader_module {
+                device.destroy_shader_module(frag_shader_module, None);
+            }
+        };
+
+        if graphics_pipelines.len() != 1 {
+            anyhow::bail!("failed to create exactly 1 graphics pipeline.",)
+        }
+
+        Ok((pipeline_layout, graphics_pipelines[0]))
+    }
+
+    /// The range a `T` pushed at `offset` takes up, failing if it doesn't
+    /// fit the device's `maxPushConstantsSize`, which may be as little as 128
+    /// bytes
+    fn push_constant_range<T>(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+    ) -> Result<vk::PushConstantRange> {
+        let size = std::mem::size_of::<T>() as u32;
+        let limit = unsafe { instance.get_physical_device_properties(physical_device) }
+            .limits
+            .max_push_constants_size;
+        if offset + size > limit {
+            anyhow::bail!(
+                "{} bytes of push constants at offset {} exceed the device's {} byte limit",
+                size,
+                offset,
+                limit
+            );
+        }
+        // offsets and sizes must be multiples of 4
+        if !offset.is_multiple_of(4) || !size.is_multiple_of(4) {
+            anyhow::bail!(
+                "push constants at offset {} of {} bytes aren't 4 byte aligned",
+                offset,
+                size
+            );
+        }
+
+        Ok(vk::PushConstantRange {
+            stage_flags: stages,
+            offset,
+            size,
+        })
+    }
+
+    /// `push_constant_ranges` are made with `push_constant_range`, which checks
+    /// they fit the device
+    fn create_compute_pipeline(
+        device: &ash::Device,
+        shader: &str,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+        let shader_module = Self::create_shader_module(device, shader)?;
+
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader_module)
+            .name(&SHADER_ENTRYPOINT);
+
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+
+        let pipeline_layout =
+            unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None)? };
+
+        let pipeline_create_info = [*vk::ComputePipelineCreateInfo::builder()
+            .stage(*stage)
+            .layout(pipeline_layout)
+            .base_pipeline_handle(vk::Pipeline::null())
+            .base_pipeline_index(-1)];
+
+        let compute_pipelines = unsafe {
+            device
+                .create_compute_pipelines(vk::PipelineCache::null(), &pipeline_create_info, None)
+                .map_err(|(_, e)| e)?
+        };
+
+        unsafe {
+            device.destroy_shader_module(shader_module, None);
+        };
+
+        if compute_pipelines.len() != 1 {
+            anyhow::bail!("failed to create exactly 1 compute pipeline.",)
         }
+
+        Ok((pipeline_layout, compute_pipelines[0]))
     }
 
-    fn draw_frame(&mut self) -> Result<()> {
-        let current_fence = [self.in_flight_fences[self.current_frame]];
+    #[allow(clippy::too_many_arguments)]
+    fn create_particle_system(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        upload: &UploadContext,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+    ) -> Result<ParticleSystem> {
+        let particles = particles::initial_particles(PARTICLE_COUNT);
 
-        unsafe {
-            self.logical_device
-                .wait_for_fences(&current_fence, true, u64::MAX)?;
+        let (buffer, buffer_memory) = Self::create_device_local_buffer(
+            instance,
+            device,
+            physical_device,
+            upload,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::SHADER_READ
+                | vk::AccessFlags::SHADER_WRITE
+                | vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            &particles,
+        )?;
+
+        let mut param_buffers = vec![];
+        let mut param_buffer_memories = vec![];
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let (param_buffer, param_buffer_memory) = Self::create_buffer(
+                instance,
+                device,
+                physical_device,
+                std::mem::size_of::<SimulationParams>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )?;
+            param_buffers.push(param_buffer);
+            param_buffer_memories.push(param_buffer_memory);
         }
 
-        let (image_index, _) = unsafe {
-            self.swapchain_loader.acquire_next_image(
-                self.swapchain,
-                u64::MAX,
-                self.image_available_semaphores[self.current_frame],
-                vk::Fence::null(),
-            )?
-        };
+        let bindings = [
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ];
 
-        let image_in_flight_fence = [self.images_in_flight[image_index as usize]];
+        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout =
+            unsafe { device.create_descriptor_set_layout(&layout_create_info, None)? };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+            },
+        ];
 
-        if image_in_flight_fence != [vk::Fence::null()] {
-            unsafe {
-                self.logical_device
-                    .wait_for_fences(&image_in_flight_fence, true, u64::MAX)?;
-            }
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = vec![descriptor_set_layout; MAX_FRAMES_IN_FLIGHT];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info)? };
+
+        for (&set, &param_buffer) in descriptor_sets.iter().zip(&param_buffers) {
+            let param_info = [vk::DescriptorBufferInfo {
+                buffer: param_buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }];
+            let particle_info = [vk::DescriptorBufferInfo {
+                buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }];
+
+            let writes = [
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&param_info),
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&particle_info),
+            ];
+
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
         }
 
-        self.images_in_flight[image_index as usize] = current_fence[0];
+        let (compute_pipeline_layout, compute_pipeline) = Self::create_compute_pipeline(
+            device,
+            "shaders/particle_comp.spv",
+            &[descriptor_set_layout],
+            &[],
+        )?;
+
+        let (graphics_pipeline_layout, graphics_pipeline) = Self::create_graphics_pipeline(
+            device,
+            render_pass,
+            &GraphicsPipelineDesc {
+                vertex_shader: "shaders/particle_vert.spv",
+                fragment_shader: Some("shaders/particle_frag.spv"),
+                set_layouts: &[],
+                push_constant_ranges: &[],
+                vertex_bindings: &Particle::binding_descriptions(),
+                vertex_attributes: &Particle::attribute_descriptions(),
+                topology: vk::PrimitiveTopology::POINT_LIST,
+                depth_test: false,
+                depth_write: false,
+                depth_bias: None,
+                subpass,
+                color_attachments: 1,
+                blend: Blend::Replace,
+            },
+        )?;
 
-        let wait_semaphores = [self.image_available_semaphores[self.current_frame]];
+        Ok(ParticleSystem {
+            buffer,
+            buffer_memory,
+            param_buffers,
+            param_buffer_memories,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            compute_pipeline_layout,
+            compute_pipeline,
+            graphics_pipeline_layout,
+            graphics_pipeline,
+        })
+    }
 
-        let signal_semaphores = [self.render_finished_semaphores[self.current_frame]];
+    fn create_gpu_culling(
+        device: &ash::Device,
+        draw_indirect_count: Option<DrawIndirectCount>,
+    ) -> Result<GpuCulling> {
+        // the parameters, then the draws, instances in and out, the visible
+        // instances' objects, indirect commands and counts
+        let bindings: Vec<_> = (0..7)
+            .map(|binding| {
+                *vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_type(if binding == 0 {
+                        vk::DescriptorType::UNIFORM_BUFFER
+                    } else {
+                        vk::DescriptorType::STORAGE_BUFFER
+                    })
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            })
+            .collect();
 
-        let command_buffers = [self.command_buffers[image_index as usize]];
+        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout =
+            unsafe { device.create_descriptor_set_layout(&layout_create_info, None)? };
 
-        let submit_info = vk::SubmitInfo::builder()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores);
+        let (pipeline_layout, pipeline) = Self::create_compute_pipeline(
+            device,
+            "shaders/cull_comp.spv",
+            &[descriptor_set_layout],
+            &[],
+        )?;
 
-        unsafe {
-            self.logical_device.reset_fences(&current_fence)?;
-            self.logical_device.queue_submit(
-                self.graphics_queue,
-                &[*submit_info],
-                self.in_flight_fences[self.current_frame],
-            )?;
-        }
+        Ok(GpuCulling {
+            descriptor_set_layout,
+            pipeline_layout,
+            pipeline,
+            draw_indirect_count,
+        })
+    }
 
-        let swapchains = [self.swapchain];
+    /// A window's culling buffers and descriptor sets, each frame in flight's
+    /// reading from that frame's instance buffer
+    fn create_cull_buffers(
+        &self,
+        gpu_culling: &GpuCulling,
+        instance_buffers: &[InstanceBuffer],
+    ) -> Result<CullBuffers> {
+        let device = &self.logical_device;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 6 * MAX_FRAMES_IN_FLIGHT as u32,
+            },
+        ];
 
-        let image_indices = [image_index];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = vec![gpu_culling.descriptor_set_layout; MAX_FRAMES_IN_FLIGHT];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info)? };
+
+        let create_buffer = |size: usize, usage, properties| {
+            Self::create_buffer(
+                &self.instance,
+                device,
+                self.physical_device,
+                size as vk::DeviceSize,
+                usage,
+                properties,
+            )
+        };
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+        let frames = descriptor_sets
+            .iter()
+            .zip(instance_buffers)
+            .map(|(&descriptor_set, instance_buffer)| {
+                let (params_buffer, params_memory) = create_buffer(
+                    std::mem::size_of::<CullParams>(),
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    host_visible,
+                )?;
+                let (draw_buffer, draw_memory) = create_buffer(
+                    MAX_OBJECTS * std::mem::size_of::<CullDraw>(),
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    host_visible,
+                )?;
+                let (visible_instance_buffer, visible_instance_memory) = create_buffer(
+                    MAX_INSTANCES * std::mem::size_of::<Instance>(),
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                )?;
+                let (visible_object_buffer, visible_object_memory) = create_buffer(
+                    MAX_INSTANCES * std::mem::size_of::<InstanceObject>(),
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                )?;
+                let (command_buffer, command_memory) = create_buffer(
+                    MAX_OBJECTS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>(),
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                )?;
+                // the visible instances, then each batch's visible draws, with
+                // a batch per draw at most
+                let (count_buffer, count_memory) = create_buffer(
+                    (1 + MAX_OBJECTS) * std::mem::size_of::<u32>(),
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+                    host_visible,
+                )?;
+
+                let buffer_infos = [
+                    params_buffer,
+                    draw_buffer,
+                    instance_buffer.buffer,
+                    visible_instance_buffer,
+                    visible_object_buffer,
+                    command_buffer,
+                    count_buffer,
+                ]
+                .map(|buffer| {
+                    [vk::DescriptorBufferInfo {
+                        buffer,
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                    }]
+                });
+                let writes: Vec<_> = buffer_infos
+                    .iter()
+                    .enumerate()
+                    .map(|(binding, info)| {
+                        *vk::WriteDescriptorSet::builder()
+                            .dst_set(descriptor_set)
+                            .dst_binding(binding as u32)
+                            .descriptor_type(if binding == 0 {
+                                vk::DescriptorType::UNIFORM_BUFFER
+                            } else {
+                                vk::DescriptorType::STORAGE_BUFFER
+                            })
+                            .buffer_info(info)
+                    })
+                    .collect();
+                unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+                Ok(CullFrame {
+                    params_buffer,
+                    params_memory,
+                    draw_buffer,
+                    draw_memory,
+                    visible_instance_buffer,
+                    visible_instance_memory,
+                    visible_object_buffer,
+                    visible_object_memory,
+                    command_buffer,
+                    command_memory,
+                    count_buffer,
+                    count_memory,
+                    descriptor_set,
+                    draw_count: 0,
+                    batches: vec![],
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        let present_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(&signal_semaphores)
-            .swapchains(&swapchains)
-            .image_indices(&image_indices);
+        Ok(CullBuffers {
+            descriptor_pool,
+            frames,
+        })
+    }
 
-        unsafe {
-            self.swapchain_loader
-                .queue_present(self.presentation_queue, &present_info)?
+    /// Line list pipelines with and without the depth test, which never write
+    /// depth as the deferred path's is read only by then
+    fn create_debug_draw_pipelines(
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        camera_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<DebugDrawPipelines> {
+        let create_pipeline = |depth_test| {
+            Self::create_graphics_pipeline(
+                device,
+                render_pass,
+                &GraphicsPipelineDesc {
+                    vertex_shader: "shaders/debug_vert.spv",
+                    fragment_shader: Some("shaders/debug_frag.spv"),
+                    set_layouts: &[camera_set_layout],
+                    push_constant_ranges: &[],
+                    vertex_bindings: &DebugVertex::binding_descriptions(),
+                    vertex_attributes: &DebugVertex::attribute_descriptions(),
+                    topology: vk::PrimitiveTopology::LINE_LIST,
+                    depth_test,
+                    depth_write: false,
+                    depth_bias: None,
+                    subpass,
+                    color_attachments: 1,
+                    blend: Blend::Premultiplied,
+                },
+            )
         };
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        let (pipeline_layout, depth_tested_pipeline) = create_pipeline(true)?;
+        // the layouts are identical, so both can be bound with the first
+        let (overlay_layout, overlay_pipeline) = create_pipeline(false)?;
+        unsafe { device.destroy_pipeline_layout(overlay_layout, None) };
 
-        Ok(())
+        Ok(DebugDrawPipelines {
+            pipeline_layout,
+            depth_tested_pipeline,
+            overlay_pipeline,
+        })
     }
 
-    fn create_sync_objects(
+    /// `range` is what each descriptor sees of the buffer. `shared_images` are
+    /// bound from binding 1 on, the same in every set.
+    fn create_uniforms_layout(
         device: &ash::Device,
-        swapchain_images: &Vec<vk::Image>,
-    ) -> Result<(
-        Vec<vk::Semaphore>,
-        Vec<vk::Semaphore>,
-        Vec<vk::Fence>,
-        Vec<vk::Fence>,
-    )> {
-        let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
-        let fence_create_info =
-            vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
-        let mut image_available_semaphores = vec![];
-        let mut render_finished_semaphores = vec![];
-        let mut in_flight_fences = vec![];
-        let images_in_flight = vec![vk::Fence::null(); swapchain_images.len()];
-        unsafe {
-            for _ in 0..MAX_FRAMES_IN_FLIGHT {
-                image_available_semaphores
-                    .push(device.create_semaphore(&semaphore_create_info, None)?);
-                render_finished_semaphores
-                    .push(device.create_semaphore(&semaphore_create_info, None)?);
-                in_flight_fences.push(device.create_fence(&fence_create_info, None)?);
+        size: vk::DeviceSize,
+        descriptor_type: vk::DescriptorType,
+        range: vk::DeviceSize,
+        stages: vk::ShaderStageFlags,
+        shared_images: &[(vk::DescriptorType, vk::DescriptorImageInfo)],
+    ) -> Result<UniformsLayout> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = std::iter::once(descriptor_type)
+            .chain(shared_images.iter().map(|&(ty, _)| ty))
+            .zip(0..)
+            .map(|(ty, binding)| {
+                *vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_type(ty)
+                    .descriptor_count(1)
+                    .stage_flags(stages)
+            })
+            .collect();
+
+        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout =
+            unsafe { device.create_descriptor_set_layout(&layout_create_info, None)? };
+
+        Ok(UniformsLayout {
+            descriptor_set_layout,
+            descriptor_type,
+            size,
+            range,
+            shared_images: shared_images.to_vec(),
+        })
+    }
+
+    /// A window's uniforms for each of its swapchain images
+    fn create_image_uniforms(
+        &self,
+        layout: &UniformsLayout,
+        image_count: usize,
+    ) -> Result<PerImageUniforms> {
+        Self::create_per_image_uniforms(
+            &self.instance,
+            &self.logical_device,
+            self.physical_device,
+            layout,
+            image_count,
+        )
+    }
+
+    fn create_per_image_uniforms(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        layout: &UniformsLayout,
+        swapchain_image_count: usize,
+    ) -> Result<PerImageUniforms> {
+        let mut buffers = vec![];
+        let mut memories = vec![];
+        let usage = match layout.descriptor_type {
+            vk::DescriptorType::STORAGE_BUFFER => vk::BufferUsageFlags::STORAGE_BUFFER,
+            _ => vk::BufferUsageFlags::UNIFORM_BUFFER,
+        };
+        for _ in 0..swapchain_image_count {
+            let (buffer, memory) = Self::create_buffer(
+                instance,
+                device,
+                physical_device,
+                layout.size,
+                usage,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )?;
+            buffers.push(buffer);
+            memories.push(memory);
+        }
+
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = std::iter::once(layout.descriptor_type)
+            .chain(layout.shared_images.iter().map(|&(ty, _)| ty))
+            .map(|ty| vk::DescriptorPoolSize {
+                ty,
+                descriptor_count: swapchain_image_count as u32,
+            })
+            .collect();
+
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(swapchain_image_count as u32);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = vec![layout.descriptor_set_layout; swapchain_image_count];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info)? };
+
+        for (&set, &buffer) in descriptor_sets.iter().zip(&buffers) {
+            let buffer_info = [vk::DescriptorBufferInfo {
+                buffer,
+                offset: 0,
+                range: layout.range,
+            }];
+
+            let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = layout
+                .shared_images
+                .iter()
+                .map(|&(_, info)| [info])
+                .collect();
+
+            let mut writes = vec![*vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(0)
+                .descriptor_type(layout.descriptor_type)
+                .buffer_info(&buffer_info)];
+            for ((&(ty, _), image_info), binding) in
+                layout.shared_images.iter().zip(&image_infos).zip(1..)
+            {
+                writes.push(
+                    *vk::WriteDescriptorSet::builder()
+                        .dst_set(set)
+                        .dst_binding(binding)
+                        .descriptor_type(ty)
+                        .image_info(image_info),
+                );
             }
 
-            Ok((
-                image_available_semaphores,
-                render_finished_semaphores,
-                in_flight_fences,
-                images_in_flight,
-            ))
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
         }
-    }
 
-    fn create_instance(
-        window: &Window,
-        enable_validation_layer: bool,
-    ) -> Result<(ash::Entry, ash::Instance)> {
-        let app_info = vk::ApplicationInfo::builder()
-            .application_name(&APP_NAME)
-            .application_version(vk::make_api_version(1, 0, 0, 0))
-            .engine_name(&ENGINE_NAME)
-            .engine_version(vk::make_api_version(1, 0, 0, 0))
-            .api_version(vk::make_api_version(1, 0, 0, 0));
+        Ok(PerImageUniforms {
+            descriptor_pool,
+            descriptor_sets,
+            buffers,
+            memories,
+        })
+    }
 
-        let entry = unsafe { ash::Entry::new()? };
+    fn find_memory_type(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        type_filter: u32,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<u32> {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        (0..memory_properties.memory_type_count)
+            .find(|&i| {
+                type_filter & (1 << i) != 0
+                    && memory_properties.memory_types[i as usize]
+                        .property_flags
+                        .contains(properties)
+            })
+            .context("failed to find a suitable memory type")
+    }
 
-        //let extensions = ash_window::enumerate_required_extensions(self.window.as_ref().unwrap())?;
-        let extensions = Self::get_required_extension(window, enable_validation_layer)?;
+    fn create_buffer(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe { device.create_buffer(&create_info, None)? };
+
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(requirements.size)
+            .memory_type_index(Self::find_memory_type(
+                instance,
+                physical_device,
+                requirements.memory_type_bits,
+                properties,
+            )?);
 
-        let extension_ptrs: Vec<*const c_char> = extensions.iter().map(|s| s.as_ptr()).collect();
+        let memory = unsafe { device.allocate_memory(&alloc_info, None)? };
 
-        Self::check_extension_support(&entry, &extension_ptrs)?;
+        unsafe { device.bind_buffer_memory(buffer, memory, 0)? };
 
-        let validation_layers = Self::get_required_validation_layers(enable_validation_layer)?;
+        Ok((buffer, memory))
+    }
 
-        let validation_layer_ptrs = validation_layers.iter().map(|l| l.as_ptr()).collect();
+    /// Creates a device local buffer holding `data`, uploaded through a
+    /// temporary staging buffer. `dst_stage` and `dst_access` describe the
+    /// buffer's first use on the graphics queue.
+    #[allow(clippy::too_many_arguments)]
+    fn create_device_local_buffer<T: Copy>(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        upload: &UploadContext,
+        usage: vk::BufferUsageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+        data: &[T],
+    ) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+
+        let (staging_buffer, staging_memory) = Self::create_buffer(
+            instance,
+            device,
+            physical_device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
 
-        Self::check_validation_layer_support(&entry, &validation_layer_ptrs)?;
+        unsafe { Self::write_to_memory(device, staging_memory, data)? };
 
-        let mut instance_info = vk::InstanceCreateInfo::builder()
-            .application_info(&app_info)
-            .enabled_extension_names(&extension_ptrs)
-            .enabled_layer_names(&validation_layer_ptrs);
+        let (buffer, memory) = Self::create_buffer(
+            instance,
+            device,
+            physical_device,
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
 
-        let mut debug_create_info;
+        Self::copy_buffer(
+            device,
+            upload,
+            staging_buffer,
+            buffer,
+            size,
+            dst_stage,
+            dst_access,
+        )?;
 
-        if enable_validation_layer {
-            debug_create_info = Self::populate_debug_messenger_create_info()?;
-            instance_info = instance_info.push_next(&mut debug_create_info);
+        unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_memory, None);
         }
 
-        let instance = unsafe { entry.create_instance(&instance_info, None)? };
-
-        Ok((entry, instance))
+        Ok((buffer, memory))
     }
 
-    fn create_command_pool(
+    unsafe fn write_to_memory<T: Copy>(
         device: &ash::Device,
-        indices: &QueueFamilyIndices,
-    ) -> Result<vk::CommandPool> {
-        let create_info = vk::CommandPoolCreateInfo::builder()
-            .queue_family_index(indices.graphics_family.unwrap())
-            .flags(vk::CommandPoolCreateFlags::empty());
-
-        let command_pool = unsafe { device.create_command_pool(&create_info, None)? };
-        Ok(command_pool)
+        memory: vk::DeviceMemory,
+        data: &[T],
+    ) -> Result<()> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let mapped = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+        std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut T, data.len());
+        device.unmap_memory(memory);
+        Ok(())
     }
 
-    fn create_command_buffers(
+    unsafe fn read_from_memory<T: Copy>(
         device: &ash::Device,
-        command_pool: &vk::CommandPool,
-        render_pass: vk::RenderPass,
-        framebuffers: &Vec<vk::Framebuffer>,
-        swapchain_extent: vk::Extent2D,
-        graphics_pipeline: vk::Pipeline,
-    ) -> Result<Vec<vk::CommandBuffer>> {
-        let alloc_info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(*command_pool)
-            .command_buffer_count(framebuffers.len() as u32)
-            .level(vk::CommandBufferLevel::PRIMARY);
-
-        let command_buffers = unsafe { device.allocate_command_buffers(&alloc_info)? };
-
-        for (i, &command) in command_buffers.iter().enumerate() {
-            let begin_info = vk::CommandBufferBeginInfo::builder();
+        memory: vk::DeviceMemory,
+        len: usize,
+    ) -> Result<Vec<T>> {
+        let size = (len * std::mem::size_of::<T>()) as vk::DeviceSize;
+        let mapped = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+        let data = std::slice::from_raw_parts(mapped as *const T, len).to_vec();
+        device.unmap_memory(memory);
+        Ok(data)
+    }
 
-            unsafe {
-                device.begin_command_buffer(command, &begin_info)?;
-            }
+    /// Copies `src` into `dst` on the transfer queue and makes the result
+    /// available to `dst_stage` on the graphics queue, transferring queue
+    /// family ownership of `dst` if the two queues are from different families.
+    fn copy_buffer(
+        device: &ash::Device,
+        upload: &UploadContext,
+        src: vk::Buffer,
+        dst: vk::Buffer,
+        size: vk::DeviceSize,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) -> Result<()> {
+        let command = Self::begin_single_time_commands(device, upload.transfer_command_pool)?;
 
-            let render_pass_info = vk::RenderPassBeginInfo::builder()
-                .render_pass(render_pass)
-                .framebuffer(framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: swapchain_extent,
-                })
-                .clear_values(&[vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    },
-                }]);
+        unsafe {
+            device.cmd_copy_buffer(
+                command,
+                src,
+                dst,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size,
+                }],
+            );
+        }
 
+        if upload.transfer_family == upload.graphics_family {
             unsafe {
-                device.cmd_begin_render_pass(
-                    command,
-                    &render_pass_info,
-                    vk::SubpassContents::INLINE,
-                );
-                device.cmd_bind_pipeline(
+                device.cmd_pipeline_barrier(
                     command,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    graphics_pipeline,
+                    vk::PipelineStageFlags::TRANSFER,
+                    dst_stage,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[Self::buffer_barrier(
+                        dst,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        dst_access,
+                        vk::QUEUE_FAMILY_IGNORED,
+                        vk::QUEUE_FAMILY_IGNORED,
+                    )],
+                    &[],
                 );
-                device.cmd_draw(command, 3, 1, 0, 0);
-                device.cmd_end_render_pass(command);
-                device.end_command_buffer(command)?;
             }
-        }
-        Ok(command_buffers)
-    }
-
-    fn create_render_pass(
-        device: &ash::Device,
-        swapchain_format: vk::Format,
-    ) -> Result<vk::RenderPass> {
-        let color_attachment_descriptions = [*vk::AttachmentDescription::builder()
-            .format(swapchain_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)];
-
-        let attachment_refs = [vk::AttachmentReference {
-            attachment: 0,
-            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        }];
 
-        let subpass = [*vk::SubpassDescription::builder()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&attachment_refs)];
+            return Self::end_single_time_commands(
+                device,
+                upload.transfer_command_pool,
+                upload.transfer_queue,
+                upload.transfer_timeline.as_deref(),
+                command,
+            );
+        }
 
-        let subpass_deps = [*vk::SubpassDependency::builder()
-            .src_subpass(vk::SUBPASS_EXTERNAL)
-            .dst_subpass(0)
-            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .src_access_mask(vk::AccessFlags::empty())
-            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)];
+        // release on the transfer queue; dst access is ignored for a release
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[Self::buffer_barrier(
+                    dst,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::empty(),
+                    upload.transfer_family,
+                    upload.graphics_family,
+                )],
+                &[],
+            );
+        }
 
-        let create_info = vk::RenderPassCreateInfo::builder()
-            .attachments(&color_attachment_descriptions)
-            .dependencies(&subpass_deps)
-            .subpasses(&subpass);
+        Self::end_single_time_commands(
+            device,
+            upload.transfer_command_pool,
+            upload.transfer_queue,
+            upload.transfer_timeline.as_deref(),
+            command,
+        )?;
 
-        let render_pass = unsafe { device.create_render_pass(&create_info, None)? };
+        // matching acquire on the graphics queue; src access is ignored for an acquire
+        let command = Self::begin_single_time_commands(device, upload.graphics_command_pool)?;
 
-        Ok(render_pass)
-    }
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[Self::buffer_barrier(
+                    dst,
+                    vk::AccessFlags::empty(),
+                    dst_access,
+                    upload.transfer_family,
+                    upload.graphics_family,
+                )],
+                &[],
+            );
+        }
 
-    fn create_frame_buffers(
-        device: &ash::Device,
-        image_views: &Vec<vk::ImageView>,
-        render_pass: &vk::RenderPass,
-        extents: vk::Extent2D,
-    ) -> Result<Vec<vk::Framebuffer>> {
-        let mut framebuffers = vec![];
-        for &view in image_views {
-            let views = &[view];
-            let create_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(*render_pass)
-                .attachments(views)
-                .width(extents.width)
-                .height(extents.height)
-                .layers(1);
-
-            let framebuffer = unsafe { device.create_framebuffer(&create_info, None) }?;
-            framebuffers.push(framebuffer);
-        }
-        Ok(framebuffers)
+        Self::end_single_time_commands(
+            device,
+            upload.graphics_command_pool,
+            upload.graphics_queue,
+            upload.graphics_timeline.as_deref(),
+            command,
+        )
     }
 
-    fn create_graphics_pipeline(
+    /// Like `copy_buffer`, but into all of a single mip level colour image,
+    /// which ends up in `SHADER_READ_ONLY_OPTIMAL` layout for fragment shaders.
+    fn copy_buffer_to_image(
         device: &ash::Device,
-        render_pass: vk::RenderPass,
-        swapchain_extent: vk::Extent2D,
-    ) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
-        let vert_shader_module = Self::create_shader_module(device, "shaders/vert.spv")?;
-        let frag_shader_module = Self::create_shader_module(device, "shaders/frag.spv")?;
-
-        let shader_stages = [
-            *vk::PipelineShaderStageCreateInfo::builder()
-                .stage(vk::ShaderStageFlags::VERTEX)
-                .module(vert_shader_module)
-                .name(&SHADER_ENTRYPOINT),
-            *vk::PipelineShaderStageCreateInfo::builder()
-                .stage(vk::ShaderStageFlags::FRAGMENT)
-                .module(frag_shader_module)
-                .name(&SHADER_ENTRYPOINT),
-        ];
-
-        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_binding_descriptions(&[])
-            .vertex_attribute_descriptions(&[]);
+        upload: &UploadContext,
+        src: vk::Buffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+    ) -> Result<()> {
+        let command = Self::begin_single_time_commands(device, upload.transfer_command_pool)?;
 
-        let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .primitive_restart_enable(false);
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[Self::image_barrier(
+                    image,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::QUEUE_FAMILY_IGNORED,
+                    vk::QUEUE_FAMILY_IGNORED,
+                )],
+            );
+
+            device.cmd_copy_buffer_to_image(
+                command,
+                src,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[*vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    })],
+            );
+        }
 
-        let viewport = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: swapchain_extent.width as f32,
-            height: swapchain_extent.height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
+        let same_family = upload.transfer_family == upload.graphics_family;
+        let (src_family, dst_family) = if same_family {
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        } else {
+            (upload.transfer_family, upload.graphics_family)
+        };
 
-        let scissor = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain_extent,
-        }];
+        // the layout transition happens once, in the release when there is one;
+        // both halves of the pair must still describe it
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command,
+                vk::PipelineStageFlags::TRANSFER,
+                if same_family {
+                    vk::PipelineStageFlags::FRAGMENT_SHADER
+                } else {
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE
+                },
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[Self::image_barrier(
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    if same_family {
+                        vk::AccessFlags::SHADER_READ
+                    } else {
+                        vk::AccessFlags::empty()
+                    },
+                    src_family,
+                    dst_family,
+                )],
+            );
+        }
 
-        let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewport)
-            .scissors(&scissor);
+        Self::end_single_time_commands(
+            device,
+            upload.transfer_command_pool,
+            upload.transfer_queue,
+            upload.transfer_timeline.as_deref(),
+            command,
+        )?;
 
-        let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::CLOCKWISE)
-            .depth_bias_enable(false)
-            .depth_bias_constant_factor(0.0)
-            .depth_bias_clamp(0.0)
-            .depth_bias_slope_factor(0.0);
+        if same_family {
+            return Ok(());
+        }
 
-        let multisampling_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-            .min_sample_shading(1.0)
-            .alpha_to_coverage_enable(false)
-            .alpha_to_one_enable(false);
+        let command = Self::begin_single_time_commands(device, upload.graphics_command_pool)?;
 
-        let color_blend_attachment_state = [*vk::PipelineColorBlendAttachmentState::builder()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
-            .blend_enable(true)
-            .src_color_blend_factor(vk::BlendFactor::ONE)
-            .dst_color_blend_factor(vk::BlendFactor::ZERO)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::ONE)
-            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-            .alpha_blend_op(vk::BlendOp::ADD)];
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[Self::image_barrier(
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::SHADER_READ,
+                    upload.transfer_family,
+                    upload.graphics_family,
+                )],
+            );
+        }
 
-        let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
-            .logic_op_enable(false)
-            .logic_op(vk::LogicOp::COPY)
-            .attachments(&color_blend_attachment_state);
+        Self::end_single_time_commands(
+            device,
+            upload.graphics_command_pool,
+            upload.graphics_queue,
+            upload.graphics_timeline.as_deref(),
+            command,
+        )
+    }
 
-        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::LINE_WIDTH];
+    fn begin_single_time_commands(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+    ) -> Result<vk::CommandBuffer> {
+        let alloc_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(command_pool)
+            .command_buffer_count(1)
+            .level(vk::CommandBufferLevel::PRIMARY);
 
-        let _dynamic_state_create_info =
-            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let command = unsafe { device.allocate_command_buffers(&alloc_info)?[0] };
 
-        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
-            .set_layouts(&[])
-            .push_constant_ranges(&[]);
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-        let pipeline_layout =
-            unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None)? };
+        unsafe { device.begin_command_buffer(command, &begin_info)? };
 
-        let pipeline_create_info = [*vk::GraphicsPipelineCreateInfo::builder()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_state_create_info)
-            .input_assembly_state(&input_assembly_state_create_info)
-            .viewport_state(&viewport_state_create_info)
-            .rasterization_state(&rasterization_state_create_info)
-            .multisample_state(&multisampling_state_create_info)
-            .color_blend_state(&color_blend_state_create_info)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0)
-            .base_pipeline_handle(vk::Pipeline::null())
-            .base_pipeline_index(-1)];
+        Ok(command)
+    }
 
-        let graphics_pipelines = unsafe {
-            device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_create_info, None)
-                .map_err(|(_, e)| e)?
-        };
+    /// Submits the commands and waits for them, just them given the queue's
+    /// timeline and everything on the queue otherwise
+    fn end_single_time_commands(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        timeline: Option<&Timeline>,
+        command: vk::CommandBuffer,
+    ) -> Result<()> {
+        let command_buffers = [command];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
 
         unsafe {
-            device.destroy_shader_module(vert_shader_module, None);
-            device.destroy_shader_module(frag_shader_module, None);
-        };
-
-        if graphics_pipelines.len() != 1 {
-            anyhow::bail!("failed to create exactly 1 graphics pipeline.",)
+            device.end_command_buffer(command)?;
+            match timeline {
+                Some(timeline) => {
+                    let value = timeline.next_value();
+                    let signal_semaphores = [timeline.semaphore];
+                    let signal_values = [value];
+                    let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+                        .signal_semaphore_values(&signal_values);
+                    let submit_info = submit_info
+                        .signal_semaphores(&signal_semaphores)
+                        .push_next(&mut timeline_info);
+                    device.queue_submit(queue, &[*submit_info], vk::Fence::null())?;
+                    timeline.wait(device, value)?;
+                }
+                None => {
+                    device.queue_submit(queue, &[*submit_info], vk::Fence::null())?;
+                    device.queue_wait_idle(queue)?;
+                }
+            }
+            device.free_command_buffers(command_pool, &command_buffers);
         }
 
-        Ok((pipeline_layout, graphics_pipelines[0]))
+        Ok(())
     }
 
     fn create_shader_module(device: &ash::Device, path: &str) -> Result<vk::ShaderModule> {
@@ -674,28 +5797,46 @@ impl VulkanApp {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_swapchain(
-        instance: &ash::Instance,
-        device: &ash::Device,
         physical_device: vk::PhysicalDevice,
         surface: vk::SurfaceKHR,
         surface_loader: &Surface,
+        swapchain_loader: &Swapchain,
         window: &Window,
         queue_indices: &QueueFamilyIndices,
-    ) -> Result<(vk::SwapchainKHR, Swapchain, vk::Format, vk::Extent2D)> {
+        settings: &RenderSettings,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<(vk::SwapchainKHR, vk::Format, OutputSpace, vk::Extent2D)> {
         let support_details =
             Self::query_swap_chain_support(physical_device, surface, surface_loader)?;
 
-        let surface_format = Self::choose_swap_surface_format(&support_details.formats)?;
-        let present_mode = Self::choose_swap_present_mode(support_details.present_modes)?;
+        let (surface_format, output_space) =
+            Self::choose_swap_surface_format(&support_details.formats, settings.dynamic_range)?;
+        debug!("Swapchain surface format: {:?}", surface_format);
+        let present_mode =
+            Self::choose_swap_present_mode(&support_details.present_modes, settings.present_mode);
+        log::info!("Present mode {:?}", present_mode);
         let extent = Self::choose_swap_extent(support_details.capabilities, window)?;
 
+        let min_image_count = support_details.capabilities.min_image_count;
         let max_image_count = support_details.capabilities.max_image_count;
-        let mut image_count = support_details.capabilities.min_image_count + 1;
+        let mut image_count = settings.image_count.unwrap_or(min_image_count + 1);
 
         if max_image_count > 0 && image_count > max_image_count {
             image_count = max_image_count;
         }
+        if image_count < min_image_count {
+            image_count = min_image_count;
+        }
+        if matches!(settings.image_count, Some(count) if count != image_count) {
+            log::warn!(
+                "surface allows {} to {} swapchain images (0 for unlimited), asking for {}",
+                min_image_count,
+                max_image_count,
+                image_count
+            );
+        }
 
         let mut create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surface)
@@ -708,7 +5849,8 @@ impl VulkanApp {
             .pre_transform(support_details.capabilities.current_transform)
             .present_mode(present_mode)
             .clipped(true)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE);
+            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .old_swapchain(old_swapchain);
 
         let indices = [
             queue_indices.graphics_family.unwrap(),
@@ -724,11 +5866,9 @@ impl VulkanApp {
                 .queue_family_indices(&[]);
         }
 
-        let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, device);
-
         let swapchain = unsafe { swapchain_loader.create_swapchain(&create_info, None)? };
 
-        Ok((swapchain, swapchain_loader, surface_format.format, extent))
+        Ok((swapchain, surface_format.format, output_space, extent))
     }
 
     fn create_image_views(
@@ -738,32 +5878,693 @@ impl VulkanApp {
     ) -> Result<Vec<vk::ImageView>> {
         let mut image_views = vec![];
         for image in images.iter() {
-            let create_info = vk::ImageViewCreateInfo::builder()
-                .image(*image)
-                .format(format)
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .components(vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::IDENTITY,
-                    g: vk::ComponentSwizzle::IDENTITY,
-                    b: vk::ComponentSwizzle::IDENTITY,
-                    a: vk::ComponentSwizzle::IDENTITY,
-                })
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                });
-
-            let view = unsafe { device.create_image_view(&create_info, None)? };
-
+            let view =
+                Self::create_image_view(device, *image, format, vk::ImageAspectFlags::COLOR)?;
             image_views.push(view);
         }
 
         Ok(image_views)
     }
 
+    fn create_image_view(
+        device: &ash::Device,
+        image: vk::Image,
+        format: vk::Format,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<vk::ImageView> {
+        Self::create_image_layers_view(
+            device,
+            image,
+            format,
+            aspect_mask,
+            vk::ImageViewType::TYPE_2D,
+            0,
+            1,
+        )
+    }
+
+    fn create_image_layers_view(
+        device: &ash::Device,
+        image: vk::Image,
+        format: vk::Format,
+        aspect_mask: vk::ImageAspectFlags,
+        view_type: vk::ImageViewType,
+        base_array_layer: u32,
+        layer_count: u32,
+    ) -> Result<vk::ImageView> {
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .format(format)
+            .view_type(view_type)
+            .components(vk::ComponentMapping {
+                r: vk::ComponentSwizzle::IDENTITY,
+                g: vk::ComponentSwizzle::IDENTITY,
+                b: vk::ComponentSwizzle::IDENTITY,
+                a: vk::ComponentSwizzle::IDENTITY,
+            })
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer,
+                layer_count,
+            });
+
+        let view = unsafe { device.create_image_view(&create_info, None)? };
+        Ok(view)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_image(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        extent: vk::Extent2D,
+        array_layers: u32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<(vk::Image, vk::DeviceMemory)> {
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(array_layers)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(usage)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let image = unsafe { device.create_image(&create_info, None)? };
+
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let memory_type = Self::find_memory_type(
+            instance,
+            physical_device,
+            requirements.memory_type_bits,
+            properties,
+        )?;
+
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type);
+
+        let memory = unsafe { device.allocate_memory(&alloc_info, None)? };
+        unsafe { device.bind_image_memory(image, memory, 0)? };
+
+        Ok((image, memory))
+    }
+
+    /// First of the usual depth formats with all of `features` when optimally tiled
+    fn find_depth_format(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        features: vk::FormatFeatureFlags,
+    ) -> Result<vk::Format> {
+        let candidates = [
+            vk::Format::D32_SFLOAT,
+            vk::Format::D32_SFLOAT_S8_UINT,
+            vk::Format::D24_UNORM_S8_UINT,
+        ];
+
+        candidates
+            .iter()
+            .copied()
+            .find(|&format| {
+                let properties = unsafe {
+                    instance.get_physical_device_format_properties(physical_device, format)
+                };
+                properties.optimal_tiling_features.contains(features)
+            })
+            .ok_or_else(|| anyhow::anyhow!("no supported depth format"))
+    }
+
+    fn create_depth_resources(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> Result<(vk::Image, vk::DeviceMemory, vk::ImageView)> {
+        let (image, memory) = Self::create_image(
+            instance,
+            device,
+            physical_device,
+            extent,
+            1,
+            format,
+            // the deferred lighting subpass reads depth back to find positions
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        let view = Self::create_image_view(device, image, format, vk::ImageAspectFlags::DEPTH)?;
+
+        Ok((image, memory, view))
+    }
+
+    /// G-buffer images at the swapchain's size, bound for the lighting subpass
+    /// alongside the depth buffer, and the pipeline that lights them
+    #[allow(clippy::too_many_arguments)]
+    fn create_gbuffer(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        extent: vk::Extent2D,
+        depth_image_view: vk::ImageView,
+        render_pass: vk::RenderPass,
+        camera_set_layout: vk::DescriptorSetLayout,
+        light_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<GBuffer> {
+        let mut images = vec![];
+        let mut memories = vec![];
+        let mut views = vec![];
+        for &format in &GBUFFER_FORMATS {
+            let (image, memory) = Self::create_image(
+                instance,
+                device,
+                physical_device,
+                extent,
+                1,
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::INPUT_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )?;
+            images.push(image);
+            memories.push(memory);
+            views.push(Self::create_image_view(
+                device,
+                image,
+                format,
+                vk::ImageAspectFlags::COLOR,
+            )?);
+        }
+
+        let inputs: Vec<(vk::ImageView, vk::ImageLayout)> = views
+            .iter()
+            .map(|&view| (view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .chain(std::iter::once((
+                depth_image_view,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            )))
+            .collect();
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..inputs.len() as u32)
+            .map(|binding| {
+                *vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            })
+            .collect();
+
+        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout =
+            unsafe { device.create_descriptor_set_layout(&layout_create_info, None)? };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: inputs.len() as u32,
+        }];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = [descriptor_set_layout];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] };
+
+        let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = inputs
+            .iter()
+            .map(|&(image_view, image_layout)| {
+                [vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view,
+                    image_layout,
+                }]
+            })
+            .collect();
+        let writes: Vec<vk::WriteDescriptorSet> = image_infos
+            .iter()
+            .zip(0..)
+            .map(|(image_info, binding)| {
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding)
+                    .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                    .image_info(image_info)
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let (lighting_pipeline_layout, lighting_pipeline) = Self::create_graphics_pipeline(
+            device,
+            render_pass,
+            &GraphicsPipelineDesc {
+                vertex_shader: "shaders/deferred_vert.spv",
+                fragment_shader: Some("shaders/deferred_frag.spv"),
+                set_layouts: &[camera_set_layout, descriptor_set_layout, light_set_layout],
+                push_constant_ranges: &[],
+                vertex_bindings: &[],
+                vertex_attributes: &[],
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                depth_test: false,
+                depth_write: false,
+                depth_bias: None,
+                subpass: 1,
+                color_attachments: 1,
+                blend: Blend::Additive,
+            },
+        )?;
+
+        Ok(GBuffer {
+            images,
+            memories,
+            views,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            lighting_pipeline_layout,
+            lighting_pipeline,
+        })
+    }
+
+    /// The HDR scene target, the targets and pipelines of every post effect,
+    /// and the pass copying the final result to the swapchain
+    #[allow(clippy::too_many_arguments)]
+    fn create_post_processing(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        swapchain_format: vk::Format,
+        swapchain_image_views: &[vk::ImageView],
+        extent: vk::Extent2D,
+        settings: &PostSettings,
+        scene_render_pass: vk::RenderPass,
+        scene_attachments: &[vk::ImageView],
+        uniforms_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<PostProcessing> {
+        let render_pass = Self::create_post_render_pass(
+            device,
+            HDR_FORMAT,
+            vk::AttachmentLoadOp::DONT_CARE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+        let blend_render_pass = Self::create_post_render_pass(
+            device,
+            HDR_FORMAT,
+            vk::AttachmentLoadOp::LOAD,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+        let output_render_pass = Self::create_post_render_pass(
+            device,
+            swapchain_format,
+            vk::AttachmentLoadOp::DONT_CARE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        )?;
+
+        let sampler = Self::create_sampler(
+            device,
+            &SamplerDesc {
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                ..Default::default()
+            },
+        )?;
+
+        let source_set_layout = Self::create_source_set_layout(device)?;
+
+        let bloom_extents: Vec<vk::Extent2D> = (1..=settings.bloom_levels)
+            .map(|level| vk::Extent2D {
+                width: (extent.width >> level).max(1),
+                height: (extent.height >> level).max(1),
+            })
+            .collect();
+
+        // the scene, the ping-pong pair, then the bloom pyramid
+        let target_count = 3 + bloom_extents.len() as u32;
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: target_count,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: target_count,
+            },
+        ];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(target_count);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let create_target = |extent, render_pass, extra_attachments: &[vk::ImageView]| {
+            Self::create_render_target(
+                instance,
+                device,
+                physical_device,
+                extent,
+                render_pass,
+                extra_attachments,
+                descriptor_pool,
+                source_set_layout,
+                sampler,
+            )
+        };
+
+        let scene = create_target(extent, scene_render_pass, scene_attachments)?;
+        let ping_pong = [
+            create_target(extent, render_pass, &[])?,
+            create_target(extent, render_pass, &[])?,
+        ];
+        let bloom = bloom_extents
+            .iter()
+            .map(|&extent| create_target(extent, render_pass, &[]))
+            .collect::<Result<Vec<_>>>()?;
+
+        let output_framebuffers = swapchain_image_views
+            .iter()
+            .map(|&view| Self::create_framebuffer(device, output_render_pass, &[view], extent))
+            .collect::<Result<Vec<_>>>()?;
+
+        let set_layouts = [uniforms_set_layout, source_set_layout, source_set_layout];
+        let create_pipeline_and_layout = |render_pass, fragment_shader, blend| {
+            Self::create_graphics_pipeline(
+                device,
+                render_pass,
+                &GraphicsPipelineDesc {
+                    vertex_shader: "shaders/post_vert.spv",
+                    fragment_shader: Some(fragment_shader),
+                    set_layouts: &set_layouts,
+                    push_constant_ranges: &[],
+                    vertex_bindings: &[],
+                    vertex_attributes: &[],
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    depth_test: false,
+                    depth_write: false,
+                    depth_bias: None,
+                    subpass: 0,
+                    color_attachments: 1,
+                    blend,
+                },
+            )
+        };
+
+        // text is drawn over the output with the output pass, its atlas bound
+        // where the first source would be
+        let (text_layout, text_pipeline) = Self::create_graphics_pipeline(
+            device,
+            output_render_pass,
+            &GraphicsPipelineDesc {
+                vertex_shader: "shaders/text_vert.spv",
+                fragment_shader: Some("shaders/text_frag.spv"),
+                set_layouts: &set_layouts,
+                push_constant_ranges: &[],
+                vertex_bindings: &TextVertex::binding_descriptions(),
+                vertex_attributes: &TextVertex::attribute_descriptions(),
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                depth_test: false,
+                depth_write: false,
+                depth_bias: None,
+                subpass: 0,
+                color_attachments: 1,
+                blend: Blend::Premultiplied,
+            },
+        )?;
+        unsafe { device.destroy_pipeline_layout(text_layout, None) };
+        // and the gui over that, each mesh's texture bound the same way
+        let (gui_layout, gui_pipeline) = Self::create_graphics_pipeline(
+            device,
+            output_render_pass,
+            &GraphicsPipelineDesc {
+                vertex_shader: "shaders/gui_vert.spv",
+                fragment_shader: Some("shaders/gui_frag.spv"),
+                set_layouts: &set_layouts,
+                push_constant_ranges: &[],
+                vertex_bindings: &GuiVertex::binding_descriptions(),
+                vertex_attributes: &GuiVertex::attribute_descriptions(),
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                depth_test: false,
+                depth_write: false,
+                depth_bias: None,
+                subpass: 0,
+                color_attachments: 1,
+                blend: Blend::Premultiplied,
+            },
+        )?;
+        unsafe { device.destroy_pipeline_layout(gui_layout, None) };
+
+        let (pipeline_layout, bloom_prefilter_pipeline) = create_pipeline_and_layout(
+            render_pass,
+            "shaders/bloom_prefilter_frag.spv",
+            Blend::Replace,
+        )?;
+        // all the layouts are identical, so every pipeline can be bound with the first
+        let create_pipeline = |render_pass, fragment_shader, blend| {
+            let (layout, pipeline) =
+                create_pipeline_and_layout(render_pass, fragment_shader, blend)?;
+            unsafe { device.destroy_pipeline_layout(layout, None) };
+            Ok::<_, anyhow::Error>(pipeline)
+        };
+
+        Ok(PostProcessing {
+            scene,
+            ping_pong,
+            bloom,
+            render_pass,
+            blend_render_pass,
+            output_render_pass,
+            output_framebuffers,
+            sampler,
+            source_set_layout,
+            descriptor_pool,
+            pipeline_layout,
+            bloom_prefilter_pipeline,
+            bloom_downsample_pipeline: create_pipeline(
+                render_pass,
+                "shaders/bloom_downsample_frag.spv",
+                Blend::Replace,
+            )?,
+            // the blend pass is compatible with the plain one, only load ops differ
+            bloom_upsample_pipeline: create_pipeline(
+                render_pass,
+                "shaders/bloom_upsample_frag.spv",
+                Blend::Additive,
+            )?,
+            bloom_composite_pipeline: create_pipeline(
+                render_pass,
+                "shaders/bloom_composite_frag.spv",
+                Blend::Replace,
+            )?,
+            tonemap_pipeline: create_pipeline(
+                render_pass,
+                "shaders/tonemap_frag.spv",
+                Blend::Replace,
+            )?,
+            vignette_pipeline: create_pipeline(
+                render_pass,
+                "shaders/vignette_frag.spv",
+                Blend::Replace,
+            )?,
+            gamma_pipeline: create_pipeline(render_pass, "shaders/gamma_frag.spv", Blend::Replace)?,
+            output_pipeline: create_pipeline(
+                output_render_pass,
+                "shaders/output_frag.spv",
+                Blend::Replace,
+            )?,
+            text_pipeline,
+            gui_pipeline,
+        })
+    }
+
+    /// A post pass source is an image and its sampler, like a material texture
+    fn create_source_set_layout(device: &ash::Device) -> Result<vk::DescriptorSetLayout> {
+        let bindings = [
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+        Ok(unsafe { device.create_descriptor_set_layout(&layout_create_info, None)? })
+    }
+
+    fn write_source_set(
+        device: &ash::Device,
+        descriptor_set: vk::DescriptorSet,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) {
+        let image_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let sampler_info = [vk::DescriptorImageInfo {
+            sampler,
+            image_view: vk::ImageView::null(),
+            image_layout: vk::ImageLayout::UNDEFINED,
+        }];
+        let writes = [
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_info),
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&sampler_info),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Uploads the font atlas and binds it like a post source, so the text
+    /// pipeline can share the post pipeline layout
+    fn create_text_renderer(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        upload: &UploadContext,
+        atlas: FontAtlas,
+        atlas_texture: &TextureData,
+    ) -> Result<TextRenderer> {
+        let texture =
+            Self::create_texture(instance, device, physical_device, upload, atlas_texture)?;
+        let set_layout = Self::create_source_set_layout(device)?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+            },
+        ];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let set_layouts = [set_layout];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] };
+        Self::write_source_set(device, descriptor_set, texture.view, texture.sampler);
+
+        Ok(TextRenderer {
+            atlas,
+            texture,
+            set_layout,
+            descriptor_pool,
+            descriptor_set,
+        })
+    }
+
+    /// The descriptors egui's textures are bound with, which start out empty
+    /// and come and go as egui asks
+    fn create_gui_renderer(device: &ash::Device) -> Result<GuiRenderer> {
+        let set_layout = Self::create_source_set_layout(device)?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: MAX_GUI_TEXTURES,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: MAX_GUI_TEXTURES,
+            },
+        ];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .pool_sizes(&pool_sizes)
+            .max_sets(MAX_GUI_TEXTURES);
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None)? };
+
+        Ok(GuiRenderer {
+            set_layout,
+            descriptor_pool,
+            textures: HashMap::new(),
+            deletions: DeletionQueue::default(),
+        })
+    }
+
+    /// An HDR colour image with a framebuffer rendering to it, along with
+    /// `extra_attachments`, and a descriptor set sampling it
+    #[allow(clippy::too_many_arguments)]
+    fn create_render_target(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        extra_attachments: &[vk::ImageView],
+        descriptor_pool: vk::DescriptorPool,
+        set_layout: vk::DescriptorSetLayout,
+        sampler: vk::Sampler,
+    ) -> Result<RenderTarget> {
+        let (image, memory) = Self::create_image(
+            instance,
+            device,
+            physical_device,
+            extent,
+            1,
+            HDR_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+        let view = Self::create_image_view(device, image, HDR_FORMAT, vk::ImageAspectFlags::COLOR)?;
+
+        let attachments: Vec<vk::ImageView> = std::iter::once(view)
+            .chain(extra_attachments.iter().copied())
+            .collect();
+        let framebuffer = Self::create_framebuffer(device, render_pass, &attachments, extent)?;
+
+        let set_layouts = [set_layout];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] };
+
+        Self::write_source_set(device, descriptor_set, view, sampler);
+
+        Ok(RenderTarget {
+            extent,
+            image,
+            memory,
+            view,
+            framebuffer,
+            descriptor_set,
+        })
+    }
+
     fn pick_physical_device(
         instance: &ash::Instance,
         surface: vk::SurfaceKHR,
@@ -791,32 +6592,50 @@ impl VulkanApp {
         physical_device: vk::PhysicalDevice,
         enable_validation_layer: bool,
         indices: &QueueFamilyIndices,
-    ) -> Result<(ash::Device, vk::Queue, vk::Queue)> {
+        optional_extensions: &[&CStr],
+        features: &DeviceFeatures,
+    ) -> Result<(ash::Device, vk::Queue, vk::Queue, vk::Queue)> {
         //let indices = Self::find_queue_families(instance, physical_device, surface, surface_loader)?;
 
         if !indices.is_complete() {
             anyhow::bail!("incomplete queue family support");
         }
 
-        let queue_create_info = [
-            *vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(indices.graphics_family.unwrap())
-                .queue_priorities(&[1.0]),
-            *vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(indices.presentation_family.unwrap())
-                .queue_priorities(&[1.0]),
-        ];
-
-        let extensions = [ash::extensions::khr::Swapchain::name()];
-
-        let extension_ptrs: Vec<*const c_char> = extensions.iter().map(|s| s.as_ptr()).collect();
+        let queue_priorities = [1.0];
+        let queue_create_info: Vec<vk::DeviceQueueCreateInfo> = indices
+            .unique_families()
+            .into_iter()
+            .map(|family| {
+                *vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(family)
+                    .queue_priorities(&queue_priorities)
+            })
+            .collect();
 
-        let features = vk::PhysicalDeviceFeatures::builder();
+        let extension_ptrs: Vec<*const c_char> =
+            std::iter::once(ash::extensions::khr::Swapchain::name())
+                .chain(optional_extensions.iter().copied())
+                .map(|s| s.as_ptr())
+                .collect();
 
         let mut create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_create_info)
             .enabled_extension_names(&extension_ptrs)
-            .enabled_features(&features);
+            .enabled_features(&features.core);
+
+        // what the bindless texture arrays need, see `descriptor_indexing_support`
+        let mut descriptor_indexing_features =
+            vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+                .descriptor_binding_partially_bound(true)
+                .descriptor_binding_sampled_image_update_after_bind(true);
+        if features.descriptor_indexing {
+            create_info = create_info.push_next(&mut descriptor_indexing_features);
+        }
+        let mut timeline_semaphore_features =
+            vk::PhysicalDeviceTimelineSemaphoreFeatures::builder().timeline_semaphore(true);
+        if features.timeline_semaphore {
+            create_info = create_info.push_next(&mut timeline_semaphore_features);
+        }
 
         let validation_layers = Self::get_required_validation_layers(enable_validation_layer)?;
         let validation_layer_ptrs: Vec<*const c_char> =
@@ -831,8 +6650,10 @@ impl VulkanApp {
             unsafe { device.get_device_queue(indices.graphics_family.unwrap(), 0) };
         let presentation_queue =
             unsafe { device.get_device_queue(indices.presentation_family.unwrap(), 0) };
+        let transfer_queue =
+            unsafe { device.get_device_queue(indices.transfer_family.unwrap(), 0) };
 
-        Ok((device, graphics_queue, presentation_queue))
+        Ok((device, graphics_queue, presentation_queue, transfer_queue))
     }
 
     fn query_swap_chain_support(
@@ -886,32 +6707,111 @@ impl VulkanApp {
         return Ok(indices.is_complete() && swapchain_support);
     }
 
-    fn choose_swap_surface_format<'a>(
-        available_formats: &'a Vec<vk::SurfaceFormatKHR>,
-    ) -> Result<&'a vk::SurfaceFormatKHR> {
-        for format in available_formats.iter() {
-            if format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-                && format.format == vk::Format::B8G8R8A8_SRGB
-            {
-                return Ok(format);
+    /// Picks HDR10 over scRGB when HDR is preferred and the surface offers it,
+    /// otherwise SDR
+    fn choose_swap_surface_format(
+        available_formats: &[vk::SurfaceFormatKHR],
+        dynamic_range: DynamicRange,
+    ) -> Result<(vk::SurfaceFormatKHR, OutputSpace)> {
+        let find = |format, color_space| {
+            available_formats
+                .iter()
+                .find(|f| f.format == format && f.color_space == color_space)
+                .copied()
+        };
+
+        if dynamic_range == DynamicRange::Hdr {
+            if let Some(format) = find(
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            ) {
+                return Ok((format, OutputSpace::Hdr10));
+            }
+            if let Some(format) = find(
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            ) {
+                return Ok((format, OutputSpace::Scrgb));
             }
+            log::warn!("surface offers neither HDR10 nor scRGB, presenting SDR");
         }
 
-        Ok(available_formats
-            .get(0)
-            .expect("failed to find an available format"))
+        // UNORM rather than SRGB, the gamma effect encodes the image itself
+        if let Some(format) = find(
+            vk::Format::B8G8R8A8_UNORM,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        ) {
+            return Ok((format, OutputSpace::Sdr));
+        }
+
+        Ok((
+            *available_formats
+                .get(0)
+                .expect("failed to find an available format"),
+            OutputSpace::Sdr,
+        ))
     }
 
-    fn choose_swap_present_mode<'a>(
-        available_present_modes: Vec<vk::PresentModeKHR>,
-    ) -> Result<vk::PresentModeKHR> {
-        for mode in available_present_modes {
-            if mode == vk::PresentModeKHR::MAILBOX {
-                return Ok(mode);
-            }
+    /// Tells the display the primaries and brightness range the swapchain is
+    /// mastered for
+    fn set_hdr_metadata(
+        device: &ash::Device,
+        hdr_metadata: &vk::ExtHdrMetadataFn,
+        swapchain: vk::SwapchainKHR,
+        output_space: OutputSpace,
+        settings: &RenderSettings,
+    ) {
+        let xy = |x, y| vk::XYColorEXT { x, y };
+        let (red, green, blue) = match output_space {
+            OutputSpace::Sdr => return,
+            OutputSpace::Hdr10 => (xy(0.708, 0.292), xy(0.170, 0.797), xy(0.131, 0.046)),
+            OutputSpace::Scrgb => (xy(0.640, 0.330), xy(0.300, 0.600), xy(0.150, 0.060)),
+        };
+        let metadata = vk::HdrMetadataEXT::builder()
+            .display_primary_red(red)
+            .display_primary_green(green)
+            .display_primary_blue(blue)
+            .white_point(xy(0.3127, 0.3290))
+            .max_luminance(settings.max_luminance)
+            .min_luminance(0.001)
+            .max_content_light_level(settings.max_luminance)
+            .max_frame_average_light_level(settings.max_frame_average_nits);
+
+        unsafe {
+            hdr_metadata.set_hdr_metadata_ext(device.handle(), 1, &swapchain, &*metadata);
+        }
+    }
+
+    /// The preferred mode if the surface offers it, or the first of its
+    /// fallbacks that it does
+    fn choose_swap_present_mode(
+        available_present_modes: &[vk::PresentModeKHR],
+        preferred: PresentMode,
+    ) -> vk::PresentModeKHR {
+        let chosen = preferred
+            .fallbacks()
+            .iter()
+            .copied()
+            .find(|&mode| available_present_modes.contains(&Self::vk_present_mode(mode)))
+            // every surface offers FIFO
+            .unwrap_or(PresentMode::Fifo);
+        if chosen != preferred {
+            log::warn!(
+                "{:?} presentation is unavailable, falling back to {:?}",
+                preferred,
+                chosen
+            );
         }
+        Self::vk_present_mode(chosen)
+    }
 
-        Ok(vk::PresentModeKHR::FIFO)
+    fn vk_present_mode(mode: PresentMode) -> vk::PresentModeKHR {
+        match mode {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::Relaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
     }
 
     fn choose_swap_extent(
@@ -938,14 +6838,20 @@ impl VulkanApp {
     fn check_device_extension_support(
         instance: &ash::Instance,
         device: vk::PhysicalDevice,
+    ) -> Result<bool> {
+        Self::device_supports_extension(instance, device, ash::extensions::khr::Swapchain::name())
+    }
+
+    fn device_supports_extension(
+        instance: &ash::Instance,
+        device: vk::PhysicalDevice,
+        name: &CStr,
     ) -> Result<bool> {
         unsafe {
             let extensions = instance.enumerate_device_extension_properties(device)?;
 
             for vk::ExtensionProperties { extension_name, .. } in extensions {
-                if CStr::from_ptr(extension_name.as_ptr())
-                    == ash::extensions::khr::Swapchain::name()
-                {
+                if CStr::from_ptr(extension_name.as_ptr()) == name {
                     return Ok(true);
                 }
             }
@@ -954,32 +6860,144 @@ impl VulkanApp {
         Ok(false)
     }
 
+    /// Whether the device can sample from partially bound, update-after-bind
+    /// arrays of `MAX_BINDLESS_TEXTURES` textures indexed in the shader: core
+    /// in Vulkan 1.2 and VK_EXT_descriptor_indexing before it. `Some` with
+    /// whether that extension needs enabling if so.
+    fn descriptor_indexing_support(
+        instance: &ash::Instance,
+        api_version: u32,
+        device: vk::PhysicalDevice,
+    ) -> Result<Option<bool>> {
+        let needs_extension = match Self::promoted_extension_support(
+            instance,
+            api_version,
+            device,
+            vk::ExtDescriptorIndexingFn::name(),
+        )? {
+            Some(needs_extension) => needs_extension,
+            None => return Ok(None),
+        };
+
+        let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut indexing_features);
+        unsafe { instance.get_physical_device_features2(device, &mut features) };
+        let supported = features
+            .features
+            .shader_sampled_image_array_dynamic_indexing
+            == vk::TRUE
+            && indexing_features.descriptor_binding_partially_bound == vk::TRUE
+            && indexing_features.descriptor_binding_sampled_image_update_after_bind == vk::TRUE;
+
+        let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+        let mut properties =
+            vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_properties);
+        unsafe { instance.get_physical_device_properties2(device, &mut properties) };
+        let fits = [
+            indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+            indexing_properties.max_per_stage_descriptor_update_after_bind_samplers,
+            indexing_properties.max_descriptor_set_update_after_bind_sampled_images,
+            indexing_properties.max_descriptor_set_update_after_bind_samplers,
+        ]
+        .iter()
+        .all(|&limit| limit >= MAX_BINDLESS_TEXTURES);
+
+        Ok((supported && fits).then_some(needs_extension))
+    }
+
+    /// Whether the device has timeline semaphores: core in Vulkan 1.2 and
+    /// VK_KHR_timeline_semaphore before it. `Some` with whether that
+    /// extension needs enabling if so.
+    fn timeline_semaphore_support(
+        instance: &ash::Instance,
+        api_version: u32,
+        device: vk::PhysicalDevice,
+    ) -> Result<Option<bool>> {
+        let needs_extension = match Self::promoted_extension_support(
+            instance,
+            api_version,
+            device,
+            TimelineSemaphore::name(),
+        )? {
+            Some(needs_extension) => needs_extension,
+            None => return Ok(None),
+        };
+
+        let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut timeline_features);
+        unsafe { instance.get_physical_device_features2(device, &mut features) };
+
+        Ok((timeline_features.timeline_semaphore == vk::TRUE).then_some(needs_extension))
+    }
+
+    /// Whether the features of an extension promoted to Vulkan 1.2 can be
+    /// used at `api_version`, `Some` with whether the extension needs enabling
+    /// if so. Querying the features takes the 1.1 entry points either way.
+    fn promoted_extension_support(
+        instance: &ash::Instance,
+        api_version: u32,
+        device: vk::PhysicalDevice,
+        name: &CStr,
+    ) -> Result<Option<bool>> {
+        if api_version < vk::API_VERSION_1_1 {
+            Ok(None)
+        } else if api_version >= vk::API_VERSION_1_2 {
+            Ok(Some(false))
+        } else {
+            Ok(Self::device_supports_extension(instance, device, name)?.then_some(true))
+        }
+    }
+
     fn find_queue_families(
         instance: &ash::Instance,
         device: vk::PhysicalDevice,
         surface: vk::SurfaceKHR,
         surface_loader: &Surface,
     ) -> Result<QueueFamilyIndices> {
-        let instance = instance;
-        let mut indices = QueueFamilyIndices {
-            graphics_family: None,
-            presentation_family: None,
+        let families = unsafe { instance.get_physical_device_queue_family_properties(device) };
+
+        let mut supports_surface = vec![];
+        for index in 0..families.len() as u32 {
+            supports_surface.push(unsafe {
+                surface_loader.get_physical_device_surface_support(device, index, surface)?
+            });
+        }
+
+        let find_family = |predicate: &dyn Fn(usize, vk::QueueFlags) -> bool| {
+            families
+                .iter()
+                .enumerate()
+                .position(|(index, family)| {
+                    family.queue_count > 0 && predicate(index, family.queue_flags)
+                })
+                .map(|index| index as u32)
         };
 
-        let families = unsafe { instance.get_physical_device_queue_family_properties(device) };
-        for (index, family) in families.iter().enumerate() {
-            if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                indices.graphics_family = Some(index as u32);
-            }
+        // the particle simulation and culling are dispatched on the graphics
+        // queue, so it needs compute too
+        let is_graphics = |flags: vk::QueueFlags| {
+            flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        };
 
-            let supports_surface = unsafe {
-                surface_loader.get_physical_device_surface_support(device, index as u32, surface)?
-            };
-            if supports_surface {
-                indices.presentation_family = Some(index as u32);
-            }
+        let mut indices = QueueFamilyIndices::default();
+
+        // a single family for both avoids sharing swapchain images between queues
+        if let Some(family) =
+            find_family(&|index, flags| is_graphics(flags) && supports_surface[index])
+        {
+            indices.graphics_family = Some(family);
+            indices.presentation_family = Some(family);
+        } else {
+            indices.graphics_family = find_family(&|_, flags| is_graphics(flags));
+            indices.presentation_family = find_family(&|index, _| supports_surface[index]);
         }
 
+        indices.transfer_family = find_family(&|_, flags| {
+            flags.contains(vk::QueueFlags::TRANSFER)
+                && !flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .or(indices.graphics_family);
+
         Ok(indices)
     }
 
@@ -1119,27 +7137,58 @@ impl Drop for VulkanApp {
                 debug_utils_loader.destroy_debug_utils_messenger(debug_callback, None)
             }
 
-            for i in 0..MAX_FRAMES_IN_FLIGHT {
-                self.logical_device
-                    .destroy_semaphore(self.image_available_semaphores[i], None);
-                self.logical_device
-                    .destroy_semaphore(self.render_finished_semaphores[i], None);
-                self.logical_device
-                    .destroy_fence(self.in_flight_fences[i], None);
+            for window in &self.windows {
+                window.destroy(
+                    &self.logical_device,
+                    self.command_pool,
+                    &self.swapchain_loader,
+                    &self.surface_loader,
+                );
             }
 
-            for framebuffer in self.framebuffers.iter() {
-                self.logical_device.destroy_framebuffer(*framebuffer, None)
-            }
+            self.mesh_buffers.destroy(&self.logical_device);
 
-            for image_view in self.swapchain_image_views.iter() {
-                self.logical_device.destroy_image_view(*image_view, None);
+            for texture in self.textures.iter() {
+                texture.destroy(&self.logical_device);
+            }
+            self.material_textures.destroy(&self.logical_device);
+            if let Some(text) = &self.text {
+                text.destroy(&self.logical_device);
             }
+            self.gui_renderer.destroy(&self.logical_device);
 
+            self.particle_system.destroy(&self.logical_device);
+            if let Some(gpu_culling) = &self.gpu_culling {
+                gpu_culling.destroy(&self.logical_device);
+            }
+            self.debug_pipelines.destroy(&self.logical_device);
+            for layout in [
+                &self.camera_uniforms_layout,
+                &self.object_uniforms_layout,
+                &self.light_uniforms_layout,
+                &self.post_uniforms_layout,
+            ] {
+                layout.destroy(&self.logical_device);
+            }
+            self.shadow_maps.destroy(&self.logical_device);
             self.logical_device.destroy_pipeline(self.pipeline, None);
 
             self.logical_device
                 .destroy_command_pool(self.command_pool, None);
+            self.logical_device
+                .destroy_command_pool(self.upload_context.transfer_command_pool, None);
+            if let Some(timeline) = &self.graphics_timeline {
+                timeline.destroy(&self.logical_device);
+            }
+            if let Some(timeline) = &self.upload_context.transfer_timeline {
+                if !self
+                    .graphics_timeline
+                    .as_ref()
+                    .is_some_and(|graphics| Rc::ptr_eq(graphics, timeline))
+                {
+                    timeline.destroy(&self.logical_device);
+                }
+            }
 
             self.logical_device
                 .destroy_pipeline_layout(self.pipeline_layout, None);
@@ -1148,11 +7197,6 @@ impl Drop for VulkanApp {
 
             self.logical_device.destroy_device(None);
 
-            self.surface_loader.destroy_surface(self.surface, None);
-
-            self.swapchain_loader
-                .destroy_swapchain(self.swapchain, None);
-
             self.instance.destroy_instance(None);
         }
     }
@@ -1160,5 +7204,6 @@ impl Drop for VulkanApp {
 
 fn main() -> Result<()> {
     env_logger::init();
-    VulkanApp::new("Vulkan", 800, 600)?.run()
+    let scene_path = std::env::args().nth(1);
+    VulkanApp::new("Vulkan", 800, 600, scene_path.as_deref().map(Path::new))?.run()
 }
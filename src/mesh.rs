@@ -0,0 +1,341 @@
+use ash::vk;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, MetricSpace, One, Point3, Transform};
+
+/// Vertex layout of the mesh pipeline, must match the inputs of shader.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// xyz is the tangent, w the handedness of the bitangent
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+    pub color: [f32; 3],
+}
+
+impl Vertex {
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 12,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 24,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 40,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 4,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 48,
+            },
+        ]
+    }
+}
+
+/// Per-instance inputs of the mesh pipelines, stepped once per instance
+/// rather than per vertex. Must match the instance inputs of shader.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    /// Where the copy sits within its draw. It transforms normals as well,
+    /// so may only rotate, translate and scale uniformly
+    pub model: Matrix4<f32>,
+    /// Multiplies the vertex colours
+    pub color: [f32; 3],
+}
+
+/// Untransformed and white, what uninstanced draws are drawn with
+impl Default for Instance {
+    fn default() -> Self {
+        Instance {
+            model: Matrix4::one(),
+            color: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl Instance {
+    /// The binding after the vertices'
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 1,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }]
+    }
+
+    /// The model matrix a column per location, from 5 on after the vertex
+    /// attributes, then the colour
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 5,
+                binding: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 6,
+                binding: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 16,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 7,
+                binding: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 8,
+                binding: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 48,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 9,
+                binding: 1,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 64,
+            },
+        ]
+    }
+}
+
+/// Which draw's object uniforms an instance is drawn with. Read from a
+/// binding of its own, parallel to the instances', so the GPU culling pass
+/// can write one for each instance it keeps
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceObject(pub u32);
+
+impl InstanceObject {
+    /// The binding after the instances'
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 2,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }]
+    }
+
+    /// At location 10, after the instance attributes
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 1] {
+        [vk::VertexInputAttributeDescription {
+            location: 10,
+            binding: 2,
+            format: vk::Format::R32_UINT,
+            offset: 0,
+        }]
+    }
+}
+
+/// Host visible buffers a frame's instances, and the object each is drawn
+/// with, are written into
+pub struct InstanceBuffer {
+    pub buffer: vk::Buffer,
+    pub memory: vk::DeviceMemory,
+    pub object_buffer: vk::Buffer,
+    pub object_memory: vk::DeviceMemory,
+}
+
+impl InstanceBuffer {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_buffer(self.object_buffer, None);
+        device.free_memory(self.object_memory, None);
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box containing `points`
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        let empty = Aabb {
+            min: Point3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Point3::new(f32::MIN, f32::MIN, f32::MIN),
+        };
+        points.into_iter().fold(empty, |aabb, point| Aabb {
+            min: Point3::new(
+                aabb.min.x.min(point.x),
+                aabb.min.y.min(point.y),
+                aabb.min.z.min(point.z),
+            ),
+            max: Point3::new(
+                aabb.max.x.max(point.x),
+                aabb.max.y.max(point.y),
+                aabb.max.z.max(point.z),
+            ),
+        })
+    }
+
+    /// Bit 0 of the index picks max x, bit 1 max y and bit 2 max z
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    /// The box around this one's corners moved by `matrix`
+    pub fn transformed(&self, matrix: Matrix4<f32>) -> Self {
+        Self::from_points(
+            self.corners()
+                .into_iter()
+                .map(|corner| matrix.transform_point(corner)),
+        )
+    }
+}
+
+/// Bounding sphere, cheaper to test than a box but usually looser
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// The sphere around this one moved by `matrix`, grown by its largest
+    /// scale so it stays a sphere
+    pub fn transformed(&self, matrix: Matrix4<f32>) -> Self {
+        let scale = [matrix.x, matrix.y, matrix.z]
+            .map(|axis| axis.truncate().magnitude())
+            .into_iter()
+            .fold(0.0, f32::max);
+        Sphere {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Mesh data on the CPU, ready to be uploaded
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Unit cube centred on the origin with per-face normals and colours
+    pub fn cube() -> Self {
+        // normal, then the two axes spanning the face
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        for (normal, u, v) in faces {
+            let base = vertices.len() as u32;
+            let color = normal.map(|n| 0.6 + 0.4 * n.abs());
+
+            for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let position = [0, 1, 2].map(|i| 0.5 * (normal[i] + su * u[i] + sv * v[i]));
+                vertices.push(Vertex {
+                    position,
+                    normal,
+                    tangent: [u[0], u[1], u[2], 1.0],
+                    // texture v runs down the face
+                    uv: [0.5 * (su + 1.0), 0.5 * (1.0 - sv)],
+                    color,
+                });
+            }
+
+            // counter-clockwise seen from outside, the pipeline's front face
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        Mesh { vertices, indices }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(
+            self.vertices
+                .iter()
+                .map(|vertex| Point3::from(vertex.position)),
+        )
+    }
+
+    /// The sphere centred on the bounds that just reaches the furthest vertex
+    pub fn bounding_sphere(&self) -> Sphere {
+        let bounds = self.bounds();
+        let center = bounds.min.midpoint(bounds.max);
+        let radius = self
+            .vertices
+            .iter()
+            .map(|vertex| Point3::from(vertex.position).distance(center))
+            .fold(0.0, f32::max);
+        Sphere { center, radius }
+    }
+}
+
+/// Every mesh's vertices and indices, uploaded together to one device local
+/// vertex buffer and one index buffer so draws of different meshes share them
+pub struct MeshBuffers {
+    pub vertex_buffer: vk::Buffer,
+    pub vertex_buffer_memory: vk::DeviceMemory,
+    pub index_buffer: vk::Buffer,
+    pub index_buffer_memory: vk::DeviceMemory,
+}
+
+impl MeshBuffers {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_buffer(self.index_buffer, None);
+        device.free_memory(self.index_buffer_memory, None);
+        device.destroy_buffer(self.vertex_buffer, None);
+        device.free_memory(self.vertex_buffer_memory, None);
+    }
+}
+
+/// Where a mesh sits in the `MeshBuffers`
+pub struct GpuMesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Added to each of the mesh's indices, which count from its own first vertex
+    pub vertex_offset: i32,
+    /// Bounds of the vertices in model space
+    pub bounds: Aabb,
+    pub bounding_sphere: Sphere,
+}
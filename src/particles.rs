@@ -0,0 +1,111 @@
+use ash::vk;
+
+pub const PARTICLE_COUNT: u32 = 8192;
+
+/// Number of invocations per workgroup, must match `local_size_x` in particle.comp
+pub const PARTICLE_WORKGROUP_SIZE: u32 = 256;
+
+/// Layout shared by the compute shader's storage buffer (std430) and the
+/// vertex input of the particle pipeline.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Must match the `SimulationParams` uniform block in particle.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SimulationParams {
+    pub delta_time: f32,
+    pub particle_count: u32,
+}
+
+impl Particle {
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 16,
+            },
+        ]
+    }
+}
+
+/// Lays the particles out on a golden-angle spiral with tangential velocities,
+/// which gives a deterministic swirl without needing a random number generator.
+pub fn initial_particles(count: u32) -> Vec<Particle> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+
+    (0..count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let radius = 0.1 + 0.8 * t.sqrt();
+            let angle = i as f32 * golden_angle;
+            let (sin, cos) = angle.sin_cos();
+            let speed = 0.25 / radius.sqrt();
+
+            Particle {
+                position: [radius * cos, radius * sin],
+                velocity: [-sin * speed, cos * speed],
+                color: [1.0 - t, 0.5 * t + 0.25, t, 1.0],
+            }
+        })
+        .collect()
+}
+
+/// Vulkan objects owned by the particle simulation. The storage buffer is
+/// written by the compute pipeline and read back as a vertex buffer by the
+/// graphics pipeline; each frame in flight of the main window, which steps
+/// the simulation, gets its own parameter buffer and descriptor set so the
+/// time step can be updated while the other frame is in flight.
+pub struct ParticleSystem {
+    pub buffer: vk::Buffer,
+    pub buffer_memory: vk::DeviceMemory,
+    pub param_buffers: Vec<vk::Buffer>,
+    pub param_buffer_memories: Vec<vk::DeviceMemory>,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub compute_pipeline_layout: vk::PipelineLayout,
+    pub compute_pipeline: vk::Pipeline,
+    pub graphics_pipeline_layout: vk::PipelineLayout,
+    pub graphics_pipeline: vk::Pipeline,
+}
+
+impl ParticleSystem {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_pipeline(self.graphics_pipeline, None);
+        device.destroy_pipeline_layout(self.graphics_pipeline_layout, None);
+        device.destroy_pipeline(self.compute_pipeline, None);
+        device.destroy_pipeline_layout(self.compute_pipeline_layout, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+
+        for (&buffer, &memory) in self.param_buffers.iter().zip(&self.param_buffer_memories) {
+            device.destroy_buffer(buffer, None);
+            device.free_memory(memory, None);
+        }
+
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.buffer_memory, None);
+    }
+}
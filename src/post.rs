@@ -0,0 +1,288 @@
+use ash::vk;
+use serde::Deserialize;
+
+use crate::input::{ActionMap, InputState};
+use crate::settings::RenderSettings;
+
+/// Format of the scene colour and of every post-processing target
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// The colour space of the swapchain, which the output pass encodes for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputSpace {
+    /// 0..1 sRGB, gamma encoded by the gamma effect
+    Sdr,
+    /// Rec.2020 primaries with the PQ curve in a 10 bit image
+    Hdr10,
+    /// Linear sRGB primaries in a float image, 1 being 80 nits
+    Scrgb,
+}
+
+impl OutputSpace {
+    pub fn is_hdr(self) -> bool {
+        self != OutputSpace::Sdr
+    }
+}
+
+/// One full-screen effect in the post-processing chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostEffect {
+    /// Blurs what is brighter than the threshold and adds it back on top
+    Bloom,
+    /// Scales by the exposure and maps the result into 0..1
+    Tonemap,
+    /// Darkens the corners
+    Vignette,
+    /// Encodes linear colour for display
+    Gamma,
+}
+
+impl PostEffect {
+    /// The action toggling this effect at runtime
+    pub fn toggle_action(self) -> &'static str {
+        match self {
+            PostEffect::Bloom => "toggle_bloom",
+            PostEffect::Tonemap => "toggle_tonemap",
+            PostEffect::Vignette => "toggle_vignette",
+            PostEffect::Gamma => "toggle_gamma",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tonemapper {
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+    Reinhard,
+}
+
+/// The post-processing chain, read from a TOML file like
+///
+/// ```toml
+/// chain = ["bloom", "tonemap", "vignette", "gamma"]
+/// disabled = ["vignette"]
+/// exposure = 1.5
+/// tonemapper = "reinhard"
+/// ```
+///
+/// where any setting left out keeps its default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PostSettings {
+    /// Effects in the order they run
+    pub chain: Vec<PostEffect>,
+    /// Effects in the chain that are switched off, until toggled back on
+    pub disabled: Vec<PostEffect>,
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    /// Luminance above which pixels bloom
+    pub bloom_threshold: f32,
+    /// How much of the blurred bright pixels is added back
+    pub bloom_intensity: f32,
+    /// Halvings of the resolution in the bloom pyramid, fixed at startup
+    pub bloom_levels: u32,
+    /// 0 leaves the corners alone, 1 blacks them out
+    pub vignette_strength: f32,
+    pub gamma: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        PostSettings {
+            chain: vec![
+                PostEffect::Bloom,
+                PostEffect::Tonemap,
+                PostEffect::Vignette,
+                PostEffect::Gamma,
+            ],
+            disabled: vec![],
+            exposure: 1.0,
+            tonemapper: Tonemapper::Aces,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.05,
+            bloom_levels: 5,
+            vignette_strength: 0.3,
+            gamma: 2.2,
+        }
+    }
+}
+
+impl PostSettings {
+    /// The effects to run this frame, in order. Gamma is skipped for HDR output,
+    /// which the output pass encodes itself
+    pub fn active(&self, output: OutputSpace) -> Vec<PostEffect> {
+        self.chain
+            .iter()
+            .copied()
+            .filter(|effect| !self.disabled.contains(effect))
+            .filter(|&effect| !(effect == PostEffect::Gamma && output.is_hdr()))
+            .collect()
+    }
+
+    /// Switches effects on and off with their toggle actions
+    pub fn update(&mut self, input: &InputState, actions: &ActionMap) {
+        for effect in self.chain.clone() {
+            if actions.pressed(effect.toggle_action(), input) {
+                self.toggle(effect);
+            }
+        }
+    }
+
+    pub fn toggle(&mut self, effect: PostEffect) {
+        if let Some(i) = self.disabled.iter().position(|&e| e == effect) {
+            self.disabled.remove(i);
+            log::info!("{:?} on", effect);
+        } else {
+            self.disabled.push(effect);
+            log::info!("{:?} off", effect);
+        }
+    }
+}
+
+/// Parameters of every post pass, must match the `PostUniforms` block in the
+/// post-processing shaders
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PostUniforms {
+    pub exposure: f32,
+    /// 0 for ACES, 1 for Reinhard
+    pub tonemapper: u32,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub vignette_strength: f32,
+    pub gamma: f32,
+    /// 0 for SDR, 1 for HDR10, 2 for scRGB
+    pub output_space: u32,
+    /// Nits of an output value of 1
+    pub paper_white: f32,
+    /// Output value the tonemapper rolls off to, 1 in SDR
+    pub peak: f32,
+}
+
+impl PostUniforms {
+    pub fn new(settings: &PostSettings, output: OutputSpace, render: &RenderSettings) -> Self {
+        PostUniforms {
+            exposure: settings.exposure,
+            tonemapper: match settings.tonemapper {
+                Tonemapper::Aces => 0,
+                Tonemapper::Reinhard => 1,
+            },
+            bloom_threshold: settings.bloom_threshold,
+            bloom_intensity: settings.bloom_intensity,
+            vignette_strength: settings.vignette_strength,
+            gamma: settings.gamma,
+            output_space: match output {
+                OutputSpace::Sdr => 0,
+                OutputSpace::Hdr10 => 1,
+                OutputSpace::Scrgb => 2,
+            },
+            paper_white: render.paper_white,
+            peak: if output.is_hdr() {
+                (render.max_luminance / render.paper_white).max(1.0)
+            } else {
+                1.0
+            },
+        }
+    }
+}
+
+/// An offscreen colour image that passes render into and sample from
+pub struct RenderTarget {
+    pub extent: vk::Extent2D,
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub framebuffer: vk::Framebuffer,
+    /// Binds the target as the source of a post pass
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl RenderTarget {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_framebuffer(self.framebuffer, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+/// The HDR scene target and everything the post passes need to turn it into
+/// the swapchain image
+pub struct PostProcessing {
+    /// The scene's colour, its framebuffer is the scene render pass's
+    pub scene: RenderTarget,
+    /// Each effect reads one and writes the other
+    pub ping_pong: [RenderTarget; 2],
+    /// Successively halved copies of the bright parts of the image
+    pub bloom: Vec<RenderTarget>,
+    /// Overwrites a target
+    pub render_pass: vk::RenderPass,
+    /// Blends onto a target, for adding bloom levels back up the pyramid
+    pub blend_render_pass: vk::RenderPass,
+    /// Writes the swapchain image
+    pub output_render_pass: vk::RenderPass,
+    pub output_framebuffers: Vec<vk::Framebuffer>,
+    pub sampler: vk::Sampler,
+    pub source_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// Set 0 the post uniforms, sets 1 and 2 sources
+    pub pipeline_layout: vk::PipelineLayout,
+    pub bloom_prefilter_pipeline: vk::Pipeline,
+    pub bloom_downsample_pipeline: vk::Pipeline,
+    pub bloom_upsample_pipeline: vk::Pipeline,
+    pub bloom_composite_pipeline: vk::Pipeline,
+    pub tonemap_pipeline: vk::Pipeline,
+    pub vignette_pipeline: vk::Pipeline,
+    pub gamma_pipeline: vk::Pipeline,
+    pub output_pipeline: vk::Pipeline,
+    /// Draws glyph quads over the output, in the output pass
+    pub text_pipeline: vk::Pipeline,
+    /// Draws egui's meshes over the text, in the output pass
+    pub gui_pipeline: vk::Pipeline,
+}
+
+impl PostProcessing {
+    pub fn effect_pipeline(&self, effect: PostEffect) -> vk::Pipeline {
+        match effect {
+            PostEffect::Bloom => self.bloom_composite_pipeline,
+            PostEffect::Tonemap => self.tonemap_pipeline,
+            PostEffect::Vignette => self.vignette_pipeline,
+            PostEffect::Gamma => self.gamma_pipeline,
+        }
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        for pipeline in [
+            self.bloom_prefilter_pipeline,
+            self.bloom_downsample_pipeline,
+            self.bloom_upsample_pipeline,
+            self.bloom_composite_pipeline,
+            self.tonemap_pipeline,
+            self.vignette_pipeline,
+            self.gamma_pipeline,
+            self.output_pipeline,
+            self.text_pipeline,
+            self.gui_pipeline,
+        ] {
+            device.destroy_pipeline(pipeline, None);
+        }
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.source_set_layout, None);
+        device.destroy_sampler(self.sampler, None);
+        for &framebuffer in &self.output_framebuffers {
+            device.destroy_framebuffer(framebuffer, None);
+        }
+        for target in std::iter::once(&self.scene)
+            .chain(&self.ping_pong)
+            .chain(&self.bloom)
+        {
+            target.destroy(device);
+        }
+        device.destroy_render_pass(self.output_render_pass, None);
+        device.destroy_render_pass(self.blend_render_pass, None);
+        device.destroy_render_pass(self.render_pass, None);
+    }
+}
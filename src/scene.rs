@@ -0,0 +1,391 @@
+use cgmath::{Matrix, Matrix4, One, Quaternion, SquareMatrix, Vector3};
+use std::ops::Range;
+
+use crate::camera::Frustum;
+use crate::culling::CpuCullStats;
+use crate::light::Light;
+use crate::mesh::{GpuMesh, Instance};
+
+/// Index into the renderer's list of uploaded meshes
+pub type MeshId = usize;
+
+/// Index into the renderer's list of materials
+pub type MaterialId = usize;
+
+/// Index into the renderer's list of textures
+pub type TextureId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Per-draw data, must match the `ObjectUniforms` block in the mesh shaders
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectUniforms {
+    pub model: Matrix4<f32>,
+    /// Inverse transpose of `model`, keeps normals perpendicular under non-uniform scale
+    pub normal_matrix: Matrix4<f32>,
+    pub base_color: [f32; 4],
+    /// rgb emissive factor, a unused
+    pub emissive: [f32; 4],
+    /// metallic, roughness, normal scale and occlusion strength
+    pub material: [f32; 4],
+    /// Indices of the material's textures into the bindless texture arrays,
+    /// in `Material::textures` order
+    pub textures: [u32; 5],
+    _padding: [u32; 3],
+}
+
+impl ObjectUniforms {
+    pub fn new(model: Matrix4<f32>, material: &Material, textures: [TextureId; 5]) -> Self {
+        let normal_matrix = model
+            .invert()
+            .map_or(Matrix4::one(), |inverse| inverse.transpose());
+        let [r, g, b] = material.emissive;
+
+        ObjectUniforms {
+            model,
+            normal_matrix,
+            base_color: material.base_color,
+            emissive: [r, g, b, 0.0],
+            material: [
+                material.metallic,
+                material.roughness,
+                material.normal_scale,
+                material.occlusion_strength,
+            ],
+            textures: textures.map(|texture| texture as u32),
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// glTF style metallic-roughness material. Texture values are multiplied by
+/// the matching factor; metallic is read from the blue channel of
+/// `metallic_roughness_texture` and roughness from its green channel.
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<TextureId>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub metallic_roughness_texture: Option<TextureId>,
+    pub normal_texture: Option<TextureId>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureId>,
+    pub occlusion_strength: f32,
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<TextureId>,
+}
+
+/// The glTF default material: white and fully metallic and rough
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+        }
+    }
+}
+
+impl Material {
+    /// The material's textures in descriptor set binding order: base colour,
+    /// metallic-roughness, normal, occlusion and emissive. Missing ones are
+    /// replaced by the given 1x1 white and flat normal textures, which leave
+    /// the matching factors unchanged.
+    pub fn textures(&self, white: TextureId, flat_normal: TextureId) -> [TextureId; 5] {
+        [
+            self.base_color_texture.unwrap_or(white),
+            self.metallic_roughness_texture.unwrap_or(white),
+            self.normal_texture.unwrap_or(flat_normal),
+            self.occlusion_texture.unwrap_or(white),
+            self.emissive_texture.unwrap_or(white),
+        ]
+    }
+
+    /// Untextured dielectric of a single colour
+    pub fn from_color(base_color: [f32; 4]) -> Self {
+        Material {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<MeshId>,
+    pub material: Option<MaterialId>,
+    pub light: Option<Light>,
+    /// Where the node's copies of its mesh are in the scene's instance list,
+    /// one default instance if `None`
+    instances: Option<Range<usize>>,
+    local: Transform,
+    world: Matrix4<f32>,
+    /// The local transform changed since `world` was last computed
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl Node {
+    /// Only up to date after `Scene::update_world_transforms`
+    pub fn world_matrix(&self) -> Matrix4<f32> {
+        self.world
+    }
+}
+
+/// A draw emitted by `Scene::draws`
+#[derive(Clone, Copy, Debug)]
+pub struct Draw {
+    pub world: Matrix4<f32>,
+    pub mesh: MeshId,
+    pub material: MaterialId,
+    /// The draw's copies of the mesh in `Scene::instances`
+    pub first_instance: u32,
+    pub instance_count: u32,
+    /// Whether any of its instances is in the camera's view. Culled draws are
+    /// still drawn into the shadow maps
+    pub visible: bool,
+}
+
+/// Hierarchy of nodes, each placed relative to its parent. World matrices are
+/// cached and only recomputed for nodes whose own or an ancestor's local
+/// transform changed.
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    /// Every node's instances end to end, after the default instance that
+    /// uninstanced nodes share
+    instances: Vec<Instance>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            nodes: vec![],
+            roots: vec![],
+            instances: vec![Instance::default()],
+        }
+    }
+}
+
+impl Scene {
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            mesh: None,
+            material: None,
+            light: None,
+            instances: None,
+            local,
+            world: Matrix4::one(),
+            dirty: true,
+            parent,
+            children: vec![],
+        });
+
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(NodeId)
+    }
+
+    /// Applies `f` to a node's local transform and marks it dirty
+    pub fn update_local_transform(&mut self, id: NodeId, f: impl FnOnce(&mut Transform)) {
+        let node = &mut self.nodes[id.0];
+        f(&mut node.local);
+        node.dirty = true;
+    }
+
+    pub fn set_mesh(&mut self, id: NodeId, mesh: MeshId, material: MaterialId) {
+        let node = &mut self.nodes[id.0];
+        node.mesh = Some(mesh);
+        node.material = Some(material);
+    }
+
+    pub fn set_light(&mut self, id: NodeId, light: Light) {
+        self.nodes[id.0].light = Some(light);
+    }
+
+    /// Draws the node's mesh once per instance, each placed within the node
+    pub fn set_instances(&mut self, id: NodeId, instances: &[Instance]) {
+        let old = match self.nodes[id.0].instances.clone() {
+            // reuse the old space when it fits
+            Some(range) if range.len() == instances.len() => {
+                self.instances[range].copy_from_slice(instances);
+                return;
+            }
+            Some(range) if !range.is_empty() => range,
+            _ => self.instances.len()..self.instances.len(),
+        };
+
+        // replace the old range in place, moving the ranges after it along
+        // so no space is left behind
+        self.instances
+            .splice(old.clone(), instances.iter().copied());
+        for node in &mut self.nodes {
+            if let Some(range) = &mut node.instances {
+                if range.start >= old.end {
+                    *range = range.start - old.len() + instances.len()
+                        ..range.end - old.len() + instances.len();
+                }
+            }
+        }
+        self.nodes[id.0].instances = Some(old.start..old.start + instances.len());
+    }
+
+    /// The node's instances, to move them about. Empty for uninstanced nodes
+    pub fn instances_mut(&mut self, id: NodeId) -> &mut [Instance] {
+        match &self.nodes[id.0].instances {
+            Some(range) => &mut self.instances[range.clone()],
+            None => &mut [],
+        }
+    }
+
+    /// Every draw's instances, indexed by their `first_instance`
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn update_world_transforms(&mut self) {
+        // children are only ever added after their parent, and a node can't be
+        // re-parented, so a walk in insertion order always sees the parent first
+        let mut changed = vec![false; self.nodes.len()];
+
+        for index in 0..self.nodes.len() {
+            let (parent_world, parent_changed) = match self.nodes[index].parent {
+                Some(parent) => (self.nodes[parent.0].world, changed[parent.0]),
+                None => (Matrix4::one(), false),
+            };
+
+            let node = &mut self.nodes[index];
+            if node.dirty || parent_changed {
+                node.world = parent_world * node.local.matrix();
+                node.dirty = false;
+                changed[index] = true;
+            }
+        }
+    }
+
+    /// Depth first traversal from the roots, yielding every node with a mesh.
+    /// Given a frustum, each node's instances are tested against it by their
+    /// world bounds, sphere first and then box, and the node is culled if
+    /// none are in it. A child's bounds needn't lie within its parent's, so
+    /// the children of culled nodes are still visited. The counts are only
+    /// kept when culling. Call `update_world_transforms` first.
+    pub fn draws(
+        &self,
+        frustum: Option<&Frustum>,
+        meshes: &[GpuMesh],
+    ) -> (Vec<Draw>, Option<CpuCullStats>) {
+        let mut draws = vec![];
+        let mut stats = CpuCullStats::default();
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+
+            if let (Some(mesh), Some(material)) = (node.mesh, node.material) {
+                let instances = node.instances.clone().unwrap_or(0..1);
+                let visible = frustum.is_none_or(|frustum| {
+                    let bounds = &meshes[mesh];
+                    self.instances[instances.clone()].iter().any(|instance| {
+                        let model = node.world * instance.model;
+                        frustum.intersects_sphere(&bounds.bounding_sphere.transformed(model))
+                            && frustum.intersects_aabb(&bounds.bounds.transformed(model))
+                    })
+                });
+                if visible {
+                    stats.visible += 1;
+                } else {
+                    stats.culled += 1;
+                }
+
+                draws.push(Draw {
+                    world: node.world,
+                    mesh,
+                    material,
+                    first_instance: instances.start as u32,
+                    instance_count: instances.len() as u32,
+                    visible,
+                });
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
+
+        (draws, frustum.map(|_| stats))
+    }
+
+    /// Every light with the world transform of its node.
+    /// Call `update_world_transforms` first.
+    pub fn lights(&self) -> Vec<(Matrix4<f32>, Light)> {
+        self.nodes
+            .iter()
+            .filter_map(|node| node.light.map(|light| (node.world, light)))
+            .collect()
+    }
+}
@@ -0,0 +1,161 @@
+use serde::Deserialize;
+
+/// How the scene's lighting is computed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderPath {
+    /// Every object loops over all the lights as it is drawn
+    #[default]
+    Forward,
+    /// Objects only fill a G-buffer, which a single fullscreen pass then lights
+    Deferred,
+}
+
+/// What the swapchain should present
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DynamicRange {
+    /// An 8 bit sRGB image, which every surface supports
+    #[default]
+    Sdr,
+    /// HDR10 or scRGB where the surface offers either, SDR otherwise
+    Hdr,
+}
+
+/// How presented frames are paced against the display's refresh
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    /// Vsync, rendering waits for a free image so it never tears
+    Fifo,
+    /// Vsync, but a frame that missed its refresh is shown at once and may tear
+    Relaxed,
+    /// Vsync without waiting, a newer frame replaces the one queued for display
+    #[default]
+    Mailbox,
+    /// No vsync, frames are shown as soon as they're done and tear
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 4] = [
+        PresentMode::Fifo,
+        PresentMode::Relaxed,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    /// The mode after this one, for cycling through them at runtime
+    pub fn next(self) -> Self {
+        match self {
+            PresentMode::Fifo => PresentMode::Relaxed,
+            PresentMode::Relaxed => PresentMode::Mailbox,
+            PresentMode::Mailbox => PresentMode::Immediate,
+            PresentMode::Immediate => PresentMode::Fifo,
+        }
+    }
+
+    /// This mode then the ones to fall back to, in order, when the surface
+    /// doesn't offer it. Every surface offers FIFO, so it always ends the list
+    pub fn fallbacks(self) -> &'static [PresentMode] {
+        match self {
+            PresentMode::Fifo => &[PresentMode::Fifo],
+            PresentMode::Relaxed => &[PresentMode::Relaxed, PresentMode::Fifo],
+            PresentMode::Mailbox => &[PresentMode::Mailbox, PresentMode::Fifo],
+            PresentMode::Immediate => &[
+                PresentMode::Immediate,
+                PresentMode::Mailbox,
+                PresentMode::Fifo,
+            ],
+        }
+    }
+}
+
+/// What the toggle_fullscreen action switches windows to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FullscreenMode {
+    /// A borderless window covering the monitor, which keeps its video mode
+    #[default]
+    Borderless,
+    /// Takes the monitor over, switching it to `resolution` and `refresh_rate`
+    Exclusive,
+}
+
+/// Renderer options, read from a TOML file like
+///
+/// ```toml
+/// render_path = "deferred"
+/// dynamic_range = "hdr"
+/// present_mode = "fifo"
+/// image_count = 3
+/// fullscreen = "exclusive"
+/// resolution = [1920, 1080]
+/// ```
+///
+/// where any setting left out keeps its default. All but the present mode,
+/// clear colour and culling are fixed at startup.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub render_path: RenderPath,
+    pub dynamic_range: DynamicRange,
+    /// Brightness in nits of an HDR output value of 1, so of SDR white
+    pub paper_white: f32,
+    /// Brightness in nits the display reaches, which HDR tonemapping rolls off to
+    pub max_luminance: f32,
+    /// Brightness in nits the display is told a whole frame averages at most,
+    /// sent along with `max_luminance` as HDR metadata
+    pub max_frame_average_nits: f32,
+    pub present_mode: PresentMode,
+    /// Swapchain images to ask for, clamped to what the surface allows. One
+    /// more than the surface's minimum if left out
+    pub image_count: Option<u32>,
+    pub fullscreen: FullscreenMode,
+    /// Whether the main window opens fullscreen
+    pub start_fullscreen: bool,
+    /// Index of the monitor to go fullscreen on, the window's own if left out
+    pub monitor: Option<usize>,
+    /// Exclusive fullscreen resolution, the monitor's largest if left out
+    pub resolution: Option<[u32; 2]>,
+    /// Exclusive fullscreen refresh rate in Hz, the highest available if left out
+    pub refresh_rate: Option<u16>,
+    /// Linear colour behind the scene, before post-processing
+    pub clear_color: [f32; 3],
+    /// Whether draws with nothing in a window's view are skipped
+    pub cpu_culling: bool,
+    /// Whether a compute pass frustum culls the scene's instances and writes
+    /// the draws' indirect commands, on devices that allow it
+    pub gpu_culling: bool,
+    /// Whether materials index one array of every texture where the device
+    /// supports descriptor indexing, rather than binding a set each. Only
+    /// read at startup
+    pub bindless: bool,
+    /// Whether each queue's progress is tracked with a timeline semaphore
+    /// where the device has them, rather than with fences. Only read at startup
+    pub timeline_semaphores: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            render_path: RenderPath::Forward,
+            dynamic_range: DynamicRange::Sdr,
+            paper_white: 200.0,
+            max_luminance: 1000.0,
+            max_frame_average_nits: 400.0,
+            present_mode: PresentMode::Mailbox,
+            image_count: None,
+            fullscreen: FullscreenMode::Borderless,
+            start_fullscreen: false,
+            monitor: None,
+            resolution: None,
+            refresh_rate: None,
+            clear_color: [0.0, 0.0, 0.0],
+            cpu_culling: true,
+            gpu_culling: true,
+            bindless: true,
+            timeline_semaphores: true,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use ash::vk;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3, Vector4};
+use serde::Deserialize;
+
+use crate::camera::{orthographic, perspective, Projection};
+use crate::light::{Light, LightKind, MAX_LIGHTS};
+
+/// Cascades per directional light, must match `SHADOW_CASCADES` in shader.frag
+pub const SHADOW_CASCADES: usize = 4;
+
+/// Layers in the shadow map array, shared by all shadow casting lights.
+/// Must match `MAX_SHADOW_MAPS` in shader.frag.
+pub const MAX_SHADOW_MAPS: usize = 8;
+
+/// How far behind a cascade's bounds casters are still rendered, so that
+/// objects outside the view can shadow it
+const CASTER_DISTANCE: f32 = 50.0;
+
+/// Near plane of spot light shadow frustums
+const SPOT_SHADOW_NEAR: f32 = 0.05;
+
+/// Far plane of spot light shadow frustums for lights without a range
+const SPOT_SHADOW_FAR: f32 = 100.0;
+
+/// Shadow quality knobs, read from a TOML file like
+///
+/// ```toml
+/// resolution = 2048
+/// depth_bias_constant = 1.25
+/// depth_bias_slope = 1.75
+/// cascade_splits = [5.0, 15.0, 40.0, 100.0]
+/// ```
+///
+/// where any setting left out keeps its default.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+    /// Width and height of each shadow map
+    pub resolution: u32,
+    /// Constant depth bias of the shadow pass, in units of the smallest depth step
+    pub depth_bias_constant: f32,
+    /// Depth bias scaled by the polygon's depth slope
+    pub depth_bias_slope: f32,
+    /// View space distance at which each cascade of the sun ends
+    pub cascade_splits: [f32; SHADOW_CASCADES],
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            resolution: 2048,
+            depth_bias_constant: 1.25,
+            depth_bias_slope: 1.75,
+            cascade_splits: [5.0, 15.0, 40.0, 100.0],
+        }
+    }
+}
+
+/// Light space matrices for one frame, with the shadow map layers of each light
+#[derive(Default)]
+pub struct ShadowLayout {
+    /// One view projection matrix per used shadow map layer
+    pub matrices: Vec<Matrix4<f32>>,
+    /// First layer of each light in the light list, if it casts shadows
+    pub first_layers: Vec<Option<usize>>,
+}
+
+impl ShadowLayout {
+    /// Hands out shadow map layers in light order, `SHADOW_CASCADES` for each
+    /// directional light and one per spot light, until they run out.
+    pub fn new(
+        lights: &[(Matrix4<f32>, Light)],
+        view: Matrix4<f32>,
+        projection: &Projection,
+        settings: &ShadowSettings,
+    ) -> Self {
+        let mut layout = ShadowLayout::default();
+
+        for (world, light) in lights.iter().take(MAX_LIGHTS) {
+            let direction = light_direction(world);
+
+            let matrices = match light.kind {
+                _ if !light.casts_shadows => vec![],
+                LightKind::Directional => cascade_matrices(direction, view, projection, settings),
+                LightKind::Spot {
+                    outer_cone_angle, ..
+                } => vec![spot_matrix(world, direction, outer_cone_angle, light.range)],
+                LightKind::Point => vec![],
+            };
+
+            if matrices.is_empty() || layout.matrices.len() + matrices.len() > MAX_SHADOW_MAPS {
+                layout.first_layers.push(None);
+            } else {
+                layout.first_layers.push(Some(layout.matrices.len()));
+                layout.matrices.extend(matrices);
+            }
+        }
+
+        layout
+    }
+}
+
+fn light_direction(world: &Matrix4<f32>) -> Vector3<f32> {
+    let direction = -world.z.truncate();
+    if direction.magnitude2() > 0.0 {
+        direction.normalize()
+    } else {
+        Vector3::new(0.0, 0.0, -1.0)
+    }
+}
+
+/// An up vector that isn't parallel to `direction`
+fn up_for(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() < 0.99 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_z()
+    }
+}
+
+fn spot_matrix(
+    world: &Matrix4<f32>,
+    direction: Vector3<f32>,
+    outer_cone_angle: f32,
+    range: Option<f32>,
+) -> Matrix4<f32> {
+    let position = Point3::from_vec(world.w.truncate());
+    let view = Matrix4::look_to_rh(position, direction, up_for(direction));
+    let proj = perspective(
+        Rad(2.0 * outer_cone_angle),
+        1.0,
+        SPOT_SHADOW_NEAR,
+        range.unwrap_or(SPOT_SHADOW_FAR),
+    );
+    proj * view
+}
+
+/// Each cascade covers a slice of the camera frustum between two split
+/// distances, fitted with a bounding sphere so its size doesn't change as the
+/// camera turns.
+fn cascade_matrices(
+    direction: Vector3<f32>,
+    view: Matrix4<f32>,
+    projection: &Projection,
+    settings: &ShadowSettings,
+) -> Vec<Matrix4<f32>> {
+    let inverse_view = view.invert().unwrap_or_else(Matrix4::identity);
+    let tan_half_fovy = (projection.fovy.0 / 2.0).tan();
+
+    let mut near = projection.near;
+    let mut matrices = vec![];
+
+    for &far in &settings.cascade_splits {
+        let far = far.min(projection.far);
+
+        let corners: Vec<Vector3<f32>> = [near, far]
+            .iter()
+            .flat_map(|&distance| {
+                let half_height = distance * tan_half_fovy;
+                let half_width = half_height * projection.aspect;
+                [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+                    let corner = Vector4::new(x * half_width, y * half_height, -distance, 1.0);
+                    (inverse_view * corner).truncate()
+                })
+            })
+            .collect();
+
+        let center = corners.iter().sum::<Vector3<f32>>() / corners.len() as f32;
+        let radius = corners
+            .iter()
+            .map(|corner| (corner - center).magnitude())
+            .fold(0.0, f32::max)
+            // rounding keeps the texel size constant from frame to frame
+            .ceil();
+
+        // a light view through the world origin keeps the texel grid fixed in
+        // world space, so snapping the bounds to it stops shadow edges crawling
+        let light_view = Matrix4::look_to_rh(Point3::origin(), direction, up_for(direction));
+        let light_center = light_view * center.extend(1.0);
+        let texel_size = 2.0 * radius / settings.resolution as f32;
+        let snap = |v: f32| (v / texel_size).round() * texel_size;
+        let (x, y) = (snap(light_center.x), snap(light_center.y));
+
+        let light_proj = orthographic(
+            x - radius,
+            x + radius,
+            y - radius,
+            y + radius,
+            -light_center.z - radius - CASTER_DISTANCE,
+            -light_center.z + radius,
+        );
+        matrices.push(light_proj * light_view);
+
+        near = far;
+    }
+
+    matrices
+}
+
+/// Pushed for each shadow map layer, must match the `ShadowPass` push
+/// constant block in shadow.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowPassConstants {
+    pub light_view_proj: Matrix4<f32>,
+}
+
+/// A depth array image with a layer per shadow map, and what's needed to render into it
+pub struct ShadowMaps {
+    pub resolution: u32,
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    /// All layers, for sampling
+    pub array_view: vk::ImageView,
+    /// One view and framebuffer per layer, for rendering
+    pub layer_views: Vec<vk::ImageView>,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    /// Comparison sampler returning the lit fraction of a 2x2 texel footprint
+    pub sampler: vk::Sampler,
+    pub render_pass: vk::RenderPass,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+impl ShadowMaps {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        for &framebuffer in &self.framebuffers {
+            device.destroy_framebuffer(framebuffer, None);
+        }
+        device.destroy_render_pass(self.render_pass, None);
+        device.destroy_sampler(self.sampler, None);
+        for &view in &self.layer_views {
+            device.destroy_image_view(view, None);
+        }
+        device.destroy_image_view(self.array_view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
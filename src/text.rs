@@ -0,0 +1,482 @@
+use ab_glyph::{point, Font, FontVec, GlyphId, PxScale, PxScaleFont, ScaleFont};
+use anyhow::{Context, Result};
+use ash::vk;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use log::debug;
+
+use crate::texture::{SamplerDesc, Texture, TextureData};
+
+/// Most glyphs drawn into a window in a frame, sizing its vertex buffers
+pub const MAX_GLYPHS: usize = 4096;
+
+/// Width of the glyph atlas, its height grows to fit the glyphs
+const ATLAS_WIDTH: u32 = 512;
+
+/// Drawn in place of characters the atlas doesn't have
+const REPLACEMENT: char = '?';
+
+/// The text overlay, read from a TOML file like
+///
+/// ```toml
+/// font = "fonts/DejaVuSans.ttf"
+/// size = 20
+/// color = [1.0, 1.0, 0.0, 1.0]
+/// ```
+///
+/// where any setting left out keeps its default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TextSettings {
+    /// TrueType font rasterised into the atlas at startup
+    pub font: PathBuf,
+    /// Pixel size glyphs are rasterised at, the distance field scales from it
+    pub raster_size: f32,
+    /// How far in atlas pixels the distance field reaches out from an outline
+    pub spread: f32,
+    /// Pixel size text is drawn at
+    pub size: f32,
+    pub color: [f32; 4],
+    /// Whether the frame stats are shown, until toggled
+    pub show_stats: bool,
+}
+
+impl Default for TextSettings {
+    fn default() -> Self {
+        TextSettings {
+            font: PathBuf::from("fonts/DejaVuSans.ttf"),
+            raster_size: 48.0,
+            spread: 6.0,
+            size: 16.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            show_stats: true,
+        }
+    }
+}
+
+/// Vertex layout of the text pipeline, must match the inputs of text.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TextVertex {
+    /// Pixels from the top left of the screen until `to_clip_space`
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl TextVertex {
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 8,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 16,
+            },
+        ]
+    }
+
+    /// Converts pixel positions on a screen of `extent` to clip space
+    pub fn to_clip_space(vertices: &mut [TextVertex], extent: vk::Extent2D) {
+        for vertex in vertices {
+            vertex.position = [
+                vertex.position[0] / extent.width as f32 * 2.0 - 1.0,
+                vertex.position[1] / extent.height as f32 * 2.0 - 1.0,
+            ];
+        }
+    }
+}
+
+/// Where a glyph is in the atlas and how to place it, all in pixels at the
+/// raster size
+#[derive(Clone, Copy, Debug)]
+struct GlyphInfo {
+    id: GlyphId,
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    /// From the pen on the baseline to the quad's top left corner
+    offset: [f32; 2],
+    /// Zero for glyphs with nothing to draw, like space
+    size: [f32; 2],
+    advance: f32,
+}
+
+/// A font's printable ASCII glyphs as signed distance fields packed into one
+/// texture, which stay sharp scaled well past the size they were rasterised at
+pub struct FontAtlas {
+    font: FontVec,
+    glyphs: HashMap<char, GlyphInfo>,
+    raster_size: f32,
+}
+
+impl FontAtlas {
+    /// Rasterises the configured font, returning the atlas along with its
+    /// texture to upload. The distance is stored in alpha, 0.5 on the outline
+    pub fn load(settings: &TextSettings) -> Result<(Self, TextureData)> {
+        let path = &settings.font;
+        let data = std::fs::read(path)
+            .with_context(|| format!("could not read font {}", path.display()))?;
+        let font = FontVec::try_from_vec(data)
+            .with_context(|| format!("could not parse font {}", path.display()))?;
+        let scaled = font.as_scaled(PxScale::from(settings.raster_size));
+        // room for the field to fall off to nothing around each glyph
+        let padding = settings.spread.ceil() as u32 + 1;
+
+        let mut glyphs = HashMap::new();
+        let mut fields = vec![];
+        // glyphs are packed left to right in rows as tall as their tallest
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for c in ' '..='~' {
+            let id = font.glyph_id(c);
+            let mut glyph = GlyphInfo {
+                id,
+                uv_min: [0.0, 0.0],
+                uv_max: [0.0, 0.0],
+                offset: [0.0, 0.0],
+                size: [0.0, 0.0],
+                advance: scaled.h_advance(id),
+            };
+
+            let outline =
+                font.outline_glyph(id.with_scale_and_position(scaled.scale, point(0.0, 0.0)));
+            if let Some(outline) = outline {
+                let bounds = outline.px_bounds();
+                let width = bounds.width() as u32 + 2 * padding;
+                let height = bounds.height() as u32 + 2 * padding;
+
+                let mut coverage = vec![0.0; (width * height) as usize];
+                outline.draw(|gx, gy, c| {
+                    coverage[((gy + padding) * width + gx + padding) as usize] = c;
+                });
+
+                if width > ATLAS_WIDTH {
+                    anyhow::bail!(
+                        "glyph '{}' of {} is {} pixels wide at raster size {} and spread {}, \
+                         wider than the {} pixel atlas",
+                        c,
+                        path.display(),
+                        width,
+                        settings.raster_size,
+                        settings.spread,
+                        ATLAS_WIDTH
+                    );
+                }
+                if x + width > ATLAS_WIDTH {
+                    x = 0;
+                    y += row_height;
+                    row_height = 0;
+                }
+
+                // in pixels until the atlas height is known
+                glyph.uv_min = [x as f32, y as f32];
+                glyph.uv_max = [(x + width) as f32, (y + height) as f32];
+                glyph.offset = [bounds.min.x - padding as f32, bounds.min.y - padding as f32];
+                glyph.size = [width as f32, height as f32];
+
+                let field = Self::signed_distance_field(&coverage, width, height, settings.spread);
+                fields.push((x, y, width, field));
+                x += width;
+                row_height = row_height.max(height);
+            }
+
+            glyphs.insert(c, glyph);
+        }
+        let atlas_height = (y + row_height).max(1);
+
+        for glyph in glyphs.values_mut() {
+            for uv in [&mut glyph.uv_min, &mut glyph.uv_max] {
+                uv[0] /= ATLAS_WIDTH as f32;
+                uv[1] /= atlas_height as f32;
+            }
+        }
+
+        // white everywhere, so the colour comes from the vertices
+        let mut pixels = [255, 255, 255, 0].repeat((ATLAS_WIDTH * atlas_height) as usize);
+        for (x, y, width, field) in fields {
+            for (i, &distance) in field.iter().enumerate() {
+                let (fx, fy) = (i as u32 % width, i as u32 / width);
+                pixels[(((y + fy) * ATLAS_WIDTH + x + fx) * 4 + 3) as usize] = distance;
+            }
+        }
+
+        debug!(
+            "Rasterised {} glyphs of {} into a {}x{} atlas",
+            glyphs.len(),
+            path.display(),
+            ATLAS_WIDTH,
+            atlas_height
+        );
+
+        let texture = TextureData {
+            width: ATLAS_WIDTH,
+            height: atlas_height,
+            pixels,
+            srgb: false,
+            sampler: SamplerDesc {
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                ..Default::default()
+            },
+        };
+
+        Ok((
+            FontAtlas {
+                font,
+                glyphs,
+                raster_size: settings.raster_size,
+            },
+            texture,
+        ))
+    }
+
+    /// Lays `text` out with its first line's top left corner at `origin`,
+    /// appending six vertices for each glyph. Lines break at newlines and,
+    /// given a `max_width`, between words that would run past it
+    pub fn layout(
+        &self,
+        text: &str,
+        origin: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        max_width: Option<f32>,
+        vertices: &mut Vec<TextVertex>,
+    ) {
+        let scaled = self.scaled();
+        let scale = size / self.raster_size;
+        let line_height = (scaled.height() + scaled.line_gap()) * scale;
+        let space = self.advance(" ") * scale;
+
+        let mut baseline = origin[1] + scaled.ascent() * scale;
+        for line in text.lines() {
+            let mut pen = origin[0];
+            let mut previous = None;
+
+            for (i, word) in line.split(' ').enumerate() {
+                if i > 0 {
+                    let wraps = max_width.is_some_and(|max_width| {
+                        pen + space + self.advance(word) * scale > origin[0] + max_width
+                    });
+                    if wraps {
+                        pen = origin[0];
+                        baseline += line_height;
+                    } else {
+                        pen += space;
+                    }
+                    previous = None;
+                }
+
+                for glyph in word.chars().filter_map(|c| self.glyph(c)) {
+                    if let Some(previous) = previous {
+                        pen += scaled.kern(previous, glyph.id) * scale;
+                    }
+                    Self::push_quad(glyph, [pen, baseline], scale, color, vertices);
+                    pen += glyph.advance * scale;
+                    previous = Some(glyph.id);
+                }
+            }
+
+            baseline += line_height;
+        }
+    }
+
+    fn scaled(&self) -> PxScaleFont<&FontVec> {
+        self.font.as_scaled(PxScale::from(self.raster_size))
+    }
+
+    fn glyph(&self, c: char) -> Option<&GlyphInfo> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&REPLACEMENT))
+    }
+
+    /// Kerned width of `text` at the raster size
+    fn advance(&self, text: &str) -> f32 {
+        let scaled = self.scaled();
+        let mut width = 0.0;
+        let mut previous = None;
+        for glyph in text.chars().filter_map(|c| self.glyph(c)) {
+            if let Some(previous) = previous {
+                width += scaled.kern(previous, glyph.id);
+            }
+            width += glyph.advance;
+            previous = Some(glyph.id);
+        }
+        width
+    }
+
+    fn push_quad(
+        glyph: &GlyphInfo,
+        pen: [f32; 2],
+        scale: f32,
+        color: [f32; 4],
+        vertices: &mut Vec<TextVertex>,
+    ) {
+        if glyph.size == [0.0, 0.0] {
+            return;
+        }
+
+        let min = [
+            pen[0] + glyph.offset[0] * scale,
+            pen[1] + glyph.offset[1] * scale,
+        ];
+        let max = [
+            min[0] + glyph.size[0] * scale,
+            min[1] + glyph.size[1] * scale,
+        ];
+        let corner = |x: usize, y: usize| TextVertex {
+            position: [[min[0], max[0]][x], [min[1], max[1]][y]],
+            uv: [
+                [glyph.uv_min[0], glyph.uv_max[0]][x],
+                [glyph.uv_min[1], glyph.uv_max[1]][y],
+            ],
+            color,
+        };
+
+        // two triangles wound like the post passes' one
+        vertices.extend([
+            corner(0, 0),
+            corner(1, 0),
+            corner(0, 1),
+            corner(1, 0),
+            corner(1, 1),
+            corner(0, 1),
+        ]);
+    }
+
+    /// Maps each pixel's signed distance to the outline, outside positive, from
+    /// -spread..spread to 255..0
+    fn signed_distance_field(coverage: &[f32], width: u32, height: u32, spread: f32) -> Vec<u8> {
+        let (width, height) = (width as usize, height as usize);
+        let inside = |x: usize, y: usize| coverage[y * width + x] >= 0.5;
+        let to_inside = Self::distance_transform(width, height, inside);
+        let to_outside = Self::distance_transform(width, height, |x, y| !inside(x, y));
+
+        to_inside
+            .iter()
+            .zip(&to_outside)
+            .map(|(&to_inside, &to_outside)| {
+                // the outline runs half a pixel from the centres either side of it
+                let distance = if to_inside == 0.0 {
+                    0.5 - to_outside
+                } else {
+                    to_inside - 0.5
+                };
+                ((0.5 - 0.5 * distance / spread).clamp(0.0, 1.0) * 255.0).round() as u8
+            })
+            .collect()
+    }
+
+    /// Distance from each pixel to the nearest one where `seed` holds, by the
+    /// two sweeps of 8SSEDT
+    fn distance_transform(
+        width: usize,
+        height: usize,
+        seed: impl Fn(usize, usize) -> bool,
+    ) -> Vec<f32> {
+        // each pixel's offset to the nearest seed found so far
+        const FAR: (i32, i32) = (9999, 9999);
+        let mut grid: Vec<(i32, i32)> = (0..width * height)
+            .map(|i| {
+                if seed(i % width, i / width) {
+                    (0, 0)
+                } else {
+                    FAR
+                }
+            })
+            .collect();
+
+        let length_squared = |(x, y): (i32, i32)| x * x + y * y;
+        let mut compare = |x: usize, y: usize, dx: i32, dy: i32| {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                return;
+            }
+            let other = grid[ny as usize * width + nx as usize];
+            let candidate = (other.0 + dx, other.1 + dy);
+            if length_squared(candidate) < length_squared(grid[y * width + x]) {
+                grid[y * width + x] = candidate;
+            }
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                compare(x, y, -1, 0);
+                compare(x, y, 0, -1);
+                compare(x, y, -1, -1);
+                compare(x, y, 1, -1);
+            }
+            for x in (0..width).rev() {
+                compare(x, y, 1, 0);
+            }
+        }
+        for y in (0..height).rev() {
+            for x in (0..width).rev() {
+                compare(x, y, 1, 0);
+                compare(x, y, 0, 1);
+                compare(x, y, -1, 1);
+                compare(x, y, 1, 1);
+            }
+            for x in 0..width {
+                compare(x, y, -1, 0);
+            }
+        }
+
+        grid.into_iter()
+            .map(|offset| (length_squared(offset) as f32).sqrt())
+            .collect()
+    }
+}
+
+/// The font atlas on the GPU, bound like a post-processing source
+pub struct TextRenderer {
+    pub atlas: FontAtlas,
+    pub texture: Texture,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl TextRenderer {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.set_layout, None);
+        self.texture.destroy(device);
+    }
+}
+
+/// A host visible buffer a window's glyph quads are written into each frame
+pub struct TextVertexBuffer {
+    pub buffer: vk::Buffer,
+    pub memory: vk::DeviceMemory,
+    /// How many vertices were written this frame
+    pub vertex_count: u32,
+}
+
+impl TextVertexBuffer {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.memory, None);
+    }
+}
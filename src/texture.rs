@@ -0,0 +1,70 @@
+use ash::vk;
+
+/// How a texture is filtered and addressed
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+/// RGBA8 texture data on the CPU, ready to be uploaded
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// Colour data such as base colour is stored in sRGB, everything else
+    /// (normals, metallic/roughness, ...) is linear
+    pub srgb: bool,
+    pub sampler: SamplerDesc,
+}
+
+impl TextureData {
+    /// 1x1 texture of a single colour, bound where a material has no texture
+    pub fn solid(rgba: [u8; 4]) -> Self {
+        TextureData {
+            width: 1,
+            height: 1,
+            pixels: rgba.to_vec(),
+            srgb: false,
+            sampler: SamplerDesc::default(),
+        }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        if self.srgb {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        }
+    }
+}
+
+/// A sampled image in device local memory
+pub struct Texture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+impl Texture {
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use ash::extensions::khr::TimelineSemaphore;
+use ash::vk;
+
+/// A queue's progress as a timeline semaphore: each submission signals the
+/// next value, so waiting for a value waits for everything submitted up to
+/// and including it
+pub struct Timeline {
+    pub semaphore: vk::Semaphore,
+    /// `None` where timeline semaphores are core, in Vulkan 1.2
+    loader: Option<TimelineSemaphore>,
+    /// The value the latest submission signals
+    submitted: Cell<u64>,
+}
+
+impl Timeline {
+    pub fn new(device: &ash::Device, loader: Option<TimelineSemaphore>) -> Result<Self> {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut type_info);
+        let semaphore = unsafe { device.create_semaphore(&create_info, None)? };
+
+        Ok(Timeline {
+            semaphore,
+            loader,
+            submitted: Cell::new(0),
+        })
+    }
+
+    /// The value for a submission to signal, which must be submitted before
+    /// the next is taken
+    pub fn next_value(&self) -> u64 {
+        self.submitted.set(self.submitted.get() + 1);
+        self.submitted.get()
+    }
+
+    /// The value the latest submission signals, waiting for which waits for
+    /// the queue to finish everything submitted so far
+    pub fn submitted(&self) -> u64 {
+        self.submitted.get()
+    }
+
+    /// The value of the latest submission the queue has finished
+    pub fn completed(&self, device: &ash::Device) -> Result<u64> {
+        let value = unsafe {
+            match &self.loader {
+                Some(loader) => loader.get_semaphore_counter_value(device.handle(), self.semaphore),
+                None => device.get_semaphore_counter_value(self.semaphore),
+            }?
+        };
+        Ok(value)
+    }
+
+    /// Blocks until the queue has finished the submission that signals `value`
+    pub fn wait(&self, device: &ash::Device, value: u64) -> Result<()> {
+        let semaphores = [self.semaphore];
+        let values = [value];
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe {
+            match &self.loader {
+                Some(loader) => loader.wait_semaphores(device.handle(), &wait_info, u64::MAX),
+                None => device.wait_semaphores(&wait_info, u64::MAX),
+            }?
+        };
+        Ok(())
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_semaphore(self.semaphore, None);
+    }
+}
+
+/// Resources that submitted work may still use, each kept until its queue's
+/// timeline reaches the value it was pushed with
+pub struct DeletionQueue<T> {
+    pending: VecDeque<(u64, T)>,
+}
+
+impl<T> Default for DeletionQueue<T> {
+    fn default() -> Self {
+        DeletionQueue {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> DeletionQueue<T> {
+    /// Values must not decrease from one push to the next
+    pub fn push(&mut self, value: u64, resource: T) {
+        self.pending.push_back((value, resource));
+    }
+
+    /// Removes the resources no longer in use once the timeline has reached
+    /// `completed`, for the caller to destroy
+    pub fn retire(&mut self, completed: u64) -> Vec<T> {
+        let count = self
+            .pending
+            .iter()
+            .take_while(|(value, _)| *value <= completed)
+            .count();
+        self.pending
+            .drain(..count)
+            .map(|(_, resource)| resource)
+            .collect()
+    }
+
+    /// Everything still queued, for when the device is idle
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.pending.iter().map(|(_, resource)| resource)
+    }
+}